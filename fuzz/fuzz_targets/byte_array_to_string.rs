@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lavap_rs::utils::byte_array_to_string;
+
+fuzz_target!(|data: &[u8]| {
+    for replace_double_quotes in [false, true] {
+        let escaped = byte_array_to_string(data, replace_double_quotes);
+
+        // Every byte must end up as printable ASCII, nothing may leak into the signed text raw
+        assert!(escaped.bytes().all(|b| (0x20..=0x7e).contains(&b)));
+        assert!(escaped.len() >= data.len());
+    }
+});
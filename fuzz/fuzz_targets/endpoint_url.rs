@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lavap_rs::endpoint::endpoint_url;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(address) = std::str::from_utf8(data) {
+        // Normalizing an already normalized URL must not change it
+        if let Ok(url) = endpoint_url(address) {
+            assert!(url.starts_with("http://") || url.starts_with("https://"));
+            assert_eq!(endpoint_url(&url), Ok(url.clone()));
+        }
+    }
+});
@@ -0,0 +1,43 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use lavap_rs::proto::{Metadata, RelayPrivateData};
+use lavap_rs::relay_session::generate_content_hash;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    connection_type: String,
+    api_url: String,
+    data: Vec<u8>,
+    request_block: i64,
+    api_interface: String,
+    salt: Vec<u8>,
+    metadata: Vec<(String, String)>,
+    addon: String,
+    extensions: Vec<String>,
+    seen_block: i64,
+}
+
+fuzz_target!(|input: Input| {
+    let data = RelayPrivateData {
+        connection_type: input.connection_type,
+        api_url: input.api_url,
+        data: input.data,
+        request_block: input.request_block,
+        api_interface: input.api_interface,
+        salt: input.salt,
+        metadata: input
+            .metadata
+            .into_iter()
+            .map(|(name, value)| Metadata { name, value })
+            .collect(),
+        addon: input.addon,
+        extensions: input.extensions,
+        seen_block: input.seen_block,
+    };
+
+    let hash = generate_content_hash(&data);
+    assert_eq!(hash.len(), 32);
+    assert_eq!(hash, generate_content_hash(&data));
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lavap_rs::pairing::parse_pairing_response;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(json) = serde_json::from_slice::<serde_json::Value>(data) {
+        if let Some((_, providers)) = parse_pairing_response(&json) {
+            assert!(providers.windows(2).all(|w| w[0].stake >= w[1].stake));
+        }
+    }
+});
@@ -0,0 +1,75 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use lavap_rs::proto::{QualityOfServiceReport, RelaySession, ReportedProvider};
+use lavap_rs::relay_session::serialize_relay_session;
+
+#[derive(Debug, Arbitrary)]
+struct Report {
+    latency: String,
+    availability: String,
+    sync: String,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Reported {
+    address: String,
+    disconnections: u64,
+    errors: u64,
+    timestamp_s: i64,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    spec_id: String,
+    content_hash: Vec<u8>,
+    session_id: u64,
+    cu_sum: u64,
+    provider: String,
+    relay_num: u64,
+    qos_report: Option<Report>,
+    epoch: i64,
+    unresponsive_providers: Vec<Reported>,
+    lava_chain_id: String,
+    qos_excellence_report: Option<Report>,
+}
+
+fn to_qos(report: Report) -> QualityOfServiceReport {
+    QualityOfServiceReport {
+        latency: report.latency,
+        availability: report.availability,
+        sync: report.sync,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let session = RelaySession {
+        spec_id: input.spec_id,
+        content_hash: input.content_hash,
+        session_id: input.session_id,
+        cu_sum: input.cu_sum,
+        provider: input.provider,
+        relay_num: input.relay_num,
+        qos_report: input.qos_report.map(to_qos),
+        epoch: input.epoch,
+        unresponsive_providers: input
+            .unresponsive_providers
+            .into_iter()
+            .map(|p| ReportedProvider {
+                address: p.address,
+                disconnections: p.disconnections,
+                errors: p.errors,
+                timestamp_s: p.timestamp_s,
+            })
+            .collect(),
+        lava_chain_id: input.lava_chain_id,
+        sig: vec![],
+        badge: None,
+        qos_excellence_report: input.qos_excellence_report.map(to_qos),
+    };
+
+    // The signed bytes must be deterministic, otherwise providers reject the signature
+    let serialized = serialize_relay_session(&session);
+    assert_eq!(serialized, serialize_relay_session(&session));
+});
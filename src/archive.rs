@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::pairing::{ProviderEndpoint, RankedProvider};
+
+pub const ARCHIVE_EXTENSION: &str = "archive";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub retry: bool,
+    // Case-insensitive fragments of provider error messages that mean the state was pruned
+    pub error_patterns: Vec<String>,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            retry: true,
+            error_patterns: vec![
+                "missing trie node".to_string(),
+                "block not available".to_string(),
+                "state not available".to_string(),
+                "historical state".to_string(),
+                "pruned".to_string(),
+            ],
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn is_pruned_state_error(&self, response: &[u8]) -> bool {
+        let response: Value = match serde_json::from_slice(response) {
+            Ok(response) => response,
+            Err(_) => return false,
+        };
+        let message = match response["error"]["message"].as_str() {
+            Some(message) => message.to_lowercase(),
+            None => return false,
+        };
+        self.error_patterns
+            .iter()
+            .any(|pattern| message.contains(&pattern.to_lowercase()))
+    }
+}
+
+//
+// Only the best endpoint of each provider is ranked, but any of its endpoints may be the
+// archive one. Providers keep their rank, the ranked endpoint is preferred when it qualifies
+pub fn archive_providers(providers: &[RankedProvider]) -> Vec<RankedProvider> {
+    providers
+        .iter()
+        .filter_map(|ranked| {
+            if is_archive(&ranked.endpoint) {
+                return Some(ranked.clone());
+            }
+            let endpoint = ranked.provider.endpoints.iter().find(|e| is_archive(e))?;
+            Some(RankedProvider::new(
+                ranked.provider.clone(),
+                endpoint.clone(),
+                ranked.latency,
+                ranked.epoch,
+            ))
+        })
+        .collect()
+}
+
+fn is_archive(endpoint: &ProviderEndpoint) -> bool {
+    endpoint.extensions.iter().any(|e| e == ARCHIVE_EXTENSION)
+}
@@ -0,0 +1,203 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::storage::Storage;
+
+const CACHE: &str = "cache";
+
+// Methods whose first param is a block hash
+const BLOCK_HASH_METHODS: &[&str] = &[
+    "eth_getBlockByHash",
+    "eth_getBlockTransactionCountByHash",
+    "eth_getTransactionByBlockHashAndIndex",
+    "eth_getUncleByBlockHashAndIndex",
+    "eth_getUncleCountByBlockHash",
+    "eth_getBlockReceipts",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    // Serve queries pinned to a block hash from any provider's earlier answer, what a
+    // block hash refers to never changes. Opt-in
+    pub enabled: bool,
+    pub max_entries: usize,
+    // Share of cache hits fetched again anyway to check the cached answer still matches
+    pub validate_fraction: f64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: 10000,
+            validate_fraction: 0.01,
+        }
+    }
+}
+
+//
+// (spec, method, block hash, digest of all params), the hash alone doesn't say which
+// account or slot of the block was asked for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    spec_id: String,
+    method: String,
+    block_hash: String,
+    params: Vec<u8>,
+}
+
+impl CacheKey {
+    fn id(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.spec_id,
+            self.method,
+            self.block_hash,
+            hex::encode(&self.params)
+        )
+    }
+}
+
+pub enum Lookup {
+    Hit(Value),
+    // Cached, but picked for validation: relay anyway and compare
+    Validate(Value),
+    Miss,
+}
+
+#[derive(Default)]
+struct Entries {
+    ids: HashSet<String>,
+    // Insertion order, the oldest entry goes first when full. Entries found in storage at
+    // startup come first, in key order
+    order: VecDeque<String>,
+}
+
+//
+// Results live in storage, a shared backend lets consumers answer from each other's entries.
+// Shared by the request handlers, which use it without holding the context lock
+pub struct ResponseCache {
+    config: CacheConfig,
+    storage: Arc<dyn Storage>,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig, storage: Arc<dyn Storage>) -> Self {
+        let order = if config.enabled {
+            storage
+                .load_all::<Value>(CACHE)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<VecDeque<_>>()
+        } else {
+            VecDeque::new()
+        };
+        Self {
+            entries: Mutex::new(Entries {
+                ids: order.iter().cloned().collect(),
+                order,
+            }),
+            config,
+            storage,
+        }
+    }
+
+    pub fn key(&self, spec_id: &str, request: &Value) -> Option<CacheKey> {
+        if !self.config.enabled {
+            return None;
+        }
+        let method = request["method"].as_str()?;
+        let params = request["params"].as_array()?;
+        let block_hash = if BLOCK_HASH_METHODS.contains(&method) {
+            params.first().and_then(Value::as_str).filter(|h| is_block_hash(h))
+        } else {
+            // EIP-1898 block parameter, e.g. eth_call(tx, {"blockHash": "0x..."}). With
+            // requireCanonical the node has to fail once the block is reorged out, which
+            // only it can tell
+            params
+                .iter()
+                .find(|param| param.get("blockHash").is_some())
+                .filter(|param| param["requireCanonical"] != Value::Bool(true))
+                .and_then(|param| param["blockHash"].as_str())
+                .filter(|h| is_block_hash(h))
+        }?;
+        Some(CacheKey {
+            spec_id: spec_id.to_string(),
+            method: method.to_string(),
+            block_hash: block_hash.to_lowercase(),
+            params: Sha256::digest(Value::Array(params.clone()).to_string().as_bytes()).to_vec(),
+        })
+    }
+
+    //
+    // The read runs on the blocking pool, a remote backend can take a round trip
+    pub async fn lookup(&self, key: &CacheKey) -> Lookup {
+        let storage = Arc::clone(&self.storage);
+        let id = key.id();
+        let cached = tokio::task::spawn_blocking(move || storage.load::<Value>(CACHE, &id))
+            .await
+            .unwrap_or_default();
+        match cached {
+            Some(result)
+                if self.config.validate_fraction > 0.0
+                    && rand::thread_rng().gen_bool(self.config.validate_fraction.min(1.0)) =>
+            {
+                Lookup::Validate(result)
+            }
+            Some(result) => Lookup::Hit(result),
+            None => Lookup::Miss,
+        }
+    }
+
+    //
+    // Only successful, non-null results are kept, null means the node doesn't know the
+    // block yet rather than that there's nothing there
+    pub fn store(&self, key: CacheKey, reply: &[u8]) {
+        let Some(result) = cacheable_result(reply) else {
+            return;
+        };
+        let id = key.id();
+        self.storage.save(CACHE, &id, &result);
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if entries.ids.insert(id.clone()) {
+            entries.order.push_back(id);
+        }
+        while entries.ids.len() > self.config.max_entries {
+            match entries.order.pop_front() {
+                Some(oldest) => {
+                    entries.ids.remove(&oldest);
+                    self.storage.remove(CACHE, &oldest);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+pub fn cached_response(request: &Value, result: &Value) -> Vec<u8> {
+    json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+        .to_string()
+        .into_bytes()
+}
+
+pub fn cacheable_result(reply: &[u8]) -> Option<Value> {
+    let reply = serde_json::from_slice::<Value>(reply).ok()?;
+    if reply.get("error").is_some_and(|error| !error.is_null()) {
+        return None;
+    }
+    reply.get("result").filter(|result| !result.is_null()).cloned()
+}
+
+fn is_block_hash(value: &str) -> bool {
+    value.len() == 66
+        && value.starts_with("0x")
+        && value[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,132 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::proto::RelayReply;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    // Expose GET/POST /admin/chaos to local clients so rates can be changed while running
+    pub admin: bool,
+    pub timeout_rate: f64,
+    pub connection_reset_rate: f64,
+    pub wrong_epoch_rate: f64,
+    pub corrupt_reply_rate: f64,
+    pub timeout_ms: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            admin: false,
+            timeout_rate: 0.0,
+            connection_reset_rate: 0.0,
+            wrong_epoch_rate: 0.0,
+            corrupt_reply_rate: 0.0,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    Timeout,
+    ConnectionReset,
+    WrongEpoch,
+}
+
+pub struct FaultInjector {
+    config: RwLock<ChaosConfig>,
+}
+
+impl FaultInjector {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+        }
+    }
+
+    pub async fn config(&self) -> ChaosConfig {
+        self.config.read().await.clone()
+    }
+
+    pub async fn admin_enabled(&self) -> bool {
+        self.config.read().await.admin
+    }
+
+    pub async fn update(&self, mut config: ChaosConfig) {
+        let mut current = self.config.write().await;
+        // The admin switch itself is only settable from the config file
+        config.admin = current.admin;
+        println!("Chaos config updated: {:?}", config);
+        *current = config;
+    }
+
+    //
+    // Simulate the provider failing before it ever answers, the relay is not sent at all
+    pub async fn inject_failure(&self, epoch: i64) -> Option<tonic::Status> {
+        let config = self.config().await;
+        let fault = roll_fault(&config)?;
+        println!("Chaos: injecting {:?}", fault);
+        Some(match fault {
+            Fault::Timeout => {
+                tokio::time::sleep(Duration::from_millis(config.timeout_ms)).await;
+                tonic::Status::deadline_exceeded("chaos: provider timed out")
+            }
+            Fault::ConnectionReset => {
+                tonic::Status::unavailable("chaos: connection reset by peer")
+            }
+            Fault::WrongEpoch => tonic::Status::invalid_argument(format!(
+                "chaos: epoch mismatch, consumer epoch {} is not the provider's current epoch",
+                epoch
+            )),
+        })
+    }
+
+    pub async fn corrupt(
+        &self,
+        result: Result<RelayReply, tonic::Status>,
+    ) -> Result<RelayReply, tonic::Status> {
+        let rate = self.config.read().await.corrupt_reply_rate;
+        match result {
+            Ok(mut reply) if rate > 0.0 && rand::thread_rng().gen_bool(rate.min(1.0)) => {
+                println!("Chaos: corrupting reply of {} bytes", reply.data.len());
+                corrupt_bytes(&mut reply.data);
+                Ok(reply)
+            }
+            result => result,
+        }
+    }
+}
+
+fn roll_fault(config: &ChaosConfig) -> Option<Fault> {
+    let roll: f64 = rand::thread_rng().gen();
+    let mut threshold = 0.0;
+    for (rate, fault) in [
+        (config.timeout_rate, Fault::Timeout),
+        (config.connection_reset_rate, Fault::ConnectionReset),
+        (config.wrong_epoch_rate, Fault::WrongEpoch),
+    ] {
+        threshold += rate.max(0.0);
+        if roll < threshold {
+            return Some(fault);
+        }
+    }
+    None
+}
+
+fn corrupt_bytes(data: &mut Vec<u8>) {
+    let mut rng = rand::thread_rng();
+    if data.is_empty() || rng.gen_bool(0.5) {
+        // Truncated reply, usually breaks the JSON
+        let len = data.len() / 2;
+        data.truncate(len);
+    } else {
+        for _ in 0..data.len().div_ceil(16) {
+            let i = rng.gen_range(0..data.len());
+            data[i] = rng.gen();
+        }
+    }
+}
@@ -6,7 +6,92 @@ use std::error::Error;
 #[derive(Debug, StructOpt)]
 pub struct Cli {
     #[structopt(long = "creds")]
-    pub creds: String,
+    pub creds: Option<String>,
+
+    #[structopt(long = "config")]
+    pub config: Option<String>,
+
+    /// Record pairing responses, probe results and relays to this file
+    #[structopt(long = "record", conflicts_with = "replay")]
+    pub record: Option<String>,
+
+    /// Replay a file written with --record instead of talking to the network
+    #[structopt(long = "replay")]
+    pub replay: Option<String>,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Export the latest signed relay session per provider session, as claimed for payment
+    ExportProofs {
+        /// Proof log to read, defaults to proofs.path from the config, then to the storage backend
+        #[structopt(long = "input")]
+        input: Option<String>,
+        /// Write the export here instead of stdout
+        #[structopt(long = "output")]
+        output: Option<String>,
+    },
+    /// Estimate the CU cost of a request mix from the chain spec, without any creds
+    EstimateCu {
+        #[structopt(long = "chain", default_value = "ETH1")]
+        chain: String,
+        /// Comma separated methods sent once per request mix
+        #[structopt(long = "methods", use_delimiter = true)]
+        methods: Vec<String>,
+        /// File with a JSON-RPC request or batch, its methods are added to --methods
+        #[structopt(long = "payload")]
+        payload: Option<String>,
+        #[structopt(long = "rps", default_value = "1")]
+        requests_per_second: f64,
+        /// Epoch length to project per-epoch usage for, e.g. "1200s"
+        #[structopt(long = "epoch-duration")]
+        epoch_duration: Option<String>,
+        /// CU allowance per provider and epoch of the subscription
+        #[structopt(long = "max-cu")]
+        max_cu: Option<u64>,
+    },
+    /// Fire a mix of JSON-RPC requests at a fixed rate and report latency, throughput and CU
+    Loadtest {
+        /// Consumer to load, without it one is started in-process from --creds
+        #[structopt(long = "url")]
+        url: Option<String>,
+        /// Comma separated methods, sent without params in turn
+        #[structopt(long = "methods", use_delimiter = true)]
+        methods: Vec<String>,
+        /// File with a JSON-RPC request or a list of them sent in turn, a nested list is a batch
+        #[structopt(long = "payload")]
+        payload: Option<String>,
+        #[structopt(long = "rps", default_value = "10")]
+        requests_per_second: f64,
+        #[structopt(long = "duration", default_value = "30s")]
+        duration: String,
+        /// Spec to price requests with when loading a remote consumer
+        #[structopt(long = "chain", default_value = "ETH1")]
+        chain: String,
+    },
+    /// Save or load a support bundle with config, pairing, provider stats and recent errors
+    Diagnostics(DiagnosticsCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DiagnosticsCommand {
+    /// Fetch the snapshot of a running consumer into a single JSON bundle
+    Dump {
+        #[structopt(long = "url", default_value = "http://127.0.0.1:3000")]
+        url: String,
+        /// Write the bundle here instead of stdout
+        #[structopt(long = "output")]
+        output: Option<String>,
+    },
+    /// Serve a bundle from a local, offline instance: its config and pairing are loaded,
+    /// no chain queries are made and relays fail unless --replay has them
+    Import {
+        #[structopt(long = "input")]
+        input: String,
+    },
 }
 
 #[derive(Debug, Deserialize)]
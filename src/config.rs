@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+use crate::archive::ArchiveConfig;
+use crate::cache::CacheConfig;
+use crate::chaos::ChaosConfig;
+use crate::dns::DnsConfig;
+use crate::dogfood::DogfoodConfig;
+use crate::error_policy::ErrorPolicyConfig;
+use crate::fallback::FallbackConfig;
+use crate::fee_oracle::FeeOracleConfig;
+use crate::geolocation::GeolocationConfig;
+use crate::get_logs::GetLogsConfig;
+use crate::handover::HandoverConfig;
+use crate::health_report::HealthReportConfig;
+use crate::listeners::ListenConfig;
+use crate::load_shedding::LoadSheddingConfig;
+use crate::middleware::MiddlewareConfig;
+use crate::pacing::PacingConfig;
+use crate::pinning::PinningConfig;
+use crate::priority::PriorityConfig;
+use crate::provider_errors::BackoffConfig;
+use crate::proofs::ProofsConfig;
+use crate::qos::QosConfig;
+use crate::quorum::QuorumConfig;
+use crate::rest::RestConfig;
+use crate::session_context::SessionConfig;
+use crate::shadow::ShadowConfig;
+use crate::storage::StorageConfig;
+use crate::tls::TlsConfig;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub chaos: ChaosConfig,
+    pub proofs: ProofsConfig,
+    pub geolocation: GeolocationConfig,
+    pub get_logs: GetLogsConfig,
+    pub pinning: PinningConfig,
+    pub archive: ArchiveConfig,
+    pub priority: PriorityConfig,
+    pub sessions: SessionConfig,
+    pub load_shedding: LoadSheddingConfig,
+    pub quorum: QuorumConfig,
+    pub fee_oracle: FeeOracleConfig,
+    pub rest: RestConfig,
+    pub dogfood: DogfoodConfig,
+    pub middleware: MiddlewareConfig,
+    pub dns: DnsConfig,
+    pub tls: TlsConfig,
+    pub backoff: BackoffConfig,
+    pub error_policy: ErrorPolicyConfig,
+    pub handover: HandoverConfig,
+    pub listen: ListenConfig,
+    pub health_report: HealthReportConfig,
+    pub shadow: ShadowConfig,
+    pub pacing: PacingConfig,
+    pub qos: QosConfig,
+    pub cache: CacheConfig,
+    pub fallback: FallbackConfig,
+    pub storage: StorageConfig,
+}
+
+impl Config {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read_to_string(path)?;
+        let config: Self = serde_json::from_str(&data)?;
+        config.listen.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None => Ok(Self::default()),
+        }
+    }
+}
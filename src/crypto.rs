@@ -21,11 +21,11 @@ pub fn sign_data(
     Ok(sig)
 }
 
-pub fn public_key_to_address(public_key: &Box<[u8]>, chain: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let sha256_hash = Sha256::digest(&public_key);
+pub fn public_key_to_address(public_key: &[u8], chain: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let sha256_hash = Sha256::digest(public_key);
     let mut hasher = Ripemd160::new();
     hasher.update(sha256_hash);
     let ripemd160_hash = hasher.finalize();
-    let address = bech32::encode(chain, &ripemd160_hash);
+    let address = bech32::encode(chain, ripemd160_hash);
     Ok(address)
 }
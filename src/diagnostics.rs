@@ -0,0 +1,330 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+use crate::chaos::ChaosConfig;
+use crate::config::Config;
+use crate::endpoint::Connector;
+use crate::fallback::FallbackConfig;
+use crate::handover::HandoverConfig;
+use crate::health_report::HealthReportConfig;
+use crate::listeners::ListenConfig;
+use crate::pairing::{
+    build_geo_pools, Provider, ProviderEndpoint, RankedProvider, SDKPairingParams,
+    SDKPairingState,
+};
+use crate::proofs::ProofsConfig;
+use crate::provider_stats::ProviderSummary;
+use crate::session_context::ConsumerSessionContext;
+use crate::shadow::ShadowConfig;
+use crate::storage::{consumer_namespace, Storage};
+use crate::utils::SPEC_ID;
+
+const MAX_ERRORS: usize = 200;
+// Relay errors, keyed by a zero-padded sequence number so a scan returns them oldest first
+const ERRORS: &str = "errors";
+// Config keys whose string values never leave the machine
+const SECRET_KEYS: &[&str] = &["secret", "password", "token", "key", "auth"];
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorEntry {
+    pub timestamp_s: i64,
+    pub spec_id: String,
+    pub provider: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointSnapshot {
+    pub address: String,
+    pub geolocation: u64,
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSnapshot {
+    pub address: String,
+    pub stake: u64,
+    pub latest_block: u64,
+    pub endpoints: Vec<EndpointSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedSnapshot {
+    pub address: String,
+    pub endpoint: String,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingSnapshot {
+    pub spec_id: String,
+    pub params: SDKPairingParams,
+    pub providers: Vec<ProviderSnapshot>,
+    // Best endpoint per provider, in rank order
+    pub ranked: Vec<RankedSnapshot>,
+}
+
+//
+// Everything support needs to look at a consumer after the fact, in one JSON document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub version: String,
+    pub created_at_s: i64,
+    pub config: Value,
+    pub pairings: Vec<PairingSnapshot>,
+    pub stats: Vec<ProviderSummary>,
+    pub errors: Vec<ErrorEntry>,
+}
+
+//
+// Recent relay errors, kept in storage so they outlive a restart, plus the snapshot this
+// instance was started from with `diagnostics import`, which is then served in place of
+// the live state
+pub struct Diagnostics {
+    errors: VecDeque<(u64, ErrorEntry)>,
+    storage: Arc<dyn Storage>,
+    namespace: String,
+    pub imported: Option<Snapshot>,
+}
+
+impl Diagnostics {
+    pub fn new(storage: Arc<dyn Storage>, consumer: &str) -> Self {
+        let namespace = consumer_namespace(ERRORS, consumer);
+        let mut errors = storage
+            .load_all::<ErrorEntry>(&namespace)
+            .into_iter()
+            .filter_map(|(key, entry)| Some((key.parse().ok()?, entry)))
+            .collect::<VecDeque<_>>();
+        while errors.len() > MAX_ERRORS {
+            if let Some((seq, _)) = errors.pop_front() {
+                storage.remove(&namespace, &error_key(seq));
+            }
+        }
+        Self {
+            errors,
+            storage,
+            namespace,
+            imported: None,
+        }
+    }
+
+    pub fn record_error(&mut self, spec_id: &str, provider: &str, message: &str) {
+        let seq = self.errors.back().map_or(0, |(seq, _)| seq + 1);
+        let entry = ErrorEntry {
+            timestamp_s: now_s(),
+            spec_id: spec_id.to_string(),
+            provider: provider.to_string(),
+            message: message.to_string(),
+        };
+        self.storage.save(&self.namespace, &error_key(seq), &entry);
+        self.errors.push_back((seq, entry));
+        if self.errors.len() > MAX_ERRORS {
+            if let Some((seq, _)) = self.errors.pop_front() {
+                self.storage.remove(&self.namespace, &error_key(seq));
+            }
+        }
+    }
+
+    pub fn errors(&self) -> Vec<ErrorEntry> {
+        self.errors.iter().map(|(_, entry)| entry.clone()).collect()
+    }
+}
+
+fn error_key(seq: u64) -> String {
+    format!("{:020}", seq)
+}
+
+pub async fn snapshot(context: &Arc<Mutex<ConsumerSessionContext>>) -> Snapshot {
+    let context = context.lock().await;
+    if let Some(imported) = &context.diagnostics.imported {
+        return imported.clone();
+    }
+
+    let mut chains = vec![(SPEC_ID.to_string(), context.pairing_state.clone())];
+    let mut extra = context.chains.iter().collect::<Vec<_>>();
+    extra.sort_by(|a, b| a.0.cmp(b.0));
+    chains.extend(extra.into_iter().map(|(spec_id, state)| (spec_id.clone(), state.clone())));
+    let mut pairings = Vec::new();
+    for (spec_id, state) in chains {
+        pairings.push(pairing_snapshot(&spec_id, &*state.lock().await));
+    }
+
+    let stats = context.stats.lock().await.summaries();
+    Snapshot {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at_s: now_s(),
+        config: sanitize_config(&context.config),
+        pairings,
+        stats,
+        errors: context.diagnostics.errors(),
+    }
+}
+
+fn pairing_snapshot(spec_id: &str, state: &SDKPairingState) -> PairingSnapshot {
+    PairingSnapshot {
+        spec_id: spec_id.to_string(),
+        params: state.params.clone(),
+        providers: state
+            .providers
+            .iter()
+            .map(|provider| ProviderSnapshot {
+                address: provider.address.clone(),
+                stake: provider.stake,
+                latest_block: provider.latest_block,
+                endpoints: provider
+                    .endpoints
+                    .iter()
+                    .map(|endpoint| EndpointSnapshot {
+                        address: endpoint.address.clone(),
+                        geolocation: endpoint.geolocation,
+                        extensions: endpoint.extensions.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        ranked: state
+            .ranked_providers
+            .iter()
+            .map(|ranked| RankedSnapshot {
+                address: ranked.provider.address.clone(),
+                endpoint: ranked.endpoint.address.clone(),
+                latency_ms: ranked.latency.as_millis() as u64,
+            })
+            .collect(),
+    }
+}
+
+//
+// The config of a bundle, minus everything that would reach beyond this machine or leave a
+// trace: proofs, the direct-node fallback, health reports, shadow traffic and injected
+// faults are off, and only the main router listens, on loopback, without SO_REUSEPORT
+pub fn imported_config(config: &Value) -> Result<Config, serde_json::Error> {
+    let mut config: Config = serde_json::from_value(config.clone())?;
+    config.proofs = ProofsConfig::default();
+    config.fallback = FallbackConfig::default();
+    config.health_report = HealthReportConfig::default();
+    config.shadow = ShadowConfig::default();
+    config.chaos = ChaosConfig::default();
+    config.handover = HandoverConfig::default();
+    config.listen = ListenConfig {
+        host: "127.0.0.1".to_string(),
+        ..ListenConfig::default()
+    };
+    Ok(config)
+}
+
+//
+// Rebuild a pairing from a snapshot, ranked the way it was when the snapshot was taken
+pub fn restore_pairing(snapshot: &PairingSnapshot, connector: Arc<Connector>) -> SDKPairingState {
+    let providers = snapshot
+        .providers
+        .iter()
+        .map(|provider| Provider {
+            address: provider.address.clone(),
+            stake: provider.stake,
+            latest_block: provider.latest_block,
+            endpoints: provider
+                .endpoints
+                .iter()
+                .map(|endpoint| ProviderEndpoint {
+                    address: endpoint.address.clone(),
+                    geolocation: endpoint.geolocation,
+                    extensions: endpoint.extensions.clone(),
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let by_address = providers
+        .iter()
+        .map(|provider| (provider.address.as_str(), provider))
+        .collect::<HashMap<_, _>>();
+    let ranked_providers = snapshot
+        .ranked
+        .iter()
+        .filter_map(|ranked| {
+            let provider = by_address.get(ranked.address.as_str())?;
+            let endpoint = provider
+                .endpoints
+                .iter()
+                .find(|endpoint| endpoint.address == ranked.endpoint)?;
+            Some(RankedProvider::new(
+                (*provider).clone(),
+                endpoint.clone(),
+                Duration::from_millis(ranked.latency_ms),
+                snapshot.params.current_epoch,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let mut state = SDKPairingState::with_connector(connector);
+    state.params = snapshot.params.clone();
+    state.geo_pools = build_geo_pools(&ranked_providers);
+    state.ranked_providers = ranked_providers;
+    state.providers = providers;
+    state
+}
+
+//
+// Config as JSON with secrets blanked out. URLs keep only scheme and host, node providers
+// put API keys in the userinfo, the path and the query alike
+pub fn sanitize_config(config: &Config) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+    sanitize(&mut value);
+    value
+}
+
+fn sanitize(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                let secret = SECRET_KEYS.iter().any(|secret| key.contains(secret));
+                match value {
+                    Value::String(_) if secret => *value = Value::String(REDACTED.to_string()),
+                    // Maps keyed by secrets, like priority.keys, keep their values only
+                    Value::Object(entries) if secret => {
+                        *entries = std::mem::take(entries)
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, (_, entry))| (format!("{}-{}", REDACTED, i), entry))
+                            .collect();
+                    }
+                    _ => sanitize(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(sanitize),
+        Value::String(s) => {
+            if let Some(redacted) = redact_url(s) {
+                *s = redacted;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_url(s: &str) -> Option<String> {
+    let (scheme, rest) = s.split_once("://")?;
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(authority_end);
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let tail = if tail.is_empty() || tail == "/" {
+        tail
+    } else {
+        "/[redacted]"
+    };
+    Some(format!("{}://{}{}", scheme, host, tail))
+}
+
+fn now_s() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Hosts nobody connected to for this many TTLs are dropped from the cache
+const IDLE_TTLS: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    pub enabled: bool,
+    // How long a resolution is trusted before it's looked up again
+    pub ttl_secs: u64,
+    // Period of the background task re-resolving hosts before their entry expires
+    pub refresh_interval_secs: u64,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 300,
+            refresh_interval_secs: 60,
+        }
+    }
+}
+
+struct CachedHost {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+    // Last address a connection succeeded on, tried first on reconnect
+    preferred: Option<SocketAddr>,
+    // Last time a connect asked for the host, background refreshes don't count
+    last_used: Instant,
+}
+
+impl CachedHost {
+    fn ordered(&self) -> Vec<SocketAddr> {
+        let mut addrs = self.addrs.clone();
+        if let Some(preferred) = self.preferred {
+            if let Some(index) = addrs.iter().position(|a| *a == preferred) {
+                addrs.swap(0, index);
+            }
+        }
+        addrs
+    }
+}
+
+//
+// Provider hostnames resolved once per TTL instead of on every connect. A failing lookup
+// keeps serving the stale addresses, a resolver hiccup shouldn't break relays mid-epoch
+#[derive(Default)]
+pub struct DnsCache {
+    config: DnsConfig,
+    hosts: Mutex<HashMap<(String, u16), CachedHost>>,
+}
+
+impl DnsCache {
+    pub fn new(config: DnsConfig) -> Self {
+        Self {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub async fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        if let Some(cached) = self.hosts.lock().await.get_mut(&key) {
+            cached.last_used = Instant::now();
+            if cached.resolved_at.elapsed() < ttl {
+                return Ok(cached.ordered());
+            }
+        }
+        self.lookup(host, port).await
+    }
+
+    async fn lookup(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+        match tokio::net::lookup_host((host, port)).await {
+            Ok(addrs) => {
+                let addrs = addrs.collect::<Vec<_>>();
+                let mut hosts = self.hosts.lock().await;
+                let preferred = hosts
+                    .get(&key)
+                    .and_then(|cached| cached.preferred)
+                    .filter(|preferred| addrs.contains(preferred));
+                let cached = hosts.entry(key).or_insert(CachedHost {
+                    addrs: vec![],
+                    resolved_at: Instant::now(),
+                    preferred: None,
+                    last_used: Instant::now(),
+                });
+                cached.addrs = addrs;
+                cached.resolved_at = Instant::now();
+                cached.preferred = preferred;
+                Ok(cached.ordered())
+            }
+            Err(e) => match self.hosts.lock().await.get(&key) {
+                Some(cached) if !cached.addrs.is_empty() => {
+                    println!("Resolving {} failed ({}), using cached addresses", host, e);
+                    Ok(cached.ordered())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    pub async fn mark_working(&self, host: &str, port: u16, addr: SocketAddr) {
+        if let Some(cached) = self.hosts.lock().await.get_mut(&(host.to_string(), port)) {
+            cached.preferred = Some(addr);
+        }
+    }
+
+    pub async fn mark_failed(&self, host: &str, port: u16, addr: SocketAddr) {
+        if let Some(cached) = self.hosts.lock().await.get_mut(&(host.to_string(), port)) {
+            if cached.preferred == Some(addr) {
+                cached.preferred = None;
+            }
+        }
+    }
+
+    //
+    // Re-resolve hosts used within the last TTL that expire before the next round, so
+    // connects hit the cache. Hosts idle for a few TTLs, e.g. of providers that left the
+    // pairing, are dropped instead of being resolved forever
+    pub async fn refresh_task(self: Arc<Self>) {
+        if !self.config.enabled {
+            return;
+        }
+        let interval = Duration::from_secs(self.config.refresh_interval_secs.max(1));
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        loop {
+            tokio::time::sleep(interval).await;
+            let expiring = {
+                let mut hosts = self.hosts.lock().await;
+                hosts.retain(|_, cached| cached.last_used.elapsed() < ttl * IDLE_TTLS);
+                hosts
+                    .iter()
+                    .filter(|(_, cached)| {
+                        cached.last_used.elapsed() < ttl
+                            && cached.resolved_at.elapsed() + interval >= ttl
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>()
+            };
+            for (host, port) in expiring {
+                if let Err(e) = self.lookup(&host, port).await {
+                    println!("Refreshing {} failed: {}", host, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Mutex;
+
+use crate::pairing::get_ranked_providers;
+use crate::relay::relay_rest;
+use crate::session_context::ConsumerSessionContext;
+
+pub const GATEWAY_URL: &str = "https://rest-public-rpc.lavanet.xyz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DogfoodConfig {
+    // Send our own Lava chain queries through paired providers of `spec_id`
+    pub enabled: bool,
+    pub spec_id: String,
+}
+
+impl Default for DogfoodConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spec_id: "LAV1".to_string(),
+        }
+    }
+}
+
+//
+// Lava chain REST queries (pairing, params, subscription). With dogfooding on they are
+// relayed to Lava providers once the context is up and the LAV1 pairing is in, the public
+// gateway is only used to bootstrap and whenever the relay fails
+pub struct LavaQuerier {
+    config: DogfoodConfig,
+    client: reqwest::Client,
+    context: OnceLock<Arc<Mutex<ConsumerSessionContext>>>,
+}
+
+impl LavaQuerier {
+    pub fn new(config: DogfoodConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            context: OnceLock::new(),
+        }
+    }
+
+    pub fn attach(&self, context: Arc<Mutex<ConsumerSessionContext>>) {
+        if self.config.enabled {
+            let _ = self.context.set(context);
+        }
+    }
+
+    pub async fn get(&self, api_url: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        if let Some(context) = self.context.get() {
+            match self.relay_query(context, api_url).await {
+                Some(json) => return Ok(json),
+                None => println!("Dogfood query {} failed, falling back to gateway", api_url),
+            }
+        }
+
+        let response = self
+            .client
+            .get(format!("{}{}", GATEWAY_URL, api_url))
+            .send()
+            .await?;
+        if response.status() != 200 {
+            return Err(format!("Failed to query {}: {}", api_url, response.status()).into());
+        }
+        Ok(response.json::<Value>().await?)
+    }
+
+    async fn relay_query(
+        &self,
+        context: &Arc<Mutex<ConsumerSessionContext>>,
+        api_url: &str,
+    ) -> Option<Value> {
+        let pairing_state = context.lock().await.chains.get(&self.config.spec_id).cloned()?;
+        let providers = get_ranked_providers(pairing_state.clone()).await;
+        let providers = context.lock().await.without_backed_off(providers);
+        for provider in providers.iter().take(2) {
+            let reply = relay_rest(
+                context,
+                &pairing_state,
+                &self.config.spec_id,
+                provider,
+                "GET",
+                api_url.to_string(),
+                vec![],
+            )
+            .await;
+            let json = reply
+                .ok()
+                .and_then(|data| serde_json::from_slice::<Value>(&data).ok());
+            match json {
+                // Error bodies carry a non-zero code, those go to the next provider
+                Some(json) if json["code"].as_u64().unwrap_or(0) == 0 => return Some(json),
+                _ => println!(
+                    "Dogfood query {} to {} failed",
+                    api_url, provider.provider.address
+                ),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use crate::pairing::SDKPairingParams;
+use crate::utils::parse_duration;
+
+//
+// Lava keeps serving relays while the chain is halted: once blocks stop for longer than
+// downtime_duration every further epoch_duration counts as a virtual epoch, sessions stay on
+// the last real epoch and each virtual epoch grants the consumer another max_cu per provider.
+// We don't see blocks directly, so downtime is measured from when the next epoch was due.
+#[derive(Debug, Clone, Default)]
+pub struct EmergencyTracker {
+    epoch: i64,
+    next_epoch_at: Option<Instant>,
+    downtime_duration: Duration,
+    epoch_duration: Duration,
+}
+
+impl EmergencyTracker {
+    pub fn update(&mut self, params: &SDKPairingParams, now: Instant) {
+        if let Some(duration) = parse_duration(&params.downtime_duration) {
+            self.downtime_duration = duration;
+        }
+        if let Some(duration) = parse_duration(&params.epoch_duration) {
+            self.epoch_duration = duration;
+        }
+        if self.next_epoch_at.is_none() || params.current_epoch != self.epoch {
+            if self.in_emergency(now) {
+                println!("Epoch advanced to {}, leaving emergency mode", params.current_epoch);
+            }
+            self.epoch = params.current_epoch;
+            self.next_epoch_at =
+                Some(now + Duration::from_secs(params.time_left_to_next_pairing));
+        }
+    }
+
+    // How long the next epoch is overdue
+    pub fn downtime(&self, now: Instant) -> Duration {
+        self.next_epoch_at
+            .map(|at| now.saturating_duration_since(at))
+            .unwrap_or_default()
+    }
+
+    pub fn in_emergency(&self, now: Instant) -> bool {
+        !self.downtime_duration.is_zero() && self.downtime(now) > self.downtime_duration
+    }
+
+    pub fn virtual_epoch(&self, now: Instant) -> u64 {
+        if !self.in_emergency(now) || self.epoch_duration.is_zero() {
+            return 0;
+        }
+        let excess = self.downtime(now) - self.downtime_duration;
+        1 + excess.as_secs() / self.epoch_duration.as_secs().max(1)
+    }
+
+    // None when the pairing didn't report a CU allowance
+    pub fn cu_limit(&self, max_cu: u64, now: Instant) -> Option<u64> {
+        if max_cu == 0 {
+            return None;
+        }
+        Some(max_cu.saturating_mul(self.virtual_epoch(now) + 1))
+    }
+}
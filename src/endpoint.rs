@@ -0,0 +1,154 @@
+use axum::http::Uri;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+use crate::dns::{DnsCache, DnsConfig};
+use crate::tls::{connect_pinned, PinnedTls, TlsConfig};
+
+const DEFAULT_PORT: u16 = 443;
+
+//
+// Turn a provider iPPORT into a URL tonic can dial. Providers publish plain host:port,
+// bracketed or bare IPv6 literals and sometimes a full URL with scheme
+pub fn endpoint_url(address: &str) -> Result<String, String> {
+    let address = address.trim().trim_end_matches('/');
+    if address.is_empty() {
+        return Err("empty endpoint".to_string());
+    }
+
+    let url = match address.split_once("://") {
+        Some(("http" | "https", _)) => address.to_string(),
+        Some((scheme, _)) => return Err(format!("unsupported scheme {}", scheme)),
+        None => format!("https://{}", authority(address)?),
+    };
+
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|e| format!("invalid endpoint {}: {}", address, e))?;
+    if uri.host().is_none() {
+        return Err(format!("invalid endpoint {}: no host", address));
+    }
+    Ok(url)
+}
+
+fn authority(address: &str) -> Result<String, String> {
+    if address.contains(|c: char| "/?#@".contains(c) || c.is_whitespace()) {
+        return Err(format!("invalid endpoint {}", address));
+    }
+
+    // [::1]:443 or [::1]
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("unclosed bracket in {}", address))?;
+        host.parse::<Ipv6Addr>()
+            .map_err(|_| format!("invalid IPv6 literal {}", host))?;
+        return match port.strip_prefix(':') {
+            Some(port) => Ok(format!("[{}]:{}", host, parse_port(port)?)),
+            None if port.is_empty() => Ok(format!("[{}]:{}", host, DEFAULT_PORT)),
+            None => Err(format!("unexpected {} after IPv6 literal", port)),
+        };
+    }
+
+    // A bare IPv6 literal. iPPORT always carries a port, so the last group is read as the
+    // port whenever what's left is still a valid address
+    if address.matches(':').count() > 1 {
+        if let Some((host, port)) = address.rsplit_once(':') {
+            if let (Ok(ip), Ok(port)) = (host.parse::<Ipv6Addr>(), port.parse::<u16>()) {
+                return Ok(format!("[{}]:{}", ip, port));
+            }
+        }
+        let ip = address
+            .parse::<Ipv6Addr>()
+            .map_err(|_| format!("invalid IPv6 endpoint {}", address))?;
+        return Ok(format!("[{}]:{}", ip, DEFAULT_PORT));
+    }
+
+    // hostname or IPv4, with or without port
+    match address.split_once(':') {
+        Some((host, port)) => Ok(format!("{}:{}", host, parse_port(port)?)),
+        None => Ok(format!("{}:{}", address, DEFAULT_PORT)),
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse().map_err(|_| format!("invalid port {}", port))
+}
+
+//
+// Dials provider endpoints. Hostnames go through the DNS cache and the address that
+// worked last time is tried first, TLS still verifies against the hostname unless the
+// provider has a pin configured
+#[derive(Default)]
+pub struct Connector {
+    pub dns: Arc<DnsCache>,
+    tls: PinnedTls,
+}
+
+impl Connector {
+    pub fn new(dns: DnsConfig, tls: &TlsConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            dns: Arc::new(DnsCache::new(dns)),
+            tls: PinnedTls::new(tls)?,
+        })
+    }
+
+    pub async fn connect(&self, provider: &str, address: &str) -> Result<Channel, BoxError> {
+        let url = endpoint_url(address)?;
+        let uri = url.parse::<tonic::transport::Uri>()?;
+        let host = uri.host().unwrap_or_default().trim_matches(|c| c == '[' || c == ']');
+        let https = uri.scheme_str() == Some("https");
+        let pinned = self.tls.connector_for(provider, address);
+        if pinned.is_some() && !https {
+            return Err(format!("{} has a TLS pin, refusing plaintext {}", provider, url).into());
+        }
+        let ip = host.parse::<IpAddr>().ok();
+        if pinned.is_none() && (!self.dns.enabled() || ip.is_some()) {
+            return Ok(Endpoint::from_shared(url)?.connect().await?);
+        }
+
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let addrs = match ip {
+            Some(ip) => vec![SocketAddr::new(ip, port)],
+            None if self.dns.enabled() => self.dns.resolve(host, port).await?,
+            None => tokio::net::lookup_host((host, port)).await?.collect(),
+        };
+        let mut last_error: BoxError = format!("{} resolved to nothing", host).into();
+        for addr in addrs {
+            let result = match &pinned {
+                Some(tls) => connect_pinned(tls.clone(), uri.clone(), host, addr).await,
+                None => connect_addr(&uri, host, https, addr).await,
+            };
+            match result {
+                Ok(channel) => {
+                    self.dns.mark_working(host, port, addr).await;
+                    return Ok(channel);
+                }
+                Err(e) => {
+                    println!("Connecting to {} at {} failed: {}", host, addr, e);
+                    self.dns.mark_failed(host, port, addr).await;
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+async fn connect_addr(
+    uri: &tonic::transport::Uri,
+    host: &str,
+    https: bool,
+    addr: SocketAddr,
+) -> Result<Channel, BoxError> {
+    let scheme = if https { "https" } else { "http" };
+    let mut endpoint =
+        Endpoint::from_shared(format!("{}://{}", scheme, addr))?.origin(uri.clone());
+    if https {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().domain_name(host))?;
+    }
+    Ok(endpoint.connect().await?)
+}
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::proto::ReportedProvider;
+use crate::provider_errors::ProviderError;
+use crate::storage::{consumer_namespace, Storage};
+
+// Bans by provider address, so a restarted consumer doesn't go straight back to them
+const REPUTATION: &str = "reputation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    // Send the relay to the same provider again, for errors our side just fixed
+    RetrySame,
+    // Move on to the next ranked provider
+    Failover,
+    // Move on and stop using this provider for `ban_secs`
+    Ban,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub action: Action,
+    // Put the provider into unresponsive_providers of the following relay sessions
+    #[serde(default)]
+    pub report: bool,
+}
+
+impl PolicyRule {
+    const fn new(action: Action, report: bool) -> Self {
+        Self { action, report }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErrorPolicyConfig {
+    pub epoch_mismatch: PolicyRule,
+    pub session_out_of_sync: PolicyRule,
+    pub overloaded: PolicyRule,
+    pub unavailable: PolicyRule,
+    pub other: PolicyRule,
+    // Relay attempts per interactive request across retries and failovers
+    pub max_attempts: usize,
+    // Errors in a row after which any failover escalates to a ban
+    pub ban_after_errors: u32,
+    pub ban_secs: u64,
+    // How long a reported provider stays in unresponsive_providers
+    pub report_secs: u64,
+}
+
+impl Default for ErrorPolicyConfig {
+    fn default() -> Self {
+        Self {
+            // The resync takes a moment, another provider may already be on the new epoch
+            epoch_mismatch: PolicyRule::new(Action::Failover, false),
+            session_out_of_sync: PolicyRule::new(Action::RetrySame, false),
+            overloaded: PolicyRule::new(Action::Failover, false),
+            unavailable: PolicyRule::new(Action::Failover, true),
+            other: PolicyRule::new(Action::Failover, false),
+            max_attempts: 3,
+            ban_after_errors: 5,
+            ban_secs: 600,
+            report_secs: 1200,
+        }
+    }
+}
+
+impl ErrorPolicyConfig {
+    fn rule(&self, error: ProviderError) -> PolicyRule {
+        match error {
+            ProviderError::EpochMismatch => self.epoch_mismatch,
+            ProviderError::SessionOutOfSync => self.session_out_of_sync,
+            ProviderError::Overloaded => self.overloaded,
+            ProviderError::Unavailable => self.unavailable,
+            ProviderError::Other => self.other,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredBan {
+    banned_until_s: i64,
+}
+
+struct Report {
+    disconnections: u64,
+    errors: u64,
+    last: Instant,
+    timestamp_s: i64,
+}
+
+pub struct ErrorPolicy {
+    config: ErrorPolicyConfig,
+    consecutive_errors: HashMap<String, u32>,
+    bans: HashMap<String, Instant>,
+    reports: HashMap<String, Report>,
+    storage: Arc<dyn Storage>,
+    namespace: String,
+}
+
+impl ErrorPolicy {
+    pub fn new(config: ErrorPolicyConfig, storage: Arc<dyn Storage>, consumer: &str) -> Self {
+        let namespace = consumer_namespace(REPUTATION, consumer);
+        let (now, now_s) = (Instant::now(), now_s());
+        let mut bans = HashMap::new();
+        // A stored ban never outlasts a fresh one, whatever the stored value says
+        let ban_duration = Duration::from_secs(config.ban_secs);
+        for (provider, ban) in storage.load_all::<StoredBan>(&namespace) {
+            if ban.banned_until_s > now_s {
+                let left = Duration::from_secs(ban.banned_until_s.abs_diff(now_s));
+                if let Some(until) = now.checked_add(left.min(ban_duration)) {
+                    bans.insert(provider, until);
+                }
+            } else {
+                storage.remove(&namespace, &provider);
+            }
+        }
+        if !bans.is_empty() {
+            println!("Restored {} provider bans from storage", bans.len());
+        }
+        Self {
+            config,
+            consecutive_errors: HashMap::new(),
+            bans,
+            reports: HashMap::new(),
+            storage,
+            namespace,
+        }
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.config.max_attempts.max(1)
+    }
+
+    //
+    // What to do after `provider` failed a relay with `error`. An `injected` error, from fault
+    // injection, bans in memory only and is never reported, the provider did nothing wrong
+    pub fn decide(
+        &mut self,
+        provider: &str,
+        error: ProviderError,
+        injected: bool,
+        now: Instant,
+    ) -> Action {
+        let rule = self.config.rule(error);
+        let errors = self.consecutive_errors.entry(provider.to_string()).or_insert(0);
+        *errors += 1;
+
+        let mut action = rule.action;
+        if action == Action::Failover
+            && self.config.ban_after_errors > 0
+            && *errors >= self.config.ban_after_errors
+        {
+            action = Action::Ban;
+        }
+        if action == Action::Ban {
+            println!(
+                "Banning {} for {}s after {} errors in a row",
+                provider, self.config.ban_secs, errors
+            );
+            if let Some(until) = now.checked_add(Duration::from_secs(self.config.ban_secs)) {
+                self.bans.insert(provider.to_string(), until);
+            }
+            if !injected {
+                let ban = StoredBan {
+                    banned_until_s: now_s().saturating_add_unsigned(self.config.ban_secs),
+                };
+                self.storage.save(&self.namespace, provider, &ban);
+            }
+        }
+
+        if rule.report && !injected {
+            let timestamp_s = now_s();
+            let report = self.reports.entry(provider.to_string()).or_insert(Report {
+                disconnections: 0,
+                errors: 0,
+                last: now,
+                timestamp_s,
+            });
+            if error == ProviderError::Unavailable {
+                report.disconnections += 1;
+            } else {
+                report.errors += 1;
+            }
+            report.last = now;
+            report.timestamp_s = timestamp_s;
+        }
+        action
+    }
+
+    pub fn record_success(&mut self, provider: &str) {
+        self.consecutive_errors.remove(provider);
+    }
+
+    pub fn is_banned(&self, provider: &str, now: Instant) -> bool {
+        self.bans.get(provider).is_some_and(|until| *until > now)
+    }
+
+    //
+    // Reports to attach to a relay session sent to `target`, never the target itself
+    pub fn unresponsive_providers(&mut self, target: &str, now: Instant) -> Vec<ReportedProvider> {
+        let window = Duration::from_secs(self.config.report_secs);
+        self.reports
+            .retain(|_, report| now.saturating_duration_since(report.last) < window);
+        let mut reported = self
+            .reports
+            .iter()
+            .filter(|(address, _)| address.as_str() != target)
+            .map(|(address, report)| ReportedProvider {
+                address: address.clone(),
+                disconnections: report.disconnections,
+                errors: report.errors,
+                timestamp_s: report.timestamp_s,
+            })
+            .collect::<Vec<_>>();
+        reported.sort_by(|a, b| a.address.cmp(&b.address));
+        reported
+    }
+}
+
+fn now_s() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::session_context::RELAY_CU;
+use crate::spec::ChainSpec;
+use crate::utils::JSONRPC_INTERFACE;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EstimateRequest {
+    pub methods: Vec<String>,
+    // A JSON-RPC request or batch, its methods are added to `methods`
+    pub payload: Option<Value>,
+    pub requests_per_second: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MethodCost {
+    pub method: String,
+    pub compute_units: u64,
+    // false when the method isn't in the spec and the default relay cost was assumed
+    pub in_spec: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CuEstimate {
+    pub spec: String,
+    pub methods: Vec<MethodCost>,
+    pub cu_per_request_mix: u64,
+    pub requests_per_second: f64,
+    pub cu_per_second: f64,
+    pub cu_per_hour: f64,
+    pub cu_per_day: f64,
+    pub cu_per_epoch: Option<f64>,
+    pub max_cu_per_epoch: Option<u64>,
+    // How long the epoch allowance lasts at this rate when one provider serves everything
+    pub epoch_allowance_lasts_secs: Option<f64>,
+}
+
+pub fn methods_from_payload(payload: &Value) -> Vec<String> {
+    let calls = match payload.as_array() {
+        Some(batch) => batch.iter().collect::<Vec<_>>(),
+        None => vec![payload],
+    };
+    calls
+        .iter()
+        .filter_map(|call| call["method"].as_str().map(|m| m.to_string()))
+        .collect()
+}
+
+//
+// `methods` is one request mix, each listed method is sent once per request
+pub fn estimate(
+    spec: &ChainSpec,
+    methods: &[String],
+    requests_per_second: f64,
+    epoch_duration: Option<Duration>,
+    max_cu: Option<u64>,
+) -> CuEstimate {
+    let methods = methods
+        .iter()
+        .map(|method| {
+            let compute_units = spec.compute_units(JSONRPC_INTERFACE, method);
+            MethodCost {
+                method: method.clone(),
+                compute_units: compute_units.unwrap_or(RELAY_CU),
+                in_spec: compute_units.is_some(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let cu_per_request_mix = methods.iter().map(|m| m.compute_units).sum::<u64>();
+    let cu_per_second = cu_per_request_mix as f64 * requests_per_second;
+    let cu_per_epoch = epoch_duration.map(|d| cu_per_second * d.as_secs_f64());
+    let epoch_allowance_lasts_secs = match max_cu {
+        Some(max_cu) if cu_per_second > 0.0 => Some(max_cu as f64 / cu_per_second),
+        _ => None,
+    };
+
+    CuEstimate {
+        spec: spec.index.clone(),
+        methods,
+        cu_per_request_mix,
+        requests_per_second,
+        cu_per_second,
+        cu_per_hour: cu_per_second * 3600.0,
+        cu_per_day: cu_per_second * 86400.0,
+        cu_per_epoch,
+        max_cu_per_epoch: max_cu,
+        epoch_allowance_lasts_secs,
+    }
+}
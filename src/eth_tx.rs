@@ -0,0 +1,140 @@
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use sha3::{Digest, Keccak256};
+
+//
+// Just enough RLP to recover the sender of a signed transaction, items keep their raw
+// encoding so the signing payload can be rebuilt without re-encoding nested lists
+struct Item<'a> {
+    raw: &'a [u8],
+    payload: &'a [u8],
+}
+
+fn decode_header(input: &[u8]) -> Option<(bool, usize, usize)> {
+    let prefix = *input.first()?;
+    let (is_list, offset, len) = match prefix {
+        0x00..=0x7f => (false, 0, 1),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => {
+            let len_of_len = (prefix - 0xb7) as usize;
+            (false, 1 + len_of_len, be_usize(input.get(1..1 + len_of_len)?)?)
+        }
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => {
+            let len_of_len = (prefix - 0xf7) as usize;
+            (true, 1 + len_of_len, be_usize(input.get(1..1 + len_of_len)?)?)
+        }
+    };
+    if input.len() < offset.checked_add(len)? {
+        return None;
+    }
+    Some((is_list, offset, len))
+}
+
+fn decode_list(input: &[u8]) -> Option<Vec<Item<'_>>> {
+    let (is_list, offset, len) = decode_header(input)?;
+    if !is_list || offset + len != input.len() {
+        return None;
+    }
+    let mut rest = &input[offset..];
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        let (_, offset, len) = decode_header(rest)?;
+        items.push(Item {
+            raw: &rest[..offset + len],
+            payload: &rest[offset..offset + len],
+        });
+        rest = &rest[offset + len..];
+    }
+    Some(items)
+}
+
+fn be_usize(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() > std::mem::size_of::<usize>() {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |acc, b| (acc << 8) | *b as usize))
+}
+
+fn be_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn encode_list(items: &[u8]) -> Vec<u8> {
+    let mut out = encode_length(items.len(), 0xc0);
+    out.extend_from_slice(items);
+    out
+}
+
+fn encode_u64(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let trimmed = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(8)..];
+    match trimmed {
+        [b] if *b < 0x80 => vec![*b],
+        _ => {
+            let mut out = encode_length(trimmed.len(), 0x80);
+            out.extend_from_slice(trimmed);
+            out
+        }
+    }
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes = len.to_be_bytes();
+    let trimmed = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(0)..];
+    let mut out = vec![offset + 55 + trimmed.len() as u8];
+    out.extend_from_slice(trimmed);
+    out
+}
+
+//
+// Supports legacy (pre and post EIP-155) and EIP-2718 typed transactions, returns the
+// lowercase 0x address of the signer
+pub fn recover_sender(raw: &[u8]) -> Option<String> {
+    let (signing_payload, recovery_id, r, s) = match *raw.first()? {
+        tx_type @ 0x00..=0x7f => {
+            let items = decode_list(&raw[1..])?;
+            let (fields, signature) = items.split_at(items.len().checked_sub(3)?);
+            let mut payload = vec![tx_type];
+            payload.extend(encode_list(&fields.iter().flat_map(|i| i.raw).copied().collect::<Vec<_>>()));
+            let parity = be_u64(signature[0].payload)?;
+            (payload, parity, signature[1].payload, signature[2].payload)
+        }
+        _ => {
+            let items = decode_list(raw)?;
+            if items.len() != 9 {
+                return None;
+            }
+            let v = be_u64(items[6].payload)?;
+            let mut fields = items[..6].iter().flat_map(|i| i.raw).copied().collect::<Vec<_>>();
+            let parity = if v >= 35 {
+                fields.extend(encode_u64((v - 35) / 2));
+                fields.extend([0x80, 0x80]);
+                (v - 35) % 2
+            } else {
+                v.checked_sub(27)?
+            };
+            (encode_list(&fields), parity, items[7].payload, items[8].payload)
+        }
+    };
+
+    if r.len() > 32 || s.len() > 32 {
+        return None;
+    }
+    let mut signature_bytes = [0u8; 64];
+    signature_bytes[32 - r.len()..32].copy_from_slice(r);
+    signature_bytes[64 - s.len()..].copy_from_slice(s);
+    let signature = Signature::from_slice(&signature_bytes).ok()?;
+    let recovery_id = RecoveryId::from_byte(u8::try_from(recovery_id).ok()?)?;
+
+    let hash = Keccak256::digest(&signing_payload);
+    let key = VerifyingKey::recover_from_prehash(&hash, &signature, recovery_id).ok()?;
+    let public_key = key.to_encoded_point(false);
+    let address = Keccak256::digest(&public_key.as_bytes()[1..]);
+    Some(format!("0x{}", hex::encode(&address[12..])))
+}
@@ -0,0 +1,197 @@
+use axum::http::{HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+pub const FALLBACK_HEADER: &str = "x-lava-fallback";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackConfig {
+    // Emergency non-Lava JSON-RPC node per spec, e.g. {"ETH1": "https://..."}. Used only while
+    // no provider of the spec is reachable or every relay attempt failed
+    pub urls: HashMap<String, String>,
+    // Same for REST specs, the node's REST base URL the call's path and query are appended to
+    pub rest_urls: HashMap<String, String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            urls: HashMap::new(),
+            rest_urls: HashMap::new(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+//
+// Where a reply came from, direct node replies carry FALLBACK_HEADER
+pub enum Served {
+    Lava(Vec<u8>),
+    Direct(Vec<u8>),
+}
+
+impl IntoResponse for Served {
+    fn into_response(self) -> Response {
+        self.respond(IntoResponse::into_response)
+    }
+}
+
+impl Served {
+    pub fn map(self, f: impl FnOnce(Vec<u8>) -> Vec<u8>) -> Self {
+        match self {
+            Served::Lava(data) => Served::Lava(f(data)),
+            Served::Direct(data) => Served::Direct(f(data)),
+        }
+    }
+
+    //
+    // Build the response with `f`, marking direct node replies
+    pub fn respond(self, f: impl FnOnce(Vec<u8>) -> Response) -> Response {
+        match self {
+            Served::Lava(data) => f(data),
+            Served::Direct(data) => {
+                let mut response = f(data);
+                response
+                    .headers_mut()
+                    .insert(FALLBACK_HEADER, HeaderValue::from_static("direct-node"));
+                response
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DirectFallback {
+    config: FallbackConfig,
+    client: reqwest::Client,
+    // Specs currently served by their direct node, to log the switch and the way back once
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl DirectFallback {
+    pub fn new(config: FallbackConfig) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(config.timeout_secs))
+                .build()
+                .unwrap_or_default(),
+            config,
+            active: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    //
+    // Takes the outcome of relaying through Lava. Server errors go to the spec's direct node
+    // if it has one, client errors and throttling are passed on as they are. Providers are
+    // still tried first on every request, so traffic moves back as soon as they recover
+    pub async fn relay(
+        &self,
+        spec_id: &str,
+        result: Result<Vec<u8>, StatusCode>,
+        payload: &[u8],
+    ) -> Result<Served, StatusCode> {
+        let url = match self.direct_node(&self.config.urls, spec_id, result).await? {
+            Ok(data) => return Ok(Served::Lava(data)),
+            Err(url) => url,
+        };
+        let request = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .body(payload.to_vec());
+        let (status, data) = self.send(url, request).await?;
+        if !status.is_success() {
+            println!("Fallback: direct node {} returned {}", url, status);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+        Ok(Served::Direct(data))
+    }
+
+    //
+    // The REST counterpart of relay. Client errors of the node are passed on, their
+    // gRPC-gateway body says what went wrong
+    pub async fn relay_rest(
+        &self,
+        spec_id: &str,
+        result: Result<Vec<u8>, StatusCode>,
+        method: &Method,
+        api_url: &str,
+        body: &[u8],
+    ) -> Result<Served, StatusCode> {
+        let url = match self.direct_node(&self.config.rest_urls, spec_id, result).await? {
+            Ok(data) => return Ok(Served::Lava(data)),
+            Err(url) => url,
+        };
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}{}", url.trim_end_matches('/'), api_url));
+        if method == Method::POST {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_vec());
+        }
+        let (_, data) = self.send(url, request).await?;
+        Ok(Served::Direct(data))
+    }
+
+    //
+    // Ok with the Lava reply, or Err with the direct node that has to serve instead
+    async fn direct_node<'a>(
+        &self,
+        urls: &'a HashMap<String, String>,
+        spec_id: &str,
+        result: Result<Vec<u8>, StatusCode>,
+    ) -> Result<Result<Vec<u8>, &'a str>, StatusCode> {
+        let status = match result {
+            Ok(data) => {
+                if self.active.lock().await.remove(spec_id) {
+                    println!("Fallback: providers of {} recovered, leaving direct node", spec_id);
+                }
+                return Ok(Ok(data));
+            }
+            Err(status) => status,
+        };
+        let Some(url) = urls.get(spec_id) else {
+            return Err(status);
+        };
+        if !status.is_server_error() {
+            return Err(status);
+        }
+        if self.active.lock().await.insert(spec_id.to_string()) {
+            println!(
+                "Fallback: no Lava provider of {} could serve ({}), routing to direct node {}",
+                spec_id, status, url
+            );
+        }
+        Ok(Err(url))
+    }
+
+    //
+    // Server errors of the direct node fail the request, anything else is its answer
+    async fn send(
+        &self,
+        url: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<(StatusCode, Vec<u8>), StatusCode> {
+        let response = request.send().await.map_err(|e| {
+            println!("Fallback: direct node {} failed: {}", url, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        let status = response.status();
+        if status.is_server_error() {
+            println!("Fallback: direct node {} returned {}", url, status);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+        let data = response.bytes().await.map_err(|e| {
+            println!("Fallback: failed to read reply of direct node {}: {}", url, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        Ok((status, data.to_vec()))
+    }
+}
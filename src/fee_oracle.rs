@@ -0,0 +1,193 @@
+use axum::http::StatusCode;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::pairing::RankedProvider;
+use crate::relay::relay;
+use crate::session_context::ConsumerSessionContext;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeOracleConfig {
+    // Number of top ranked providers asked
+    pub providers: usize,
+    // Blocks of eth_feeHistory looked at
+    pub blocks: u64,
+    // Priority fee percentiles, read as slow / standard / fast
+    pub reward_percentiles: [f64; 3],
+}
+
+impl Default for FeeOracleConfig {
+    fn default() -> Self {
+        Self {
+            providers: 3,
+            blocks: 20,
+            reward_percentiles: [25.0, 50.0, 75.0],
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeeSuggestion {
+    pub max_priority_fee_per_gas: String,
+    pub max_fee_per_gas: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeeRecommendation {
+    pub gas_price: Option<String>,
+    pub base_fee_per_gas: Option<String>,
+    pub slow: Option<FeeSuggestion>,
+    pub standard: Option<FeeSuggestion>,
+    pub fast: Option<FeeSuggestion>,
+    // Providers whose answers went into the medians
+    pub providers: Vec<String>,
+}
+
+struct ProviderFees {
+    gas_price: Option<u128>,
+    next_base_fee: Option<u128>,
+    // Mean reward over the blocks, one per configured percentile
+    rewards: Option<[u128; 3]>,
+}
+
+//
+// Ask the top providers for eth_gasPrice and eth_feeHistory at once and answer with the
+// median of each value, so one node with a skewed estimator can't move the recommendation
+pub async fn fee_recommendation(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    providers: &[RankedProvider],
+    config: &FeeOracleConfig,
+) -> Result<FeeRecommendation, StatusCode> {
+    let selected = &providers[..config.providers.max(1).min(providers.len())];
+    if selected.is_empty() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let answers = join_all(
+        selected
+            .iter()
+            .map(|provider| query_provider(context, provider, config)),
+    )
+    .await;
+
+    let mut gas_prices = Vec::new();
+    let mut base_fees = Vec::new();
+    let mut rewards: [Vec<u128>; 3] = Default::default();
+    let mut answered = Vec::new();
+    for (provider, fees) in selected.iter().zip(answers) {
+        if fees.gas_price.is_none() && fees.next_base_fee.is_none() {
+            println!("Fee oracle: no usable answer from {}", provider.provider.address);
+            continue;
+        }
+        answered.push(provider.provider.address.clone());
+        gas_prices.extend(fees.gas_price);
+        base_fees.extend(fees.next_base_fee);
+        if let Some(provider_rewards) = fees.rewards {
+            for (column, reward) in rewards.iter_mut().zip(provider_rewards) {
+                column.push(reward);
+            }
+        }
+    }
+    if answered.is_empty() {
+        return Err(StatusCode::BAD_GATEWAY);
+    }
+
+    let base_fee = median(&mut base_fees);
+    let [slow, standard, fast] = rewards.map(|mut column| {
+        let priority_fee = median(&mut column)?;
+        // Leave room for the base fee to double before the transaction gets included
+        let max_fee = base_fee?.saturating_mul(2).saturating_add(priority_fee);
+        Some(FeeSuggestion {
+            max_priority_fee_per_gas: to_hex(priority_fee),
+            max_fee_per_gas: to_hex(max_fee),
+        })
+    });
+
+    Ok(FeeRecommendation {
+        gas_price: median(&mut gas_prices).map(to_hex),
+        base_fee_per_gas: base_fee.map(to_hex),
+        slow,
+        standard,
+        fast,
+        providers: answered,
+    })
+}
+
+async fn query_provider(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    config: &FeeOracleConfig,
+) -> ProviderFees {
+    let gas_price_request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_gasPrice",
+        "params": [],
+    });
+    let fee_history_request = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "eth_feeHistory",
+        "params": [format!("{:#x}", config.blocks.max(1)), "latest", config.reward_percentiles],
+    });
+    // One after the other, two relays to the same provider at once race for its session
+    let gas_price = relay(context, provider, gas_price_request.to_string().into_bytes()).await;
+    let fee_history = relay(context, provider, fee_history_request.to_string().into_bytes()).await;
+
+    let gas_price = result_of(gas_price).and_then(|result| parse_quantity(&result));
+    let fee_history = result_of(fee_history);
+    ProviderFees {
+        gas_price,
+        // The last entry is the base fee of the block after the newest one
+        next_base_fee: fee_history
+            .as_ref()
+            .and_then(|history| history["baseFeePerGas"].as_array()?.last().and_then(parse_quantity)),
+        rewards: fee_history.as_ref().and_then(mean_rewards),
+    }
+}
+
+fn result_of(reply: Result<Vec<u8>, StatusCode>) -> Option<Value> {
+    let response: Value = serde_json::from_slice(&reply.ok()?).ok()?;
+    response.get("result").cloned()
+}
+
+fn mean_rewards(history: &Value) -> Option<[u128; 3]> {
+    let blocks = history["reward"].as_array()?;
+    if blocks.is_empty() {
+        return None;
+    }
+    let mut sums = [0u128; 3];
+    for block in blocks {
+        let block = block.as_array()?;
+        for (sum, reward) in sums.iter_mut().zip(block) {
+            *sum = sum.saturating_add(parse_quantity(reward)?);
+        }
+    }
+    Some(sums.map(|sum| sum / blocks.len() as u128))
+}
+
+fn parse_quantity(value: &Value) -> Option<u128> {
+    u128::from_str_radix(value.as_str()?.strip_prefix("0x")?, 16).ok()
+}
+
+fn to_hex(value: u128) -> String {
+    format!("{:#x}", value)
+}
+
+fn median(values: &mut [u128]) -> Option<u128> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        // Sorted, so the difference can't underflow and the sum is never formed
+        values[middle - 1] + (values[middle] - values[middle - 1]) / 2
+    } else {
+        values[middle]
+    })
+}
@@ -0,0 +1,65 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+// Lava geolocation bits as used in provider endpoints
+pub const GEOLOCATIONS: &[(&str, u64)] = &[
+    ("USC", 1),
+    ("EU", 2),
+    ("USE", 4),
+    ("USW", 8),
+    ("AF", 16),
+    ("AS", 32),
+    ("AU", 64),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeolocationConfig {
+    // Request header carrying the client region, set by the geo-balancer in front of us
+    pub header: String,
+    // Region used when the header is missing, None routes on the global ranking
+    pub default: Option<String>,
+}
+
+impl Default for GeolocationConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-lava-geolocation".to_string(),
+            default: None,
+        }
+    }
+}
+
+impl GeolocationConfig {
+    pub fn resolve(&self, headers: &HeaderMap) -> Option<u64> {
+        headers
+            .get(self.header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_geolocation)
+            .or_else(|| self.default.as_deref().and_then(parse_geolocation))
+    }
+}
+
+//
+// Accepts either a region code ("EU") or the raw bit value ("2")
+pub fn parse_geolocation(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(bits) = value.parse::<u64>() {
+        return GEOLOCATIONS
+            .iter()
+            .find(|(_, bit)| *bit == bits)
+            .map(|(_, bit)| *bit);
+    }
+    GEOLOCATIONS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, bit)| *bit)
+}
+
+pub fn geolocation_name(geolocation: u64) -> &'static str {
+    GEOLOCATIONS
+        .iter()
+        .find(|(_, bit)| *bit == geolocation)
+        .map(|(name, _)| *name)
+        .unwrap_or("unknown")
+}
@@ -0,0 +1,206 @@
+use axum::http::StatusCode;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::pairing::{RankedProvider, SDKPairingState};
+use crate::relay::{jsonrpc_relay_data, relay_with_failover};
+use crate::session_context::ConsumerSessionContext;
+use crate::utils::SPEC_ID;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GetLogsConfig {
+    // Largest fromBlock..toBlock span sent to a single provider, 0 disables splitting
+    pub max_block_range: u64,
+    // Requests needing more chunks than this are refused instead of fanned out
+    pub max_chunks: u64,
+}
+
+impl Default for GetLogsConfig {
+    fn default() -> Self {
+        Self {
+            max_block_range: 5000,
+            max_chunks: 20,
+        }
+    }
+}
+
+pub fn is_oversized(request: &Value, config: &GetLogsConfig) -> bool {
+    match block_range(request) {
+        Some((from, to)) => {
+            config.max_block_range > 0 && block_span(from, to) > config.max_block_range
+        }
+        None => false,
+    }
+}
+
+//
+// Only numeric ranges can be split, tags like "latest" and blockHash filters go through as is
+fn block_range(request: &Value) -> Option<(u64, u64)> {
+    if request["method"].as_str()? != "eth_getLogs" {
+        return None;
+    }
+    let filter = request["params"].get(0)?;
+    if filter.get("blockHash").is_some() {
+        return None;
+    }
+    let from = parse_block_number(&filter["fromBlock"])?;
+    let to = parse_block_number(&filter["toBlock"])?;
+    (from <= to).then_some((from, to))
+}
+
+// Blocks in from..=to, a range over every u64 block saturates rather than wrapping to 0
+fn block_span(from: u64, to: u64) -> u64 {
+    (to - from).saturating_add(1)
+}
+
+fn parse_block_number(value: &Value) -> Option<u64> {
+    let hex = value.as_str()?.strip_prefix("0x")?;
+    u64::from_str_radix(hex, 16).ok()
+}
+
+fn split_request(request: &Value, from: u64, to: u64, max_block_range: u64) -> Vec<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut start = from;
+    loop {
+        let end = to.min(start.saturating_add(max_block_range.max(1) - 1));
+        let mut chunk = request.clone();
+        chunk["id"] = json!(chunks.len());
+        chunk["params"][0]["fromBlock"] = json!(format!("0x{:x}", start));
+        chunk["params"][0]["toBlock"] = json!(format!("0x{:x}", end));
+        chunks.push(serde_json::to_vec(&chunk).unwrap_or_default());
+        if end == to {
+            break;
+        }
+        start = end + 1;
+    }
+    chunks
+}
+
+pub async fn relay_get_logs(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    providers: &[RankedProvider],
+    request: &Value,
+    config: &GetLogsConfig,
+) -> Result<Vec<u8>, StatusCode> {
+    let (from, to) = block_range(request).ok_or(StatusCode::BAD_REQUEST)?;
+    let span = block_span(from, to);
+    if span.div_ceil(config.max_block_range) > config.max_chunks {
+        return Ok(error_response(
+            request,
+            &format!(
+                "block range of {} exceeds the limit of {} blocks",
+                span,
+                config.max_block_range.saturating_mul(config.max_chunks)
+            ),
+        ));
+    }
+    let chunks = split_request(request, from, to, config.max_block_range);
+    println!(
+        "Splitting eth_getLogs over {} blocks into {} chunks across {} providers",
+        span,
+        chunks.len(),
+        providers.len().min(chunks.len())
+    );
+
+    let (pairing_state, max_attempts) = {
+        let context = context.lock().await;
+        (context.pairing_state.clone(), context.error_policy.max_attempts())
+    };
+
+    //
+    // One sequential lane per provider keeps relay_num ordered within each session. Within
+    // its lane a chunk is only retried on the same provider, if the error policy says so
+    let lane_count = providers.len().min(chunks.len());
+    let mut lanes: Vec<Vec<(usize, Vec<u8>)>> = vec![Vec::new(); lane_count];
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        lanes[index % lane_count].push((index, chunk));
+    }
+    let pairing_state = &pairing_state;
+    let lane_results = join_all(lanes.into_iter().enumerate().map(|(lane, chunks)| {
+        let provider = std::slice::from_ref(&providers[lane]);
+        async move {
+            let mut results = Vec::new();
+            for (index, chunk) in chunks {
+                let result =
+                    relay_chunk(context, pairing_state, provider, &chunk, max_attempts).await;
+                results.push((index, lane, chunk, result));
+            }
+            results
+        }
+    }))
+    .await;
+
+    //
+    // Chunks a provider failed move on to the next ranked providers, one chunk at a time
+    // now the lanes are done, so no provider has two of them in flight
+    let mut results = Vec::new();
+    for (index, lane, chunk, result) in lane_results.into_iter().flatten() {
+        let result = match result {
+            Err(_) if providers.len() > 1 => {
+                println!(
+                    "eth_getLogs chunk {} failed on {}, reassigning it",
+                    index, providers[lane].provider.address
+                );
+                let next = providers[lane + 1..]
+                    .iter()
+                    .chain(&providers[..lane])
+                    .cloned()
+                    .collect::<Vec<_>>();
+                relay_chunk(context, pairing_state, &next, &chunk, max_attempts).await
+            }
+            result => result,
+        };
+        results.push((index, result));
+    }
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut logs = Vec::new();
+    for (_, result) in results {
+        let response: Value = serde_json::from_slice(&result?).map_err(|e| {
+            println!("Invalid eth_getLogs chunk response: {}", e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        if let Some(error) = response.get("error") {
+            // Any failed chunk fails the whole range, the client can't use a partial log set
+            let response = json!({"jsonrpc": "2.0", "id": request["id"], "error": error});
+            return Ok(serde_json::to_vec(&response).unwrap_or_default());
+        }
+        match response["result"].as_array() {
+            Some(chunk_logs) => logs.extend(chunk_logs.iter().cloned()),
+            None => return Err(StatusCode::BAD_GATEWAY),
+        }
+    }
+
+    Ok(serde_json::to_vec(&json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "result": logs,
+    }))
+    .unwrap_or_default())
+}
+
+async fn relay_chunk(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    providers: &[RankedProvider],
+    chunk: &[u8],
+    max_attempts: usize,
+) -> Result<Vec<u8>, StatusCode> {
+    let relay_data = jsonrpc_relay_data(chunk.to_vec(), vec![]);
+    relay_with_failover(context, pairing_state, SPEC_ID, providers, relay_data, max_attempts)
+        .await
+        .map(|(data, _)| data)
+}
+
+fn error_response(request: &Value, message: &str) -> Vec<u8> {
+    serde_json::to_vec(&json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "error": {"code": -32005, "message": message},
+    }))
+    .unwrap_or_default()
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandoverConfig {
+    // Bind with SO_REUSEPORT so an upgraded binary can listen on the same port while this one
+    // still runs. Deploy by starting the new process, then sending SIGTERM to the old one.
+    // Off by default, any process of the same user could then bind the port and take traffic
+    pub reuse_port: bool,
+    // After SIGTERM, how long open requests and WS subscriptions may keep the process alive
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for HandoverConfig {
+    fn default() -> Self {
+        Self {
+            reuse_port: false,
+            drain_timeout_secs: 300,
+        }
+    }
+}
+
+pub fn bind(addr: SocketAddr, config: &HandoverConfig) -> std::io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(config.reuse_port)?;
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+//
+// SIGTERM (or Ctrl-C) starts the drain: the listener closes so new connections land on the
+// process that took over, while the ones already open here are served to the end
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(e) => {
+                    println!("Failed to listen for SIGTERM: {}", e);
+                    let _ = tokio::signal::ctrl_c().await;
+                    return;
+                }
+            };
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+//
+// Upgraded WS connections aren't tracked by the HTTP server's graceful shutdown, their
+// handlers hold a guard instead so the drain can wait for them
+#[derive(Default)]
+pub struct Drain {
+    active: AtomicUsize,
+    idle: Notify,
+}
+
+pub struct DrainGuard(Arc<Drain>);
+
+impl Drop for DrainGuard {
+    fn drop(&mut self) {
+        if self.0.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Drain {
+    pub fn track(self: &Arc<Self>) -> DrainGuard {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        DrainGuard(Arc::clone(self))
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            if self.active() == 0 {
+                return;
+            }
+            println!("Waiting for {} WS connections to close", self.active());
+            idle.await;
+        }
+    }
+}
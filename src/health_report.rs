@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+use crate::crypto::public_key_to_address;
+use crate::provider_stats::ProviderSummary;
+use crate::session_context::ConsumerSessionContext;
+use crate::utils::{LAVA_CHAIN_PREFIX, SPEC_ID};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthReportConfig {
+    // Endpoint the reports are POSTed to as JSON, nothing is sent without one
+    pub url: Option<String>,
+    pub interval_secs: u64,
+    // Extra request headers, e.g. {"Authorization": "Bearer ..."}
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for HealthReportConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            interval_secs: 60,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainHealth {
+    pub spec_id: String,
+    pub epoch: i64,
+    pub providers_paired: usize,
+    // Providers that answered the last probe
+    pub providers_reachable: usize,
+    pub cu_used: u64,
+    pub max_cu: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub consumer: String,
+    pub version: String,
+    pub timestamp_s: i64,
+    pub chains: Vec<ChainHealth>,
+    // Over the last minute, across providers
+    pub relays: usize,
+    pub relay_errors: usize,
+    pub relay_success_rate: f64,
+    pub providers: Vec<ProviderSummary>,
+}
+
+//
+// Push a health summary to the configured sink, for fleets that can't scrape us
+pub async fn health_report_task(context: Arc<Mutex<ConsumerSessionContext>>) {
+    let config = context.lock().await.config.health_report.clone();
+    let Some(url) = config.url else {
+        return;
+    };
+    println!("Sending health reports to {} every {}s", url, config.interval_secs);
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let report = health_report(&context).await;
+        let mut request = client.post(&url).json(&report);
+        for (name, value) in &config.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => println!("Health report rejected by {}: {}", url, response.status()),
+            Err(e) => println!("Failed to send health report to {}: {}", url, e),
+        }
+    }
+}
+
+pub async fn health_report(context: &Arc<Mutex<ConsumerSessionContext>>) -> HealthReport {
+    let context = context.lock().await;
+    let consumer = public_key_to_address(
+        &context.private_key.verifying_key().to_sec1_bytes(),
+        LAVA_CHAIN_PREFIX,
+    )
+    .unwrap_or_default();
+
+    let mut chains = vec![(SPEC_ID.to_string(), context.pairing_state.clone())];
+    let mut extra = context.chains.iter().collect::<Vec<_>>();
+    extra.sort_by(|a, b| a.0.cmp(b.0));
+    chains.extend(extra.into_iter().map(|(spec_id, state)| (spec_id.clone(), state.clone())));
+    let mut chain_health = Vec::new();
+    for (spec_id, state) in chains {
+        let state = state.lock().await;
+        let epoch = state.params.current_epoch;
+        chain_health.push(ChainHealth {
+            cu_used: context.epoch_cu_used(&spec_id, epoch),
+            spec_id,
+            epoch,
+            providers_paired: state.providers.len(),
+            providers_reachable: state.ranked_providers.len(),
+            max_cu: state.params.max_cu,
+        });
+    }
+
+    let providers = context.stats.lock().await.summaries();
+    let relays = providers.iter().map(|p| p.requests).sum::<usize>();
+    let relay_errors = providers.iter().map(|p| p.errors).sum::<usize>();
+    HealthReport {
+        consumer,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp_s: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64),
+        chains: chain_health,
+        relays,
+        relay_errors,
+        relay_success_rate: if relays == 0 {
+            1.0
+        } else {
+            1.0 - relay_errors as f64 / relays as f64
+        },
+        providers,
+    }
+}
@@ -0,0 +1,49 @@
+pub mod archive;
+pub mod cache;
+pub mod chaos;
+pub mod cli;
+pub mod config;
+pub mod crypto;
+pub mod diagnostics;
+pub mod dns;
+pub mod dogfood;
+pub mod emergency;
+pub mod endpoint;
+pub mod error_policy;
+pub mod estimate;
+pub mod eth_tx;
+pub mod fallback;
+pub mod fee_oracle;
+pub mod geolocation;
+pub mod get_logs;
+pub mod handover;
+pub mod health_report;
+pub mod listeners;
+pub mod load_shedding;
+pub mod loadtest;
+pub mod middleware;
+pub mod pacing;
+pub mod pairing;
+pub mod pinning;
+pub mod priority;
+pub mod proofs;
+pub mod provider_errors;
+pub mod provider_stats;
+pub mod qos;
+pub mod quorum;
+pub mod recorder;
+pub mod relay;
+pub mod relay_session;
+pub mod rest;
+pub mod server;
+pub mod session_context;
+pub mod shadow;
+pub mod spec;
+pub mod storage;
+pub mod subscriptions;
+pub mod tls;
+pub mod utils;
+
+pub mod proto {
+    tonic::include_proto!("lavanet.lava.pairing");
+}
@@ -0,0 +1,86 @@
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::Response;
+use axum::routing::{any, post};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::rest::relay_rest_call;
+use crate::server::serve_query;
+use crate::session_context::ConsumerSessionContext;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenConfig {
+    pub host: String,
+    pub port: u16,
+    // Specs served on a port of their own at the root path, e.g. {"NEAR": 3001, "LAV1": 3002},
+    // for clients that can't be pointed at /{spec}/
+    pub chains: HashMap<String, u16>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            chains: HashMap::new(),
+        }
+    }
+}
+
+impl ListenConfig {
+    //
+    // Every port is bound once, a chain can't share the main port or another chain's
+    pub fn validate(&self) -> Result<(), String> {
+        let mut ports = HashMap::from([(self.port, "the main router".to_string())]);
+        let mut chains = self.chains.iter().collect::<Vec<_>>();
+        chains.sort();
+        for (spec_id, port) in chains {
+            if let Some(other) = ports.insert(*port, spec_id.clone()) {
+                return Err(format!("Port {} is set for both {} and {}", port, other, spec_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+type ChainState = (Arc<Mutex<ConsumerSessionContext>>, String);
+
+//
+// JSON-RPC on POST /, anything else is a REST call on the chain's path. SPEC_ID gets the full
+// router instead, this one has no WS, estimate, fees or admin routes
+pub fn chain_router(context: Arc<Mutex<ConsumerSessionContext>>, spec_id: String) -> Router {
+    Router::new()
+        .route("/", post(handle_chain_query))
+        .route("/*path", any(handle_chain_rest))
+        .with_state((context, spec_id))
+}
+
+//
+// Same pipeline as the main port's / (shedding, middleware, priority, cache, fallback),
+// only the spec differs
+async fn handle_chain_query(
+    State((context, spec_id)): State<ChainState>,
+    headers: HeaderMap,
+    payload: Bytes,
+) -> Result<Response, StatusCode> {
+    serve_query(&context, &spec_id, &headers, payload).await
+}
+
+async fn handle_chain_rest(
+    State((context, spec_id)): State<ChainState>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    let api_url = match uri.query() {
+        Some(query) => format!("{}?{}", uri.path(), query),
+        None => uri.path().to_string(),
+    };
+    relay_rest_call(&context, &spec_id, method, api_url, body).await
+}
@@ -0,0 +1,62 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::priority::Priority;
+use crate::provider_stats::ProviderSummary;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadSheddingConfig {
+    pub enabled: bool,
+    // A provider counts as degraded above this error rate or p95 latency
+    pub error_rate_threshold: f64,
+    pub p95_latency_ms_threshold: u64,
+    // Shedding starts once this share of the providers with enough samples is degraded
+    pub degraded_provider_fraction: f64,
+    pub min_samples: usize,
+    // Share of interactive requests rejected while degraded, batch requests are all shed first
+    pub shed_fraction: f64,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            error_rate_threshold: 0.5,
+            p95_latency_ms_threshold: 5000,
+            degraded_provider_fraction: 0.75,
+            min_samples: 10,
+            shed_fraction: 0.5,
+        }
+    }
+}
+
+impl LoadSheddingConfig {
+    pub fn is_degraded(&self, summaries: &[ProviderSummary]) -> bool {
+        let sampled = summaries
+            .iter()
+            .filter(|s| s.requests >= self.min_samples)
+            .collect::<Vec<_>>();
+        if sampled.is_empty() {
+            return false;
+        }
+        let degraded = sampled
+            .iter()
+            .filter(|s| {
+                s.error_rate >= self.error_rate_threshold
+                    || s.p95_latency_ms >= self.p95_latency_ms_threshold
+            })
+            .count();
+        degraded as f64 / sampled.len() as f64 >= self.degraded_provider_fraction
+    }
+
+    pub fn should_shed(&self, summaries: &[ProviderSummary], priority: Priority) -> bool {
+        if !self.enabled || self.shed_fraction <= 0.0 || !self.is_degraded(summaries) {
+            return false;
+        }
+        match priority {
+            Priority::Batch => true,
+            Priority::Interactive => rand::thread_rng().gen_bool(self.shed_fraction.min(1.0)),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+use crate::provider_stats::{percentile, ProviderSummary};
+use crate::spec::ChainSpec;
+use crate::utils::JSONRPC_INTERFACE;
+
+// Beyond this the ticker period drops under 100us, more than one process can send anyway
+pub const MAX_RPS: f64 = 10_000.0;
+
+pub fn validate_rps(rps: f64) -> Result<(), String> {
+    if rps > 0.0 && rps <= MAX_RPS {
+        Ok(())
+    } else {
+        Err(format!("--rps has to be above 0 and at most {}", MAX_RPS))
+    }
+}
+
+//
+// Where the load goes: a running consumer over HTTP, or the router of one started in-process,
+// either way every request takes the full path through middleware, routing and relaying
+pub enum Target {
+    Remote { url: String, client: reqwest::Client },
+    Local(Router),
+}
+
+impl Target {
+    pub fn remote(url: &str) -> Self {
+        Self::Remote {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn send(&self, body: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Remote { url, client } => {
+                let response = client
+                    .post(url.as_str())
+                    .header(header::CONTENT_TYPE.as_str(), "application/json")
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if response.status() != 200 {
+                    return Err(response.status().to_string());
+                }
+                Ok(response.bytes().await.map_err(|e| e.to_string())?.to_vec())
+            }
+            Self::Local(router) => {
+                let request = Request::post("/")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .map_err(|e| e.to_string())?;
+                let response = router
+                    .clone()
+                    .oneshot(request)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if response.status() != StatusCode::OK {
+                    return Err(response.status().to_string());
+                }
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(body.to_vec())
+            }
+        }
+    }
+}
+
+//
+// One request per method, without params
+pub fn mix_from_methods(methods: &[String]) -> Vec<Value> {
+    methods
+        .iter()
+        .map(|method| json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": []}))
+        .collect()
+}
+
+//
+// A request object is a mix of one, a list is sent in turn, and a list inside the list is a batch
+pub fn mix_from_payload(payload: &Value) -> Vec<Value> {
+    match payload {
+        Value::Array(requests) => requests.clone(),
+        request => vec![request.clone()],
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LatencySummary {
+    pub requests: usize,
+    pub errors: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+impl LatencySummary {
+    fn from_samples(samples: &[&Sample]) -> Self {
+        let mut latencies = samples.iter().map(|s| s.latency).collect::<Vec<_>>();
+        latencies.sort();
+        Self {
+            requests: samples.len(),
+            errors: samples.iter().filter(|s| !s.success).count(),
+            p50_ms: percentile(&latencies, 0.50).as_millis() as u64,
+            p90_ms: percentile(&latencies, 0.90).as_millis() as u64,
+            p99_ms: percentile(&latencies, 0.99).as_millis() as u64,
+            max_ms: latencies.last().copied().unwrap_or_default().as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MethodReport {
+    pub latency: LatencySummary,
+    pub compute_units: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoadTestReport {
+    pub duration_secs: f64,
+    pub target_rps: f64,
+    // Successful requests per second actually achieved
+    pub throughput_rps: f64,
+    pub latency: LatencySummary,
+    pub methods: BTreeMap<String, MethodReport>,
+    // Priced with the chain spec, only successful relays are charged
+    pub cu_consumed: u64,
+    pub providers: Vec<ProviderSummary>,
+}
+
+struct Sample {
+    label: String,
+    latency: Duration,
+    success: bool,
+    compute_units: u64,
+}
+
+//
+// Send `mix` round robin at `rps` for `duration`, then wait for whatever is still in flight
+pub async fn run(
+    target: Arc<Target>,
+    mix: &[Value],
+    spec: &ChainSpec,
+    rps: f64,
+    duration: Duration,
+) -> LoadTestReport {
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rps.max(0.001)));
+    let mut in_flight = JoinSet::new();
+    let start = Instant::now();
+    let mut sent = 0usize;
+    while start.elapsed() < duration {
+        ticker.tick().await;
+        let request = &mix[sent % mix.len()];
+        let label = label(request);
+        let payload = request.to_string().into_bytes();
+        let compute_units = spec.relay_cu(JSONRPC_INTERFACE, &payload);
+        let target = Arc::clone(&target);
+        in_flight.spawn(async move {
+            let sent_at = Instant::now();
+            let result = target.send(payload).await;
+            let latency = sent_at.elapsed();
+            let success = match result {
+                Ok(data) => !is_error_reply(&data),
+                Err(e) => {
+                    println!("{} failed: {}", label, e);
+                    false
+                }
+            };
+            Sample {
+                label,
+                latency,
+                success,
+                compute_units,
+            }
+        });
+        sent += 1;
+    }
+
+    let mut samples = Vec::with_capacity(sent);
+    while let Some(result) = in_flight.join_next().await {
+        if let Ok(sample) = result {
+            samples.push(sample);
+        }
+    }
+    let elapsed = start.elapsed();
+    report(&samples, rps, elapsed)
+}
+
+fn report(samples: &[Sample], target_rps: f64, elapsed: Duration) -> LoadTestReport {
+    let mut by_method = BTreeMap::<String, Vec<&Sample>>::new();
+    for sample in samples {
+        by_method.entry(sample.label.clone()).or_default().push(sample);
+    }
+    let methods = by_method
+        .into_iter()
+        .map(|(label, samples)| {
+            let compute_units = charged_cu(&samples);
+            let report = MethodReport {
+                latency: LatencySummary::from_samples(&samples),
+                compute_units,
+            };
+            (label, report)
+        })
+        .collect();
+
+    let all = samples.iter().collect::<Vec<_>>();
+    let successes = samples.iter().filter(|s| s.success).count();
+    LoadTestReport {
+        duration_secs: elapsed.as_secs_f64(),
+        target_rps,
+        throughput_rps: successes as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        latency: LatencySummary::from_samples(&all),
+        methods,
+        cu_consumed: charged_cu(&all),
+        providers: vec![],
+    }
+}
+
+fn charged_cu(samples: &[&Sample]) -> u64 {
+    samples
+        .iter()
+        .filter(|s| s.success)
+        .map(|s| s.compute_units)
+        .sum()
+}
+
+fn label(request: &Value) -> String {
+    match request {
+        Value::Array(batch) => format!("batch({})", batch.len()),
+        request => request["method"].as_str().unwrap_or("unknown").to_string(),
+    }
+}
+
+//
+// A single request answered with a JSON-RPC error counts as failed, batches by their HTTP status
+fn is_error_reply(data: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(data)
+        .map(|reply| reply.get("error").is_some_and(|error| !error.is_null()))
+        .unwrap_or(true)
+}
@@ -1,84 +1,311 @@
-mod cli;
-mod crypto;
-mod pairing;
-mod relay_session;
-mod server;
-mod session_context;
-mod utils;
-
-use crate::utils::LAVA_CHAIN_PREFIX;
-use cli::{Cli, Creds};
-use crypto::{public_key_to_address, signing_key_from_hex};
-use server::start_server;
-use session_context::ConsumerSessionContext;
-
-use crate::pairing::{
-    get_ranked_providers, get_sdk_pairing_params, sdk_pairing_task, SDKPairingState,
+use lavap_rs::cli::{Cli, Command, Creds, DiagnosticsCommand};
+use lavap_rs::config::Config;
+use lavap_rs::crypto::{public_key_to_address, signing_key_from_hex};
+use lavap_rs::diagnostics::{imported_config, restore_pairing, Snapshot};
+use lavap_rs::dogfood::LavaQuerier;
+use lavap_rs::endpoint::Connector;
+use lavap_rs::estimate::{estimate, methods_from_payload};
+use lavap_rs::proofs::{export_proofs, export_stored_proofs};
+use lavap_rs::recorder::Recorder;
+use lavap_rs::health_report::health_report_task;
+use lavap_rs::loadtest::{
+    mix_from_methods, mix_from_payload, run as run_loadtest, validate_rps, Target,
 };
+use lavap_rs::server::{router, start_server};
+use lavap_rs::session_context::ConsumerSessionContext;
+use lavap_rs::spec::{fetch_spec, ChainSpec};
+use lavap_rs::storage::{open_storage, MemoryStorage};
+use lavap_rs::utils::{parse_duration, LAVA_CHAIN_PREFIX, SPEC_ID};
 
+use lavap_rs::pairing::{get_ranked_providers, sdk_pairing_task, SDKPairingState};
+
+use k256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use std::collections::HashMap;
 use std::sync::Arc;
 use structopt::StructOpt;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 
-pub mod proto {
-    tonic::include_proto!("lavanet.lava.pairing");
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::from_args();
-    let creds = Creds::from_file(&args.creds)?;
+    let mut args = Cli::from_args();
+    let config = Config::load(args.config.as_deref())?;
+
+    if let Some(cmd) = args.cmd.take() {
+        return run_command(cmd, &config, &args).await;
+    }
+
+    let (context, shutdowns) = start_consumer(&args, config).await?;
+    tokio::spawn(health_report_task(context.clone()));
+
+    //
+    // Spawn the server
+    let server_context = context.clone();
+    let task = tokio::spawn(async move {
+        if let Err(e) = start_server(server_context).await {
+            eprintln!("Server error: {}", e);
+        }
+    });
+    task.await?;
+
+    // Shutdown the SDK pairing tasks
+    for shutdown_tx in shutdowns {
+        shutdown_tx.send(()).await?;
+    }
+
+    Ok(())
+}
+
+//
+// Start pairing and set up the session context, everything short of serving it. Pairing and
+// ranking go on in the background, until they're done requests take the direct-node fallback
+async fn start_consumer(
+    args: &Cli,
+    config: Config,
+) -> Result<(Arc<Mutex<ConsumerSessionContext>>, Vec<mpsc::Sender<()>>), Box<dyn std::error::Error>> {
+    let creds = Creds::from_file(args.creds.as_deref().ok_or("--creds is required")?)?;
     let private_key = signing_key_from_hex(&creds.secret_key)?;
     let verifying_key = private_key.verifying_key();
     let public_key_bytes = verifying_key.to_sec1_bytes();
     let address = public_key_to_address(&public_key_bytes, LAVA_CHAIN_PREFIX)?;
 
+    let recorder = Arc::new(match (&args.record, &args.replay) {
+        (Some(path), _) => Recorder::record(path)?,
+        (_, Some(path)) => Recorder::replay(path)?,
+        _ => Recorder::live(),
+    });
+
+    let storage = open_storage(&config.storage)?;
+    let querier = Arc::new(LavaQuerier::new(config.dogfood.clone()));
+    let connector = Arc::new(Connector::new(config.dns.clone(), &config.tls)?);
+    tokio::spawn(Arc::clone(&connector.dns).refresh_task());
+
     //
-    // Start the SDK pairing task
-    let state = Arc::new(Mutex::new(SDKPairingState::new()));
+    // Start the SDK pairing task, plus one per additional REST chain
+    let state = Arc::new(Mutex::new(SDKPairingState::with_connector(Arc::clone(&connector))));
     let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
     let pairing_state = Arc::clone(&state);
+    let pairing_recorder = Arc::clone(&recorder);
+    let pairing_address = address.clone();
+    let pairing_querier = Arc::clone(&querier);
+    let pairing_storage = Arc::clone(&storage);
     tokio::spawn(async move {
-        sdk_pairing_task(address, "ETH1".to_string(), pairing_state, shutdown_rx).await;
+        sdk_pairing_task(
+            pairing_address,
+            SPEC_ID.to_string(),
+            pairing_state,
+            pairing_recorder,
+            pairing_querier,
+            pairing_storage,
+            shutdown_rx,
+        )
+        .await;
     });
 
-    //
-    //
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        let ranked_providers = get_ranked_providers(Arc::clone(&state)).await;
-
-        if ranked_providers.len() > 0 {
-            println!("Top Ranked Providers:");
-            for (i, provider) in ranked_providers.iter().enumerate() {
-                println!(
-                    "{}. Address: {}, Latency: {:?}",
-                    i + 1,
-                    provider.provider.address,
-                    provider.latency,
-                );
-            }
-
-            let params = get_sdk_pairing_params(Arc::clone(&state)).await;
-            println!("Current SDK Pairing Params: {:?}", params);
-            break;
+    // Dogfooding needs the Lava chain's own pairing even when it isn't served
+    let mut chain_ids = config.rest.chains.clone();
+    for chain in config.listen.chains.keys() {
+        if chain != SPEC_ID && !chain_ids.contains(chain) {
+            chain_ids.push(chain.clone());
         }
     }
+    if config.dogfood.enabled && !chain_ids.contains(&config.dogfood.spec_id) {
+        chain_ids.push(config.dogfood.spec_id.clone());
+    }
+    let mut chains = HashMap::new();
+    let mut chain_shutdowns = Vec::new();
+    for chain in &chain_ids {
+        let chain_state = Arc::new(Mutex::new(SDKPairingState::with_connector(Arc::clone(
+            &connector,
+        ))));
+        let (chain_shutdown_tx, chain_shutdown_rx) = mpsc::channel(1);
+        tokio::spawn(sdk_pairing_task(
+            address.clone(),
+            chain.clone(),
+            Arc::clone(&chain_state),
+            Arc::clone(&recorder),
+            Arc::clone(&querier),
+            Arc::clone(&storage),
+            chain_shutdown_rx,
+        ));
+        chains.insert(chain.clone(), chain_state);
+        chain_shutdowns.push(chain_shutdown_tx);
+    }
 
-    //
-    // Spawn the server
-    let context = Arc::new(Mutex::new(ConsumerSessionContext::new(private_key.clone(), state)));
-    let server_context = context.clone();
-    let task = tokio::spawn(async move {
-        if let Err(e) = start_server(server_context).await {
-            eprintln!("Server error: {}", e);
+    let mut context =
+        ConsumerSessionContext::new(private_key.clone(), state, recorder, storage, config)?;
+    context.chains = chains;
+    let context = Arc::new(Mutex::new(context));
+    querier.attach(context.clone());
+
+    let mut shutdowns = vec![shutdown_tx];
+    shutdowns.extend(chain_shutdowns);
+    Ok((context, shutdowns))
+}
+
+async fn run_command(
+    cmd: Command,
+    config: &Config,
+    args: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        Command::ExportProofs { input, output } => {
+            //
+            // Without a proof file the proofs kept in storage are exported
+            let export = match input.or_else(|| config.proofs.path.clone()) {
+                Some(input) => export_proofs(&input)?,
+                None => {
+                    //
+                    // Stored proofs are kept per consumer, the key tells whose to export
+                    let creds =
+                        Creds::from_file(args.creds.as_deref().ok_or("--creds is required")?)?;
+                    let public_key =
+                        signing_key_from_hex(&creds.secret_key)?.verifying_key().to_sec1_bytes();
+                    let address = public_key_to_address(&public_key, LAVA_CHAIN_PREFIX)?;
+                    export_stored_proofs(&*open_storage(&config.storage)?, &address)
+                }
+            };
+            let export = serde_json::to_string_pretty(&export)?;
+            match output {
+                Some(path) => std::fs::write(path, export)?,
+                None => println!("{}", export),
+            }
         }
-    });
-    task.await?;
+        Command::EstimateCu {
+            chain,
+            mut methods,
+            payload,
+            requests_per_second,
+            epoch_duration,
+            max_cu,
+        } => {
+            if let Some(path) = payload {
+                let payload = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                methods.extend(methods_from_payload(&payload));
+            }
+            if methods.is_empty() {
+                return Err("No methods given, pass --methods or --payload".into());
+            }
+            let epoch_duration = match epoch_duration {
+                Some(value) => Some(parse_duration(&value).ok_or("Invalid --epoch-duration")?),
+                None => None,
+            };
+            let spec = fetch_spec(&LavaQuerier::new(config.dogfood.clone()), &chain).await?;
+            let estimate = estimate(&spec, &methods, requests_per_second, epoch_duration, max_cu);
+            println!("{}", serde_json::to_string_pretty(&estimate)?);
+        }
+        Command::Loadtest {
+            url,
+            methods,
+            payload,
+            requests_per_second,
+            duration,
+            chain,
+        } => {
+            let mut mix = mix_from_methods(&methods);
+            if let Some(path) = payload {
+                let payload = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                mix.extend(mix_from_payload(&payload));
+            }
+            if mix.is_empty() {
+                return Err("No requests given, pass --methods or --payload".into());
+            }
+            let duration = parse_duration(&duration).ok_or("Invalid --duration")?;
+            validate_rps(requests_per_second)?;
+
+            let report = match url {
+                Some(url) => {
+                    let querier = LavaQuerier::new(config.dogfood.clone());
+                    let spec = fetch_spec(&querier, &chain).await.unwrap_or_else(|e| {
+                        println!("No spec for {}, pricing requests at the default: {}", chain, e);
+                        ChainSpec::default()
+                    });
+                    let target = Arc::new(Target::remote(&url));
+                    let mut report =
+                        run_loadtest(target, &mix, &spec, requests_per_second, duration).await;
+
+                    // Per-provider numbers come from the consumer's own stats
+                    let diagnostics_url = format!("{}/diagnostics", url.trim_end_matches('/'));
+                    if let Ok(response) = reqwest::get(diagnostics_url).await {
+                        if let Ok(snapshot) = response.json::<Snapshot>().await {
+                            report.providers = snapshot.stats;
+                        }
+                    }
+                    report
+                }
+                None => {
+                    let (context, shutdowns) = start_consumer(args, config.clone()).await?;
+                    //
+                    // The mix is drawn from the spec, and load against no providers at all
+                    // would only measure the fallback, so wait for the first ranking
+                    let pairing_state = context.lock().await.pairing_state.clone();
+                    while get_ranked_providers(Arc::clone(&pairing_state)).await.is_empty() {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    }
+                    let spec = pairing_state.lock().await.spec.clone();
+                    let target = Arc::new(Target::Local(router(context.clone()).await));
+                    let mut report =
+                        run_loadtest(target, &mix, &spec, requests_per_second, duration).await;
+                    let stats = context.lock().await.stats.clone();
+                    report.providers = stats.lock().await.summaries();
+                    for shutdown_tx in shutdowns {
+                        shutdown_tx.send(()).await?;
+                    }
+                    report
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::Diagnostics(DiagnosticsCommand::Dump { url, output }) => {
+            let response = reqwest::get(format!("{}/diagnostics", url.trim_end_matches('/'))).await?;
+            if response.status() != 200 {
+                return Err(format!("Failed to fetch diagnostics: {}", response.status()).into());
+            }
+            let bundle = serde_json::to_string_pretty(&response.json::<Snapshot>().await?)?;
+            match output {
+                Some(path) => std::fs::write(path, bundle)?,
+                None => println!("{}", bundle),
+            }
+        }
+        Command::Diagnostics(DiagnosticsCommand::Import { input }) => {
+            let bundle: Snapshot = serde_json::from_str(&std::fs::read_to_string(&input)?)?;
+            println!(
+                "Loaded {} {} bundle taken at {} from {}",
+                bundle.name, bundle.version, bundle.created_at_s, input
+            );
 
-    // Shutdown the SDK pairing task
-    shutdown_tx.send(()).await?;
+            // Nothing this instance does should leave a trace or reach the network
+            let config = imported_config(&bundle.config)?;
+            let recorder = Arc::new(match args.replay.as_deref() {
+                Some(path) => Recorder::replay(path)?,
+                None => Recorder::offline(),
+            });
 
+            let connector = Arc::new(Connector::default());
+            let mut state = Arc::new(Mutex::new(SDKPairingState::with_connector(Arc::clone(
+                &connector,
+            ))));
+            let mut chains = HashMap::new();
+            for pairing in &bundle.pairings {
+                let restored = Arc::new(Mutex::new(restore_pairing(pairing, Arc::clone(&connector))));
+                if pairing.spec_id == SPEC_ID {
+                    state = restored;
+                } else {
+                    chains.insert(pairing.spec_id.clone(), restored);
+                }
+            }
+
+            //
+            // Nothing of the original consumer's storage is needed, or touched
+            let private_key = SigningKey::random(&mut OsRng);
+            let storage = Arc::new(MemoryStorage::default());
+            let mut context =
+                ConsumerSessionContext::new(private_key, state, recorder, storage, config)?;
+            context.chains = chains;
+            context.diagnostics.imported = Some(bundle);
+            start_server(Arc::new(Mutex::new(context))).await?;
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MiddlewareConfig {
+    // Applied in order to every JSON-RPC call, and in reverse order to its response
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    // Send calls of `from` as `to`, e.g. to map a deprecated method name
+    RewriteMethod { from: String, to: String },
+    // Refuse calls of these methods outright
+    Deny { methods: Vec<String> },
+    // Cut params down to `max_params` and drop these keys from object params
+    SanitizeParams {
+        method: Option<String>,
+        max_params: Option<usize>,
+        #[serde(default)]
+        drop_fields: Vec<String>,
+    },
+    // Remove the values at these JSON pointers from the response, e.g. "/result/from"
+    RedactResponse {
+        method: Option<String>,
+        pointers: Vec<String>,
+    },
+    // Set `value` at the JSON pointer in the response, its parent has to exist
+    InjectField {
+        method: Option<String>,
+        pointer: String,
+        value: Value,
+    },
+}
+
+//
+// A step on the relay path, config rules are one kind, code can plug in others
+pub trait Middleware: Send + Sync {
+    fn on_request(&self, _call: &mut Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_response(&self, _call: &Value, _response: &mut Value) {}
+}
+
+fn applies(method: &Option<String>, call: &Value) -> bool {
+    method
+        .as_ref()
+        .is_none_or(|method| call["method"].as_str() == Some(method.as_str()))
+}
+
+impl Middleware for Rule {
+    fn on_request(&self, call: &mut Value) -> Result<(), String> {
+        match self {
+            Rule::RewriteMethod { from, to } => {
+                if call["method"].as_str() == Some(from.as_str()) {
+                    call["method"] = Value::String(to.clone());
+                }
+            }
+            Rule::Deny { methods } => {
+                if let Some(method) = call["method"].as_str() {
+                    if methods.iter().any(|m| m == method) {
+                        return Err(format!("Method {} is not allowed", method));
+                    }
+                }
+            }
+            Rule::SanitizeParams {
+                method,
+                max_params,
+                drop_fields,
+            } => {
+                if !applies(method, call) {
+                    return Ok(());
+                }
+                if let Some(params) = call["params"].as_array_mut() {
+                    if let Some(max_params) = max_params {
+                        params.truncate(*max_params);
+                    }
+                    for param in params.iter_mut().filter_map(Value::as_object_mut) {
+                        for field in drop_fields {
+                            param.remove(field);
+                        }
+                    }
+                }
+            }
+            Rule::RedactResponse { .. } | Rule::InjectField { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn on_response(&self, call: &Value, response: &mut Value) {
+        match self {
+            Rule::RedactResponse { method, pointers } if applies(method, call) => {
+                for pointer in pointers {
+                    if let Some((parent, key)) = split_pointer(pointer) {
+                        match response.pointer_mut(parent) {
+                            Some(Value::Object(object)) => {
+                                object.remove(key);
+                            }
+                            Some(Value::Array(array)) => {
+                                if let Ok(index) = key.parse::<usize>() {
+                                    if index < array.len() {
+                                        array[index] = Value::Null;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Rule::InjectField {
+                method,
+                pointer,
+                value,
+            } if applies(method, call) => {
+                if let Some((parent, key)) = split_pointer(pointer) {
+                    if let Some(Value::Object(object)) = response.pointer_mut(parent) {
+                        object.insert(key.to_string(), value.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn split_pointer(pointer: &str) -> Option<(&str, &str)> {
+    pointer.rsplit_once('/')
+}
+
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new(config: &MiddlewareConfig) -> Self {
+        Self {
+            layers: config
+                .rules
+                .iter()
+                .map(|rule| Arc::new(rule.clone()) as Arc<dyn Middleware>)
+                .collect(),
+        }
+    }
+
+    pub fn with(mut self, layer: Arc<dyn Middleware>) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    //
+    // Runs every call of a request or batch through the chain, an Err is the reason the
+    // first refusing layer gave
+    pub fn apply_request(&self, request: &mut Value) -> Result<(), String> {
+        for call in calls_mut(request) {
+            for layer in &self.layers {
+                layer.on_request(call)?;
+            }
+        }
+        Ok(())
+    }
+
+    //
+    // `request` is the call as the client sent it, before any rewrite. Batch responses are
+    // matched to their calls by id since nodes may reorder them, a reply no call claims only
+    // gets the rules that aren't scoped to a method
+    pub fn apply_response(&self, request: &Value, response: &mut Value) {
+        let unmatched = Value::Null;
+        for reply in calls_mut(response) {
+            let call = match request.as_array() {
+                Some(batch) => batch
+                    .iter()
+                    .find(|call| call["id"] == reply["id"])
+                    .unwrap_or(&unmatched),
+                None => request,
+            };
+            for layer in self.layers.iter().rev() {
+                layer.on_response(call, reply);
+            }
+        }
+    }
+}
+
+fn calls_mut(value: &mut Value) -> Vec<&mut Value> {
+    match value {
+        Value::Array(batch) => batch.iter_mut().collect(),
+        call => vec![call],
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacingConfig {
+    // Spread each provider's epoch CU allowance evenly over the epoch
+    pub enabled: bool,
+    // Share of the allowance that may be spent at once on top of the steady rate
+    pub burst_fraction: f64,
+    // Longest a relay waits for CU to refill, beyond that it goes to another provider
+    pub max_wait_ms: u64,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            burst_fraction: 0.1,
+            max_wait_ms: 1000,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    // CU per second
+    rate: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketState {
+    pub spec_id: String,
+    pub epoch: i64,
+    pub provider: String,
+    pub tokens: f64,
+    pub capacity: f64,
+    pub rate_cu_per_sec: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PacingMetrics {
+    pub enabled: bool,
+    // Relays let through right away, after waiting, or turned away
+    pub admitted: u64,
+    pub delayed: u64,
+    pub rejected: u64,
+    pub total_delay_ms: u64,
+    pub buckets: Vec<BucketState>,
+}
+
+//
+// Token bucket per (spec, epoch, provider). A relay reserves its CU up front and is told how
+// long to wait until the bucket has refilled enough, so concurrent relays queue up fairly
+#[derive(Default)]
+pub struct CuPacer {
+    config: PacingConfig,
+    buckets: HashMap<(String, i64, String), Bucket>,
+    admitted: u64,
+    delayed: u64,
+    rejected: u64,
+    total_delay: Duration,
+}
+
+impl CuPacer {
+    pub fn new(config: PacingConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    //
+    // Ok with how long to wait before sending, Err when that would be longer than max_wait
+    pub fn reserve(
+        &mut self,
+        (spec_id, epoch, provider): (&str, i64, &str),
+        cu: u64,
+        limit: u64,
+        epoch_duration: Duration,
+        now: Instant,
+    ) -> Result<Duration, Duration> {
+        if !self.config.enabled || limit == 0 || epoch_duration.is_zero() {
+            return Ok(Duration::ZERO);
+        }
+        self.buckets
+            .retain(|(spec, bucket_epoch, _), _| spec != spec_id || *bucket_epoch >= epoch - 1);
+
+        let cu = cu as f64;
+        let capacity = (limit as f64 * self.config.burst_fraction).max(cu);
+        let bucket = self
+            .buckets
+            .entry((spec_id.to_string(), epoch, provider.to_string()))
+            .or_insert(Bucket {
+                tokens: capacity,
+                capacity,
+                rate: limit as f64 / epoch_duration.as_secs_f64(),
+                updated: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.capacity);
+        bucket.updated = now;
+
+        let deficit = cu - bucket.tokens;
+        let wait = if deficit > 0.0 {
+            Duration::from_secs_f64(deficit / bucket.rate)
+        } else {
+            Duration::ZERO
+        };
+        if wait > Duration::from_millis(self.config.max_wait_ms) {
+            self.rejected += 1;
+            return Err(wait);
+        }
+        bucket.tokens -= cu;
+        if wait.is_zero() {
+            self.admitted += 1;
+        } else {
+            self.delayed += 1;
+            self.total_delay += wait;
+        }
+        Ok(wait)
+    }
+
+    pub fn metrics(&self, now: Instant) -> PacingMetrics {
+        let mut buckets = self
+            .buckets
+            .iter()
+            .map(|((spec_id, epoch, provider), bucket)| {
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                BucketState {
+                    spec_id: spec_id.clone(),
+                    epoch: *epoch,
+                    provider: provider.clone(),
+                    tokens: (bucket.tokens + elapsed * bucket.rate).min(bucket.capacity),
+                    capacity: bucket.capacity,
+                    rate_cu_per_sec: bucket.rate,
+                }
+            })
+            .collect::<Vec<_>>();
+        buckets.sort_by(|a, b| {
+            (&a.spec_id, a.epoch, &a.provider).cmp(&(&b.spec_id, b.epoch, &b.provider))
+        });
+        PacingMetrics {
+            enabled: self.config.enabled,
+            admitted: self.admitted,
+            delayed: self.delayed,
+            rejected: self.rejected,
+            total_delay_ms: self.total_delay.as_millis() as u64,
+            buckets,
+        }
+    }
+}
@@ -1,18 +1,30 @@
 use futures::future::join_all;
-use reqwest;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, Mutex, Notify};
 use tokio::time::timeout;
-use tonic::transport::{Channel, Endpoint};
+use tonic::transport::Channel;
 
 use crate::proto::relayer_client::RelayerClient;
+use crate::dogfood::LavaQuerier;
+use crate::emergency::EmergencyTracker;
+use crate::endpoint::{endpoint_url, Connector};
+use crate::geolocation::{geolocation_name, GEOLOCATIONS};
 use crate::proto::ProbeRequest;
+use crate::recorder::Recorder;
+use crate::spec::ChainSpec;
+use crate::storage::{consumer_namespace, Storage};
 
 const MAX_PROVIDERS_TO_TEST: usize = 10;
-const BASE_URL: &str = "https://rest-public-rpc.lavanet.xyz/lavanet/lava/pairing/sdk_pairing";
+const SDK_PAIRING_PATH: &str = "/lavanet/lava/pairing/sdk_pairing";
 const MAX_PROBE_DURATION: Duration = Duration::from_secs(1);
+const OVERDUE_EPOCH_POLL_INTERVAL: u64 = 10;
+const STORED_PAIRING_POLL_INTERVAL: u64 = 10;
+const RESYNC_DEBOUNCE: Duration = Duration::from_secs(5);
+// Last sdk_pairing response per chain, as the gateway sent it
+const PAIRINGS: &str = "pairings";
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SDKPairingParams {
@@ -20,22 +32,33 @@ pub struct SDKPairingParams {
     pub time_left_to_next_pairing: u64,
     pub spec_last_updated_block: u64,
     pub block_of_next_pairing: u64,
+    pub max_cu: u64,
     pub downtime_duration: String,
     pub epoch_duration: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct ProviderEndpoint {
+    pub address: String,
+    pub geolocation: u64,
+    pub extensions: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Provider {
     pub address: String,
     pub stake: u64,
-    pub endpoints: Vec<String>,
+    pub endpoints: Vec<ProviderEndpoint>,
     pub latest_block: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct RankedProvider {
     pub provider: Provider,
+    pub endpoint: ProviderEndpoint,
     pub latency: Duration,
+    // Epoch of the pairing this provider was ranked in, relays to it are signed for that epoch
+    pub epoch: i64,
     client: Arc<Mutex<Option<RelayerClient<Channel>>>>,
 }
 
@@ -43,34 +66,99 @@ pub struct SDKPairingState {
     pub params: SDKPairingParams,
     pub providers: Vec<Provider>,
     pub ranked_providers: Vec<RankedProvider>,
+    // Same ranking restricted to endpoints serving each geolocation bit
+    pub geo_pools: HashMap<u64, Vec<RankedProvider>>,
     pub last_updated: std::time::Instant,
+    pub emergency: EmergencyTracker,
+    pub spec: ChainSpec,
+    // Shared by every chain's pairing, probes and relays dial through it
+    pub connector: Arc<Connector>,
+    // Wakes the pairing task early, e.g. when a provider rejected our epoch
+    pub resync: Arc<Notify>,
+    last_resync: Option<Instant>,
+}
+
+impl Default for SDKPairingState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SDKPairingState {
     pub fn new() -> Self {
+        Self::with_connector(Arc::new(Connector::default()))
+    }
+
+    pub fn with_connector(connector: Arc<Connector>) -> Self {
         Self {
             params: SDKPairingParams::default(),
             providers: Vec::new(),
             ranked_providers: Vec::new(),
+            geo_pools: HashMap::new(),
             last_updated: std::time::Instant::now(),
+            emergency: EmergencyTracker::default(),
+            spec: ChainSpec::default(),
+            connector,
+            resync: Arc::new(Notify::new()),
+            last_resync: None,
+        }
+    }
+
+    //
+    // Wake the pairing task at most once per RESYNC_DEBOUNCE. Every relay in flight on a
+    // stale epoch gets rejected the same way, one refresh covers all of them
+    pub fn request_resync(&mut self, now: Instant) -> bool {
+        if self
+            .last_resync
+            .is_some_and(|last| now.saturating_duration_since(last) < RESYNC_DEBOUNCE)
+        {
+            return false;
+        }
+        self.last_resync = Some(now);
+        self.resync.notify_one();
+        true
+    }
+
+    //
+    // Move the provider behind every other one, in the overall ranking and each geo pool.
+    // The next pairing refresh ranks it from scratch again
+    pub fn demote(&mut self, provider_address: &str) {
+        let pools =
+            std::iter::once(&mut self.ranked_providers).chain(self.geo_pools.values_mut());
+        for pool in pools {
+            let position = pool
+                .iter()
+                .position(|p| p.provider.address == provider_address);
+            if let Some(index) = position {
+                let demoted = pool.remove(index);
+                pool.push(demoted);
+            }
         }
     }
 }
 
 impl RankedProvider {
-    pub async fn get_client(&self) -> Result<RelayerClient<Channel>, Box<dyn std::error::Error>> {
+    pub fn new(provider: Provider, endpoint: ProviderEndpoint, latency: Duration, epoch: i64) -> Self {
+        Self {
+            provider,
+            endpoint,
+            latency,
+            epoch,
+            client: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub async fn get_client(
+        &self,
+        connector: &Connector,
+    ) -> Result<RelayerClient<Channel>, Box<dyn std::error::Error + Send + Sync>> {
         let mut client_guard = self.client.lock().await;
         
         if client_guard.is_none() {
-            if let Some(endpoint) = self.provider.endpoints.first() {
-                let channel = tonic::transport::Channel::from_shared(endpoint.clone())
-                    .unwrap()
-                    .connect()
-                    .await?;
-                *client_guard = Some(RelayerClient::new(channel));
-            } else {
-                return Err("No endpoint available for the provider".into());
-            }
+            let channel = connector
+                .connect(&self.provider.address, &self.endpoint.address)
+                .await?;
+            *client_guard = Some(RelayerClient::new(channel));
         }
         
         Ok((*client_guard).as_ref().unwrap().clone())
@@ -81,10 +169,12 @@ pub async fn sdk_pairing_task(
     address: String,
     chain_id: String,
     state: Arc<Mutex<SDKPairingState>>,
+    recorder: Arc<Recorder>,
+    querier: Arc<LavaQuerier>,
+    storage: Arc<dyn Storage>,
     mut shutdown: mpsc::Receiver<()>,
 ) {
-    let client = reqwest::Client::new();
-
+    let resync = state.lock().await.resync.clone();
     loop {
         let next_pairing = get_sdk_pairing_params(Arc::clone(&state))
             .await
@@ -95,55 +185,164 @@ pub async fn sdk_pairing_task(
                 println!("Shutting down SDK pairing task");
                 break;
             }
-            _ = tokio::time::sleep(Duration::from_secs(next_pairing)) => {
-                if let Err(e) = refresh_state(&client, &address, &chain_id, &state).await {
-                    eprintln!("Error refreshing state: {}", e);
-                }
+            _ = tokio::time::sleep(Duration::from_secs(next_pairing)) => {}
+            _ = resync.notified() => {
+                println!("Resyncing {} pairing", chain_id);
             }
         }
+        let refresh = refresh_state(&querier, &storage, &address, &chain_id, &state, &recorder);
+        if let Err(e) = refresh.await {
+            eprintln!("Error refreshing state: {}", e);
+        }
     }
 }
 
+//
+// The backend may be a network hop away, keep the read off the runtime's workers
+async fn load_pairing(
+    storage: &Arc<dyn Storage>,
+    namespace: String,
+    chain_id: &str,
+) -> Option<serde_json::Value> {
+    let (storage, chain_id) = (Arc::clone(storage), chain_id.to_string());
+    tokio::task::spawn_blocking(move || storage.load(&namespace, &chain_id))
+        .await
+        .unwrap_or_default()
+}
+
 async fn refresh_state(
-    client: &reqwest::Client,
+    querier: &LavaQuerier,
+    storage: &Arc<dyn Storage>,
     address: &str,
     chain_id: &str,
     state: &Arc<Mutex<SDKPairingState>>,
+    recorder: &Arc<Recorder>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     //
     // In case of failure retry in 1 second
     let mut state_guard = state.lock().await;
     state_guard.params.time_left_to_next_pairing = 1;
+    let first_pairing = state_guard.ranked_providers.is_empty();
     drop(state_guard);
 
     //
-    //
-    let url = format!("{}?chainID={}&client={}", BASE_URL, chain_id, address);
-    let response = client.get(&url).send().await?;
-    if response.status() != 200 {
-        return Err(format!("Failed to fetch state: {}", response.status()).into());
-    }
+    // Until a chain has its first pairing the stored one gets it serving, should the
+    // gateway be down while we start
+    let mut stored = false;
+    let namespace = consumer_namespace(PAIRINGS, address);
+    let json = if recorder.is_replaying() {
+        recorder.next_pairing().await?
+    } else {
+        let path = format!("{}?chainID={}&client={}", SDK_PAIRING_PATH, chain_id, address);
+        // Only the message is kept, the error itself can't be held across the stored read
+        let fetched = querier.get(&path).await.map_err(|e| e.to_string());
+        match fetched {
+            Ok(json) => {
+                storage.save(&namespace, chain_id, &json);
+                json
+            }
+            Err(e) if first_pairing => {
+                let Some(json) = load_pairing(storage, namespace, chain_id).await else {
+                    return Err(e.into());
+                };
+                println!("Pairing query for {} failed ({}), using the stored one", chain_id, e);
+                stored = true;
+                json
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    recorder.record_pairing(&json).await;
 
     //
     //
-    let json = response.json::<serde_json::Value>().await?;
-    if let Some(pairing) = json.get("pairing") {
-        let new_params = parse_sdk_pairing_params(&json, pairing);
-        let providers = parse_providers(pairing);
-        let ranked_providers: Vec<RankedProvider> = probe_and_rank_providers(providers.clone()).await;
+    let (mut new_params, providers) =
+        parse_pairing_response(&json).ok_or("No pairing information found")?;
+    if stored {
+        new_params.time_left_to_next_pairing = STORED_PAIRING_POLL_INTERVAL;
+    }
+    let spec = ChainSpec::parse(&json["spec"]);
 
+    //
+    // While the next epoch is overdue the pairing can't change, keep the one we have and
+    // poll at a gentler pace, the chain may be halted
+    {
         let mut state_guard = state.lock().await;
-        state_guard.params = new_params;
-        state_guard.providers = providers;
-        state_guard.last_updated = std::time::Instant::now();
-        state_guard.ranked_providers = ranked_providers;
-    } else {
-        return Err("No pairing information found".into());
+        let now = Instant::now();
+        let same_epoch = new_params.current_epoch == state_guard.params.current_epoch;
+        state_guard.emergency.update(&new_params, now);
+        if !spec.is_empty() {
+            state_guard.spec = spec;
+        }
+        if same_epoch
+            && !state_guard.ranked_providers.is_empty()
+            && !state_guard.emergency.downtime(now).is_zero()
+        {
+            if state_guard.emergency.in_emergency(now) {
+                println!(
+                    "Emergency mode: epoch {} overdue by {:?}, virtual epoch {}",
+                    new_params.current_epoch,
+                    state_guard.emergency.downtime(now),
+                    state_guard.emergency.virtual_epoch(now),
+                );
+            }
+            state_guard.params = SDKPairingParams {
+                time_left_to_next_pairing: OVERDUE_EPOCH_POLL_INTERVAL,
+                ..new_params
+            };
+            return Ok(());
+        }
+    }
+
+    let (api_interface, connector) = {
+        let state_guard = state.lock().await;
+        (
+            state_guard.spec.primary_interface().to_string(),
+            state_guard.connector.clone(),
+        )
+    };
+    let ranked_endpoints = probe_and_rank_providers(
+        providers.clone(),
+        chain_id,
+        &api_interface,
+        new_params.current_epoch,
+        &connector,
+        recorder,
+    )
+    .await;
+    let ranked_providers = best_per_provider(ranked_endpoints.iter());
+    let geo_pools = build_geo_pools(&ranked_endpoints);
+
+    let mut state_guard = state.lock().await;
+    state_guard.params = new_params;
+    state_guard.providers = providers;
+    state_guard.last_updated = std::time::Instant::now();
+    state_guard.ranked_providers = ranked_providers;
+    state_guard.geo_pools = geo_pools;
+
+    if first_pairing && !state_guard.ranked_providers.is_empty() {
+        println!("Top Ranked Providers of {}:", chain_id);
+        for (i, provider) in state_guard.ranked_providers.iter().enumerate() {
+            println!(
+                "{}. Address: {}, Latency: {:?}",
+                i + 1,
+                provider.provider.address,
+                provider.latency,
+            );
+        }
+        println!("Current SDK Pairing Params: {:?}", state_guard.params);
     }
 
     Ok(())
 }
 
+//
+// Parse a raw sdk_pairing response, the body comes straight from the gateway
+pub fn parse_pairing_response(json: &serde_json::Value) -> Option<(SDKPairingParams, Vec<Provider>)> {
+    let pairing = json.get("pairing")?;
+    Some((parse_sdk_pairing_params(json, pairing), parse_providers(pairing)))
+}
+
 fn parse_sdk_pairing_params(
     json: &serde_json::Value,
     pairing: &serde_json::Value,
@@ -169,6 +368,11 @@ fn parse_sdk_pairing_params(
             .unwrap_or("")
             .parse::<u64>()
             .unwrap_or(0),
+        max_cu: json["max_cu"]
+            .as_str()
+            .unwrap_or("")
+            .parse::<u64>()
+            .unwrap_or(0),
         downtime_duration: json["downtime_params"]["downtime_duration"]
             .as_str()
             .unwrap_or("")
@@ -189,7 +393,7 @@ fn parse_providers(pairing: &serde_json::Value) -> Vec<Provider> {
             }
         }
     }
-    providers.sort_by(|a, b| b.stake.cmp(&a.stake));
+    providers.sort_by_key(|p| std::cmp::Reverse(p.stake));
     providers.truncate(MAX_PROVIDERS_TO_TEST);
     providers
 }
@@ -201,7 +405,7 @@ fn parse_provider(provider: &serde_json::Value) -> Option<Provider> {
         endpoints: provider["endpoints"]
             .as_array()?
             .iter()
-            .filter_map(|e| e["iPPORT"].as_str().map(|s| s.to_string()))
+            .filter_map(parse_endpoint)
             .collect(),
         latest_block: provider["block_report"]["latest_block"]
             .as_str()?
@@ -210,13 +414,61 @@ fn parse_provider(provider: &serde_json::Value) -> Option<Provider> {
     })
 }
 
-async fn probe_and_rank_providers(providers: Vec<Provider>) -> Vec<RankedProvider> {
+fn parse_endpoint(endpoint: &serde_json::Value) -> Option<ProviderEndpoint> {
+    let geolocation = &endpoint["geolocation"];
+    let address = endpoint["iPPORT"].as_str()?;
+    if let Err(e) = endpoint_url(address) {
+        println!("Skipping endpoint: {}", e);
+        return None;
+    }
+    Some(ProviderEndpoint {
+        address: address.to_string(),
+        geolocation: geolocation
+            .as_u64()
+            .or_else(|| geolocation.as_str().and_then(|g| g.parse().ok()))
+            .unwrap_or(0),
+        extensions: endpoint["extensions"]
+            .as_array()
+            .map(|extensions| {
+                extensions
+                    .iter()
+                    .filter_map(|e| e.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+//
+// Every endpoint of every provider gets probed, the result is ranked per endpoint
+async fn probe_and_rank_providers(
+    providers: Vec<Provider>,
+    chain_id: &str,
+    api_interface: &str,
+    epoch: i64,
+    connector: &Arc<Connector>,
+    recorder: &Arc<Recorder>,
+) -> Vec<RankedProvider> {
     let mut probe_tasks = Vec::new();
 
     for provider in providers {
-        if let Some(endpoint) = provider.endpoints.first().cloned() {
+        for endpoint in provider.endpoints.clone() {
+            let provider = provider.clone();
+            let recorder = Arc::clone(recorder);
+            let chain_id = chain_id.to_string();
+            let api_interface = api_interface.to_string();
+            let connector = Arc::clone(connector);
             let probe_task = tokio::spawn(async move {
-                let (ranked_provider, is_successful) = probe_provider(provider, endpoint).await;
+                let (ranked_provider, is_successful) = probe_provider(
+                    provider,
+                    endpoint,
+                    epoch,
+                    &chain_id,
+                    &api_interface,
+                    &connector,
+                    &recorder,
+                )
+                .await;
                 if is_successful {
                     Some(ranked_provider)
                 } else {
@@ -240,47 +492,82 @@ async fn probe_and_rank_providers(providers: Vec<Provider>) -> Vec<RankedProvide
         })
         .collect::<Vec<_>>();
 
-    ranked_providers.sort_by(|a, b| a.latency.cmp(&b.latency));
+    ranked_providers.sort_by_key(|p| p.latency);
 
     println!("Finished probing all providers");
     ranked_providers
 }
 
-async fn probe_provider(provider: Provider, mut endpoint: String) -> (RankedProvider, bool) {
+fn best_per_provider<'a>(ranked: impl Iterator<Item = &'a RankedProvider>) -> Vec<RankedProvider> {
+    let mut seen = HashSet::new();
+    ranked
+        .filter(|ranked_provider| seen.insert(ranked_provider.provider.address.clone()))
+        .cloned()
+        .collect()
+}
+
+pub fn build_geo_pools(ranked_endpoints: &[RankedProvider]) -> HashMap<u64, Vec<RankedProvider>> {
+    let mut pools = HashMap::new();
+    for (_, bit) in GEOLOCATIONS {
+        let pool = best_per_provider(
+            ranked_endpoints
+                .iter()
+                .filter(|ranked_provider| ranked_provider.endpoint.geolocation & bit != 0),
+        );
+        if !pool.is_empty() {
+            println!("{} pool: {} providers", geolocation_name(*bit), pool.len());
+            pools.insert(*bit, pool);
+        }
+    }
+    pools
+}
+
+async fn probe_provider(
+    provider: Provider,
+    provider_endpoint: ProviderEndpoint,
+    epoch: i64,
+    chain_id: &str,
+    api_interface: &str,
+    connector: &Connector,
+    recorder: &Recorder,
+) -> (RankedProvider, bool) {
     let start = Instant::now();
-    endpoint = format!("https://{}", endpoint);
+    let endpoint = endpoint_url(&provider_endpoint.address)
+        .unwrap_or_else(|_| format!("https://{}", provider_endpoint.address));
+
+    if recorder.is_replaying() {
+        let (latency, is_successful) = recorder.next_probe(&endpoint).await;
+        return (
+            RankedProvider::new(provider, provider_endpoint, latency, epoch),
+            is_successful,
+        );
+    }
 
     let result = timeout(MAX_PROBE_DURATION, async {
-        match Endpoint::from_shared(endpoint.clone()) {
-            Ok(endpoint) => match endpoint.connect().await {
-                Ok(channel) => {
-                    let mut client = RelayerClient::new(channel);
-                    let request = tonic::Request::new(ProbeRequest {
-                        guid: 0,
-                        spec_id: "ETH1".to_string(),
-                        api_interface: "jsonrpc".to_string(),
-                    });
-                    let probe_result = client.probe(request).await;
-                    (Some(client), probe_result)
-                }
-                Err(e) => {
-                    println!("Connection failed: {}", e);
-                    (
-                        None,
-                        Err(tonic::Status::unavailable(format!(
-                            "Connection failed: {}",
-                            e
-                        ))),
-                    )
-                }
-            },
-            Err(e) => (
-                None,
-                Err(tonic::Status::invalid_argument(format!(
-                    "Invalid endpoint: {}",
-                    e
-                ))),
-            ),
+        match connector
+            .connect(&provider.address, &provider_endpoint.address)
+            .await
+        {
+            Ok(channel) => {
+                let mut client = RelayerClient::new(channel);
+                let request = tonic::Request::new(ProbeRequest {
+                    guid: 0,
+                    spec_id: chain_id.to_string(),
+                    api_interface: api_interface.to_string(),
+                });
+                let probe_result = client.probe(request).await;
+                (Some(client), probe_result)
+            }
+            Err(e) => {
+                println!("Connection failed: {}", e);
+                (
+                    None,
+                    Err(tonic::Status::unavailable(format!(
+                        "Connection failed: {}",
+                        e
+                    ))),
+                )
+            }
         }
     })
     .await;
@@ -317,10 +604,15 @@ async fn probe_provider(provider: Provider, mut endpoint: String) -> (RankedProv
             (None, false)
         }
     };
+    recorder
+        .record_probe(&provider.address, &endpoint, elapsed, is_successful)
+        .await;
     (
         RankedProvider {
             provider,
+            endpoint: provider_endpoint,
             latency: elapsed,
+            epoch,
             client: Arc::new(Mutex::new(client)),
         },
         is_successful,
@@ -336,3 +628,11 @@ pub async fn get_ranked_providers(state: Arc<Mutex<SDKPairingState>>) -> Vec<Ran
     let state = state.lock().await;
     state.ranked_providers.clone()
 }
+
+pub async fn get_geo_ranked_providers(
+    state: Arc<Mutex<SDKPairingState>>,
+    geolocation: u64,
+) -> Vec<RankedProvider> {
+    let state = state.lock().await;
+    state.geo_pools.get(&geolocation).cloned().unwrap_or_default()
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::eth_tx::recover_sender;
+use crate::pairing::RankedProvider;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PinningConfig {
+    // How long a sender stays on the same provider after its last nonce-sensitive call, 0 disables
+    pub window_secs: u64,
+}
+
+impl Default for PinningConfig {
+    fn default() -> Self {
+        Self { window_secs: 120 }
+    }
+}
+
+//
+// Nonce-sensitive calls are keyed by sender so pending nonces and the mempool the
+// transaction lands in come from the same node
+pub fn pin_key(request: &Value) -> Option<String> {
+    match request["method"].as_str()? {
+        "eth_sendRawTransaction" => {
+            let raw = request["params"][0].as_str()?.strip_prefix("0x")?;
+            recover_sender(&hex::decode(raw).ok()?)
+        }
+        "eth_getTransactionCount" if request["params"][1].as_str() == Some("pending") => {
+            Some(request["params"][0].as_str()?.to_lowercase())
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct ProviderPins {
+    pins: HashMap<String, (String, Instant)>,
+}
+
+impl ProviderPins {
+    pub fn select(
+        &mut self,
+        key: &str,
+        providers: &[RankedProvider],
+        window: Duration,
+    ) -> Option<RankedProvider> {
+        let now = Instant::now();
+        self.pins.retain(|_, (_, expires)| *expires > now);
+
+        // A pinned provider that dropped out of the pairing is replaced by the current top one
+        let provider = self
+            .pins
+            .get(key)
+            .and_then(|(address, _)| providers.iter().find(|p| &p.provider.address == address))
+            .or_else(|| providers.first())?;
+        self.pins.insert(
+            key.to_string(),
+            (provider.provider.address.clone(), now + window),
+        );
+        Some(provider.clone())
+    }
+}
@@ -0,0 +1,130 @@
+use axum::http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+const OUTCOME_WINDOW: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Interactive,
+    Batch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    pub header: String,
+    // API key -> priority class, unknown or missing keys get the default
+    pub keys: HashMap<String, Priority>,
+    pub default: Priority,
+    // Pressure kicks in above this relay error rate or this share of the CU limit
+    pub error_rate_threshold: f64,
+    pub cu_pressure_threshold: f64,
+    // Batch relays allowed in flight while under pressure, the rest wait in the queue
+    pub batch_concurrency: usize,
+    pub batch_queue_limit: usize,
+    pub batch_queue_timeout_ms: u64,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-api-key".to_string(),
+            keys: HashMap::new(),
+            default: Priority::Interactive,
+            error_rate_threshold: 0.25,
+            cu_pressure_threshold: 0.8,
+            batch_concurrency: 4,
+            batch_queue_limit: 100,
+            batch_queue_timeout_ms: 2000,
+        }
+    }
+}
+
+impl PriorityConfig {
+    pub fn resolve(&self, headers: &HeaderMap) -> Priority {
+        headers
+            .get(self.header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|key| self.keys.get(key))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+//
+// Interactive traffic is always admitted, batch traffic is throttled through a small
+// semaphore once providers degrade or the CU budget runs low, and shed if it can't get in
+pub struct PriorityGate {
+    config: PriorityConfig,
+    batch_slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+    outcomes: Mutex<VecDeque<bool>>,
+}
+
+impl PriorityGate {
+    pub fn new(config: PriorityConfig) -> Self {
+        Self {
+            batch_slots: Arc::new(Semaphore::new(config.batch_concurrency.max(1))),
+            queued: AtomicUsize::new(0),
+            outcomes: Mutex::new(VecDeque::new()),
+            config,
+        }
+    }
+
+    pub async fn record(&self, success: bool) {
+        let mut outcomes = self.outcomes.lock().await;
+        outcomes.push_back(success);
+        if outcomes.len() > OUTCOME_WINDOW {
+            outcomes.pop_front();
+        }
+    }
+
+    pub async fn error_rate(&self) -> f64 {
+        let outcomes = self.outcomes.lock().await;
+        if outcomes.is_empty() {
+            return 0.0;
+        }
+        outcomes.iter().filter(|ok| !**ok).count() as f64 / outcomes.len() as f64
+    }
+
+    pub async fn under_pressure(&self, cu_usage: Option<f64>) -> bool {
+        cu_usage.is_some_and(|usage| usage >= self.config.cu_pressure_threshold)
+            || self.error_rate().await >= self.config.error_rate_threshold
+    }
+
+    pub async fn admit(
+        &self,
+        priority: Priority,
+        cu_usage: Option<f64>,
+    ) -> Result<Option<OwnedSemaphorePermit>, StatusCode> {
+        if priority == Priority::Interactive || !self.under_pressure(cu_usage).await {
+            return Ok(None);
+        }
+
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.config.batch_queue_limit {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            println!("Shedding batch request, queue is full");
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        let permit = tokio::time::timeout(
+            Duration::from_millis(self.config.batch_queue_timeout_ms),
+            self.batch_slots.clone().acquire_owned(),
+        )
+        .await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        match permit {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            _ => {
+                println!("Shedding batch request, no slot freed up in time");
+                Err(StatusCode::SERVICE_UNAVAILABLE)
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::proto::RelaySession;
+use crate::storage::{consumer_namespace, Storage};
+
+// Latest proof per (provider, epoch, session), what export needs without the file
+const PROOFS: &str = "proofs";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProofsConfig {
+    // Append every signed relay session served by a provider to this file
+    pub path: Option<String>,
+    // Epochs of proofs kept in storage, older ones are past their payment window
+    pub keep_epochs: i64,
+}
+
+impl Default for ProofsConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            keep_epochs: 20,
+        }
+    }
+}
+
+//
+// The signed session as the provider received it, enough to rebuild a MsgRelayPayment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayProof {
+    pub provider: String,
+    pub spec_id: String,
+    pub epoch: i64,
+    pub session_id: u64,
+    pub relay_num: u64,
+    pub cu_sum: u64,
+    pub sig: String,
+    // base64 of the protobuf encoded RelaySession
+    pub relay_session: String,
+}
+
+impl RelayProof {
+    pub fn from_session(session: &RelaySession) -> Self {
+        Self {
+            provider: session.provider.clone(),
+            spec_id: session.spec_id.clone(),
+            epoch: session.epoch,
+            session_id: session.session_id,
+            relay_num: session.relay_num,
+            cu_sum: session.cu_sum,
+            sig: hex::encode(&session.sig),
+            relay_session: STANDARD.encode(session.encode_to_vec()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderPayout {
+    pub provider: String,
+    pub epoch: i64,
+    pub sessions: usize,
+    pub relays: u64,
+    pub cu: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofExport {
+    pub payouts: Vec<ProviderPayout>,
+    pub proofs: Vec<RelayProof>,
+}
+
+pub struct ProofLog {
+    file: Option<Mutex<File>>,
+    storage: Arc<dyn Storage>,
+    namespace: String,
+    keep_epochs: i64,
+    // Newest epoch recorded, storage is pruned whenever it moves forward
+    latest_epoch: AtomicI64,
+}
+
+impl ProofLog {
+    pub fn new(
+        config: &ProofsConfig,
+        storage: Arc<dyn Storage>,
+        consumer: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match &config.path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            file,
+            storage,
+            namespace: consumer_namespace(PROOFS, consumer),
+            keep_epochs: config.keep_epochs,
+            latest_epoch: AtomicI64::new(i64::MIN),
+        })
+    }
+
+    pub async fn record(&self, session: &RelaySession) {
+        let proof = RelayProof::from_session(session);
+        let key = format!("{}/{}/{}", proof.provider, proof.epoch, proof.session_id);
+        self.storage.save(&self.namespace, &key, &proof);
+        if self.latest_epoch.fetch_max(proof.epoch, Ordering::Relaxed) < proof.epoch {
+            self.prune(proof.epoch);
+        }
+
+        if let Some(file) = &self.file {
+            let line = match serde_json::to_string(&proof) {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to serialize relay proof: {}", e);
+                    return;
+                }
+            };
+            let mut file = file.lock().await;
+            if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+                eprintln!("Failed to write relay proof: {}", e);
+            }
+        }
+    }
+
+    //
+    // Drops stored proofs more than keep_epochs behind `epoch`. The first record after a
+    // restart prunes too, so proofs of epochs the consumer slept through don't pile up
+    fn prune(&self, epoch: i64) {
+        let oldest = epoch.saturating_sub(self.keep_epochs);
+        let (storage, namespace) = (Arc::clone(&self.storage), self.namespace.clone());
+        tokio::task::spawn_blocking(move || {
+            let stale: Vec<String> = match storage.scan(&namespace) {
+                Ok(entries) => entries
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .filter(|key| proof_epoch(key).is_some_and(|e| e < oldest))
+                    .collect(),
+                Err(e) => {
+                    println!("Storage: failed to read {}: {}", namespace, e);
+                    return;
+                }
+            };
+            if !stale.is_empty() {
+                println!(
+                    "Proofs: dropping {} stored proofs older than epoch {}",
+                    stale.len(),
+                    oldest
+                );
+            }
+            for key in stale {
+                storage.remove(&namespace, &key);
+            }
+        });
+    }
+}
+
+// Keys are provider/epoch/session
+fn proof_epoch(key: &str) -> Option<i64> {
+    key.split('/').nth(1)?.parse().ok()
+}
+
+pub fn export_proofs(path: &str) -> Result<ProofExport, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut proofs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        proofs.push(serde_json::from_str(&line)?);
+    }
+    Ok(export(proofs))
+}
+
+//
+// Same export from the proofs `consumer` kept in storage, for consumers running without a
+// proof file
+pub fn export_stored_proofs(storage: &dyn Storage, consumer: &str) -> ProofExport {
+    export(
+        storage
+            .load_all::<RelayProof>(&consumer_namespace(PROOFS, consumer))
+            .into_iter()
+            .map(|(_, proof)| proof)
+            .collect(),
+    )
+}
+
+//
+// cu_sum and relay_num are cumulative per session, so a provider only ever claims
+// the last signed relay of each (provider, epoch, session)
+fn export(proofs: Vec<RelayProof>) -> ProofExport {
+    let mut latest: BTreeMap<(String, i64, u64), RelayProof> = BTreeMap::new();
+    for proof in proofs {
+        let key = (proof.provider.clone(), proof.epoch, proof.session_id);
+        match latest.get(&key) {
+            Some(existing) if existing.relay_num >= proof.relay_num => {}
+            _ => {
+                latest.insert(key, proof);
+            }
+        }
+    }
+
+    let mut payouts: BTreeMap<(String, i64), ProviderPayout> = BTreeMap::new();
+    for proof in latest.values() {
+        let payout = payouts
+            .entry((proof.provider.clone(), proof.epoch))
+            .or_insert_with(|| ProviderPayout {
+                provider: proof.provider.clone(),
+                epoch: proof.epoch,
+                sessions: 0,
+                relays: 0,
+                cu: 0,
+            });
+        payout.sessions += 1;
+        payout.relays += proof.relay_num;
+        payout.cu += proof.cu_sum;
+    }
+
+    ProofExport {
+        payouts: payouts.into_values().collect(),
+        proofs: latest.into_values().collect(),
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+
+// Lava provider error names and messages, matched case-insensitively
+const EPOCH_ERRORS: &[&str] = &[
+    "invalidepoch",
+    "requested epoch",
+    "epoch mismatch",
+    "epochisnotregistered",
+    "epoch is not registered",
+];
+const SESSION_ERRORS: &[&str] = &[
+    "sessionoutofsync",
+    "session went out of sync",
+    "relaynumbermismatch",
+    "relay number mismatch",
+    "providerconsumercumismatch",
+    "cu mismatch",
+];
+const OVERLOAD_ERRORS: &[&str] = &["too many requests", "rate limit", "overloaded"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderError {
+    // Provider and us disagree on the epoch, our pairing is stale or theirs is
+    EpochMismatch,
+    // Session bookkeeping (relay number, CU sum) diverged from the provider's
+    SessionOutOfSync,
+    // Provider asked us to slow down
+    Overloaded,
+    // Connection or timeout level failure
+    Unavailable,
+    Other,
+}
+
+pub fn classify(status: &Status) -> ProviderError {
+    let message = status.message().to_lowercase();
+    let contains_any = |needles: &[&str]| needles.iter().any(|n| message.contains(n));
+    if contains_any(EPOCH_ERRORS) {
+        ProviderError::EpochMismatch
+    } else if contains_any(SESSION_ERRORS) {
+        ProviderError::SessionOutOfSync
+    } else if status.code() == Code::ResourceExhausted || contains_any(OVERLOAD_ERRORS) {
+        ProviderError::Overloaded
+    } else if matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded) {
+        ProviderError::Unavailable
+    } else {
+        ProviderError::Other
+    }
+}
+
+//
+// Retry-after the provider sent along with the rejection, in seconds
+pub fn retry_after(status: &Status) -> Option<Duration> {
+    let value = status.metadata().get("retry-after")?.to_str().ok()?;
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackoffConfig {
+    // First backoff of an overloaded provider, doubled on every rejection in a row
+    pub base_secs: u64,
+    pub max_secs: u64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            base_secs: 5,
+            max_secs: 120,
+        }
+    }
+}
+
+struct Backoff {
+    until: Instant,
+    strikes: u32,
+}
+
+#[derive(Default)]
+pub struct ProviderBackoff {
+    config: BackoffConfig,
+    providers: HashMap<String, Backoff>,
+}
+
+impl ProviderBackoff {
+    pub fn new(config: BackoffConfig) -> Self {
+        Self {
+            config,
+            providers: HashMap::new(),
+        }
+    }
+
+    pub fn back_off(&mut self, provider: &str, requested: Option<Duration>, now: Instant) -> Duration {
+        let strikes = self
+            .providers
+            .get(provider)
+            .map_or(0, |backoff| backoff.strikes)
+            .saturating_add(1);
+        let exponential = self
+            .config
+            .base_secs
+            .saturating_mul(1 << (strikes - 1).min(16))
+            .min(self.config.max_secs);
+        // A provider's retry-after can stretch the backoff up to max_secs, not beyond
+        let max = Duration::from_secs(self.config.max_secs);
+        let duration = requested
+            .unwrap_or(Duration::ZERO)
+            .min(max)
+            .max(Duration::from_secs(exponential));
+        let until = now
+            .checked_add(duration)
+            .unwrap_or_else(|| now + Duration::from_secs(BackoffConfig::default().max_secs));
+        self.providers.insert(provider.to_string(), Backoff { until, strikes });
+        duration
+    }
+
+    pub fn is_backed_off(&self, provider: &str, now: Instant) -> bool {
+        self.providers
+            .get(provider)
+            .is_some_and(|backoff| backoff.until > now)
+    }
+
+    //
+    // A success after the backoff ran out forgets the provider's strikes
+    pub fn record_success(&mut self, provider: &str) {
+        self.providers.remove(provider);
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const MAX_SAMPLES: usize = 200;
+const SAMPLE_MAX_AGE: Duration = Duration::from_secs(60);
+
+struct Sample {
+    at: Instant,
+    success: bool,
+    latency: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSummary {
+    pub address: String,
+    pub requests: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    // Times the provider disagreed with the majority in quorum reads
+    pub dissents: u64,
+}
+
+//
+// Recent relay outcomes per provider, only the last minute counts so recovery shows up quickly
+#[derive(Default)]
+pub struct ProviderStats {
+    samples: HashMap<String, VecDeque<Sample>>,
+    dissents: HashMap<String, u64>,
+}
+
+impl ProviderStats {
+    pub fn record(&mut self, provider_address: &str, success: bool, latency: Duration) {
+        let samples = self.samples.entry(provider_address.to_string()).or_default();
+        samples.push_back(Sample {
+            at: Instant::now(),
+            success,
+            latency,
+        });
+        if samples.len() > MAX_SAMPLES {
+            samples.pop_front();
+        }
+    }
+
+    pub fn record_dissent(&mut self, provider_address: &str) {
+        *self.dissents.entry(provider_address.to_string()).or_default() += 1;
+    }
+
+    pub fn summaries(&self) -> Vec<ProviderSummary> {
+        let now = Instant::now();
+        let mut summaries = self
+            .samples
+            .iter()
+            .filter_map(|(address, samples)| {
+                let recent = samples
+                    .iter()
+                    .filter(|s| now.saturating_duration_since(s.at) < SAMPLE_MAX_AGE)
+                    .collect::<Vec<_>>();
+                if recent.is_empty() {
+                    return None;
+                }
+                let errors = recent.iter().filter(|s| !s.success).count();
+                let mut latencies = recent.iter().map(|s| s.latency).collect::<Vec<_>>();
+                latencies.sort();
+                Some(ProviderSummary {
+                    address: address.clone(),
+                    requests: recent.len(),
+                    errors,
+                    error_rate: errors as f64 / recent.len() as f64,
+                    p50_latency_ms: percentile(&latencies, 0.50).as_millis() as u64,
+                    p95_latency_ms: percentile(&latencies, 0.95).as_millis() as u64,
+                    dissents: self.dissents.get(address).copied().unwrap_or(0),
+                })
+            })
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| a.address.cmp(&b.address));
+        summaries
+    }
+}
+
+pub fn percentile(sorted: &[Duration], quantile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * quantile).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
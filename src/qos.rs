@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::pairing::SDKPairingState;
+use crate::provider_stats::ProviderSummary;
+use crate::session_context::ConsumerSessionContext;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QosConfig {
+    // Demote the top provider mid-epoch as soon as it breaches a threshold, instead of
+    // keeping it on top until the next pairing refresh ranks it again. Opt-in
+    pub enabled: bool,
+    pub max_error_rate: f64,
+    pub max_p95_latency_ms: u64,
+    // Blocks behind the most synced provider of the pairing, as of the last probe
+    pub max_sync_lag: u64,
+    // Error rate and latency are only judged once the provider has this many recent relays
+    pub min_samples: usize,
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_error_rate: 0.3,
+            max_p95_latency_ms: 3000,
+            max_sync_lag: 20,
+            min_samples: 10,
+        }
+    }
+}
+
+impl QosConfig {
+    pub fn breach(&self, summary: Option<&ProviderSummary>, sync_lag: u64) -> Option<String> {
+        if sync_lag > self.max_sync_lag {
+            return Some(format!("{} blocks behind", sync_lag));
+        }
+        let summary = summary.filter(|s| s.requests >= self.min_samples)?;
+        if summary.error_rate > self.max_error_rate {
+            Some(format!("error rate {:.2}", summary.error_rate))
+        } else if summary.p95_latency_ms > self.max_p95_latency_ms {
+            Some(format!("p95 latency {}ms", summary.p95_latency_ms))
+        } else {
+            None
+        }
+    }
+}
+
+//
+// Called after every relay, only the top provider of the chain is judged
+pub async fn demote_on_breach(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    provider_address: &str,
+) {
+    let (config, stats) = {
+        let context = context.lock().await;
+        (context.config.qos.clone(), context.stats.clone())
+    };
+    if !config.enabled {
+        return;
+    }
+
+    let mut state = pairing_state.lock().await;
+    let is_top = state
+        .ranked_providers
+        .first()
+        .is_some_and(|top| top.provider.address == provider_address);
+    if !is_top || state.ranked_providers.len() < 2 {
+        return;
+    }
+    let synced = state.providers.iter().map(|p| p.latest_block).max().unwrap_or(0);
+    let latest_block = state
+        .providers
+        .iter()
+        .find(|p| p.address == provider_address)
+        .map_or(synced, |p| p.latest_block);
+    let summary = stats
+        .lock()
+        .await
+        .summaries()
+        .into_iter()
+        .find(|s| s.address == provider_address);
+
+    if let Some(reason) = config.breach(summary.as_ref(), synced.saturating_sub(latest_block)) {
+        state.demote(provider_address);
+        println!(
+            "QoS: demoting {} ({}), {} is now top",
+            provider_address, reason, state.ranked_providers[0].provider.address
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use axum::http::StatusCode;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::pairing::RankedProvider;
+use crate::relay::relay;
+use crate::session_context::ConsumerSessionContext;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuorumConfig {
+    // Method -> number of providers asked, e.g. {"eth_getBalance": 3}
+    pub methods: HashMap<String, usize>,
+}
+
+impl QuorumConfig {
+    pub fn quorum_size(&self, request: &Value) -> Option<usize> {
+        let size = *self.methods.get(request["method"].as_str()?)?;
+        (size > 1).then_some(size)
+    }
+}
+
+//
+// Ask the top `size` providers and answer with the reply a strict majority agrees on,
+// compared on the result/error only since ids and formatting differ between nodes
+pub async fn relay_quorum(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    providers: &[RankedProvider],
+    request: &Value,
+    payload: &[u8],
+    size: usize,
+) -> Result<Vec<u8>, StatusCode> {
+    //
+    // Fewer providers than asked for can't outvote a lying one, refuse instead of
+    // quietly lowering the bar
+    if providers.len() < size {
+        println!(
+            "Quorum of {} requested but only {} providers available",
+            size,
+            providers.len()
+        );
+        return Ok(quorum_error(request, "Not enough providers for a quorum"));
+    }
+    let selected = &providers[..size];
+
+    let replies = join_all(
+        selected
+            .iter()
+            .map(|provider| relay(context, provider, payload.to_vec())),
+    )
+    .await;
+
+    let mut votes: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+    let mut answers: Vec<Option<Vec<u8>>> = Vec::new();
+    for (index, reply) in replies.into_iter().enumerate() {
+        let answer = reply.ok().and_then(|data| {
+            let response: Value = serde_json::from_slice(&data).ok()?;
+            let outcome = response.get("result").or_else(|| response.get("error"))?;
+            votes
+                .entry(Sha256::digest(outcome.to_string().as_bytes()).to_vec())
+                .or_default()
+                .push(index);
+            Some(data)
+        });
+        answers.push(answer);
+    }
+
+    let majority = votes
+        .values()
+        .max_by_key(|voters| voters.len())
+        .filter(|voters| voters.len() * 2 > selected.len())
+        .cloned();
+
+    let stats = context.lock().await.stats.clone();
+    let mut stats = stats.lock().await;
+    match majority {
+        Some(voters) => {
+            for (index, provider) in selected.iter().enumerate() {
+                if !voters.contains(&index) {
+                    println!(
+                        "Quorum: provider {} dissented on {}",
+                        provider.provider.address, request["method"]
+                    );
+                    stats.record_dissent(&provider.provider.address);
+                }
+            }
+            answers[voters[0]].take().ok_or(StatusCode::BAD_GATEWAY)
+        }
+        None => {
+            println!(
+                "Quorum: no majority among {} providers for {}",
+                selected.len(),
+                request["method"]
+            );
+            Ok(quorum_error(request, "Providers did not reach a quorum"))
+        }
+    }
+}
+
+fn quorum_error(request: &Value, message: &str) -> Vec<u8> {
+    json!({
+        "jsonrpc": "2.0",
+        "id": request["id"],
+        "error": {"code": -32603, "message": message},
+    })
+    .to_string()
+    .into_bytes()
+}
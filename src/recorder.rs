@@ -0,0 +1,253 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+use crate::proto::{RelayReply, RelayRequest};
+
+//
+// One line of a recording file, everything the consumer got from the outside world.
+// Timestamps are u64, serde can't read a u128 back through the internally tagged enum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    Pairing {
+        timestamp_ms: u64,
+        response: serde_json::Value,
+    },
+    Probe {
+        timestamp_ms: u64,
+        provider: String,
+        endpoint: String,
+        latency_ms: u64,
+        success: bool,
+    },
+    Relay {
+        timestamp_ms: u64,
+        provider: String,
+        content_hash: String,
+        // base64 of the protobuf encoded RelayRequest / RelayReply
+        request: String,
+        reply: Option<String>,
+        error_code: Option<i32>,
+        error_message: Option<String>,
+    },
+}
+
+#[derive(Default)]
+struct ReplayLog {
+    pairings: VecDeque<serde_json::Value>,
+    probes: HashMap<String, VecDeque<(Duration, bool)>>,
+    relays: HashMap<String, VecDeque<Result<RelayReply, tonic::Status>>>,
+}
+
+enum Mode {
+    Live,
+    Record(Mutex<File>),
+    Replay(Mutex<ReplayLog>),
+}
+
+pub struct Recorder {
+    mode: Mode,
+}
+
+impl Recorder {
+    pub fn live() -> Self {
+        Self { mode: Mode::Live }
+    }
+
+    pub fn record(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        println!("Recording traffic to {}", path);
+        Ok(Self {
+            mode: Mode::Record(Mutex::new(file)),
+        })
+    }
+
+    pub fn replay(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut log = ReplayLog::default();
+        let reader = BufReader::new(File::open(path)?);
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(&line)? {
+                Record::Pairing { response, .. } => log.pairings.push_back(response),
+                Record::Probe {
+                    endpoint,
+                    latency_ms,
+                    success,
+                    ..
+                } => log
+                    .probes
+                    .entry(endpoint)
+                    .or_default()
+                    .push_back((Duration::from_millis(latency_ms), success)),
+                Record::Relay {
+                    content_hash,
+                    reply,
+                    error_code,
+                    error_message,
+                    ..
+                } => {
+                    let result = match reply {
+                        Some(reply) => Ok(RelayReply::decode(STANDARD.decode(reply)?.as_slice())?),
+                        None => Err(tonic::Status::new(
+                            tonic::Code::from(error_code.unwrap_or(tonic::Code::Unknown as i32)),
+                            error_message.unwrap_or_default(),
+                        )),
+                    };
+                    log.relays.entry(content_hash).or_default().push_back(result);
+                }
+            }
+        }
+        println!(
+            "Replaying {} pairing responses, {} probed endpoints and {} relayed requests from {}",
+            log.pairings.len(),
+            log.probes.len(),
+            log.relays.values().map(|r| r.len()).sum::<usize>(),
+            path
+        );
+        Ok(Self {
+            mode: Mode::Replay(Mutex::new(log)),
+        })
+    }
+
+    //
+    // Replay of an empty recording, nothing reaches the network and every relay fails
+    pub fn offline() -> Self {
+        Self {
+            mode: Mode::Replay(Mutex::new(ReplayLog::default())),
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.mode, Mode::Replay(_))
+    }
+
+    pub async fn record_pairing(&self, response: &serde_json::Value) {
+        self.write(Record::Pairing {
+            timestamp_ms: now_ms(),
+            response: response.clone(),
+        })
+        .await;
+    }
+
+    pub async fn record_probe(&self, provider: &str, endpoint: &str, latency: Duration, success: bool) {
+        self.write(Record::Probe {
+            timestamp_ms: now_ms(),
+            provider: provider.to_string(),
+            endpoint: endpoint.to_string(),
+            latency_ms: latency.as_millis() as u64,
+            success,
+        })
+        .await;
+    }
+
+    pub async fn record_relay(
+        &self,
+        provider: &str,
+        request: &RelayRequest,
+        result: &Result<RelayReply, tonic::Status>,
+    ) {
+        if !matches!(self.mode, Mode::Record(_)) {
+            return;
+        }
+        let (reply, error_code, error_message) = match result {
+            Ok(reply) => (Some(STANDARD.encode(reply.encode_to_vec())), None, None),
+            Err(status) => (None, Some(status.code() as i32), Some(status.message().to_string())),
+        };
+        self.write(Record::Relay {
+            timestamp_ms: now_ms(),
+            provider: provider.to_string(),
+            content_hash: relay_key(request),
+            request: STANDARD.encode(request.encode_to_vec()),
+            reply,
+            error_code,
+            error_message,
+        })
+        .await;
+    }
+
+    pub async fn next_pairing(&self) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        match &self.mode {
+            Mode::Replay(log) => log
+                .lock()
+                .await
+                .pairings
+                .pop_front()
+                .ok_or_else(|| "Replay log has no pairing responses left".into()),
+            _ => Err("Not replaying".into()),
+        }
+    }
+
+    //
+    // Endpoints that were never probed in the recording count as failed probes
+    pub async fn next_probe(&self, endpoint: &str) -> (Duration, bool) {
+        if let Mode::Replay(log) = &self.mode {
+            if let Some(probe) = log
+                .lock()
+                .await
+                .probes
+                .get_mut(endpoint)
+                .and_then(|probes| probes.pop_front())
+            {
+                return probe;
+            }
+        }
+        (Duration::ZERO, false)
+    }
+
+    pub async fn next_relay(&self, request: &RelayRequest) -> Result<RelayReply, tonic::Status> {
+        if let Mode::Replay(log) = &self.mode {
+            if let Some(result) = log
+                .lock()
+                .await
+                .relays
+                .get_mut(&relay_key(request))
+                .and_then(|relays| relays.pop_front())
+            {
+                return result;
+            }
+        }
+        Err(tonic::Status::not_found("No recorded reply for this relay"))
+    }
+
+    async fn write(&self, record: Record) {
+        if let Mode::Record(file) = &self.mode {
+            let line = match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to serialize record: {}", e);
+                    return;
+                }
+            };
+            let mut file = file.lock().await;
+            if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+                eprintln!("Failed to write record: {}", e);
+            }
+        }
+    }
+}
+
+//
+// Sessions and signatures differ between runs, the content hash of the private data does not
+fn relay_key(request: &RelayRequest) -> String {
+    request
+        .relay_session
+        .as_ref()
+        .map(|session| hex::encode(&session.content_hash))
+        .unwrap_or_default()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
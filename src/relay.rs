@@ -0,0 +1,460 @@
+use axum::http::StatusCode;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tonic::{Request, Status, Streaming};
+
+use crate::crypto::sign_data;
+use crate::error_policy::Action;
+use crate::pairing::{RankedProvider, SDKPairingState};
+use crate::proofs::ProofLog;
+use crate::provider_errors::{classify, retry_after, ProviderError};
+use crate::qos::demote_on_breach;
+use crate::proto::{RelayPrivateData, RelayReply, RelayRequest, RelaySession};
+use crate::recorder::Recorder;
+use crate::relay_session::{generate_content_hash, serialize_relay_session};
+use crate::session_context::ConsumerSessionContext;
+use crate::utils::{parse_duration, JSONRPC_INTERFACE, LAVA_CHAIN_ID, REST_INTERFACE, SPEC_ID};
+
+struct PreparedRelay {
+    request: RelayRequest,
+    epoch: i64,
+    recorder: Arc<Recorder>,
+    proofs: Arc<ProofLog>,
+}
+
+//
+// Sign and send a single relay to the given provider, returning the raw reply data
+pub async fn relay(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    payload: Vec<u8>,
+) -> Result<Vec<u8>, StatusCode> {
+    relay_with_extensions(context, provider, payload, vec![]).await
+}
+
+pub async fn relay_with_extensions(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    payload: Vec<u8>,
+    extensions: Vec<String>,
+) -> Result<Vec<u8>, StatusCode> {
+    let pairing_state = context.lock().await.pairing_state.clone();
+    send_relay(
+        context,
+        &pairing_state,
+        SPEC_ID,
+        provider,
+        jsonrpc_relay_data(payload, extensions),
+        true,
+    )
+    .await
+    .map_err(|(status, _)| status)
+}
+
+//
+// A relay whose outcome stays out of the provider stats, the error policy and QoS, for
+// shadow traffic. It's still a real relay, the session and its CU move on as usual
+pub async fn relay_untracked(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    payload: Vec<u8>,
+) -> Result<Vec<u8>, StatusCode> {
+    let pairing_state = context.lock().await.pairing_state.clone();
+    let relay_data = jsonrpc_relay_data(payload, vec![]);
+    send_relay(context, &pairing_state, SPEC_ID, provider, relay_data, false)
+        .await
+        .map_err(|(status, _)| status)
+}
+
+//
+// Relay a Cosmos REST call, `api_url` is the path and query string as the node serves it
+pub async fn relay_rest(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    spec_id: &str,
+    provider: &RankedProvider,
+    method: &str,
+    api_url: String,
+    body: Vec<u8>,
+) -> Result<Vec<u8>, StatusCode> {
+    let relay_data = rest_relay_data(method, api_url, body);
+    send_relay(context, pairing_state, spec_id, provider, relay_data, true)
+        .await
+        .map_err(|(status, _)| status)
+}
+
+//
+// Relay along the ranked providers for up to `max_attempts` sends. After each failure the
+// error policy decides whether the same provider gets another go or the next one takes over.
+// Returns the reply together with the provider that gave it
+pub async fn relay_with_failover(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    spec_id: &str,
+    providers: &[RankedProvider],
+    relay_data: RelayPrivateData,
+    max_attempts: usize,
+) -> Result<(Vec<u8>, RankedProvider), StatusCode> {
+    let mut last_status = StatusCode::SERVICE_UNAVAILABLE;
+    let mut index = 0;
+    for attempt in 1..=max_attempts {
+        let Some(provider) = providers.get(index) else {
+            break;
+        };
+        let relay_data = relay_data.clone();
+        match send_relay(context, pairing_state, spec_id, provider, relay_data, true).await {
+            Ok(data) => return Ok((data, provider.clone())),
+            Err((status, action)) => {
+                last_status = status;
+                if action != Action::RetrySame {
+                    index += 1;
+                }
+                if attempt < max_attempts {
+                    println!(
+                        "Relay to {} failed, {:?} (attempt {} of {})",
+                        provider.provider.address, action, attempt, max_attempts
+                    );
+                }
+            }
+        }
+    }
+    Err(last_status)
+}
+
+async fn send_relay(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    spec_id: &str,
+    provider: &RankedProvider,
+    relay_data: RelayPrivateData,
+    tracked: bool,
+) -> Result<Vec<u8>, (StatusCode, Action)> {
+    let provider_address = &provider.provider.address;
+    let epoch = provider.epoch;
+    let (chaos, stats) = {
+        let context = context.lock().await;
+        (context.chaos.clone(), context.stats.clone())
+    };
+
+    //
+    // Injected faults fail the relay before the session is charged or anything is signed,
+    // and they never reach the chain through reports or durable state
+    let start = Instant::now();
+    let injected = chaos.inject_failure(epoch).await;
+    let is_injected = injected.is_some();
+    let (result, elapsed) = match injected {
+        Some(status) => (Err(status), start.elapsed()),
+        None => {
+            let PreparedRelay {
+                request: relay_request,
+                epoch: _,
+                recorder,
+                proofs,
+            } = prepare_relay(context, pairing_state, spec_id, provider, relay_data)
+                .await
+                .map_err(|status| (status, Action::Failover))?;
+            let connector = pairing_state.lock().await.connector.clone();
+
+            let start = Instant::now();
+            let result = if recorder.is_replaying() {
+                recorder.next_relay(&relay_request).await
+            } else {
+                // Get the client from the top provider, failing to connect counts as unavailable
+                match provider.get_client(&connector).await {
+                    Ok(mut client) => client
+                        .relay(Request::new(relay_request.clone()))
+                        .await
+                        .map(|response| response.into_inner()),
+                    Err(e) => {
+                        println!("Failed to get client: {:?}", e);
+                        Err(Status::unavailable(e.to_string()))
+                    }
+                }
+            };
+            recorder
+                .record_relay(provider_address, &relay_request, &result)
+                .await;
+            if result.is_ok() && !recorder.is_replaying() {
+                if let Some(relay_session) = &relay_request.relay_session {
+                    proofs.record(relay_session).await;
+                }
+            }
+            (chaos.corrupt(result).await, start.elapsed())
+        }
+    };
+    if !tracked {
+        return result
+            .map(|response| response.data)
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Action::Failover));
+    }
+    stats
+        .lock()
+        .await
+        .record(provider_address, result.is_ok(), elapsed);
+    demote_on_breach(context, pairing_state, provider_address).await;
+
+    match result {
+        Ok(response) => {
+            let mut context = context.lock().await;
+            context.backoff.record_success(provider_address);
+            context.error_policy.record_success(provider_address);
+            Ok(response.data)
+        }
+        Err(status) => {
+            println!("Failed to relay request: {:?}", status);
+            let action = handle_provider_error(
+                context,
+                pairing_state,
+                spec_id,
+                epoch,
+                provider,
+                &status,
+                is_injected,
+            )
+            .await;
+            Err((StatusCode::INTERNAL_SERVER_ERROR, action))
+        }
+    }
+}
+
+//
+// Some rejections tell us what's wrong, act on those instead of only failing the relay,
+// then let the error policy pick what happens to the request. An `injected` error only
+// exercises the local reaction, the session and the pairing are fine
+async fn handle_provider_error(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    spec_id: &str,
+    epoch: i64,
+    provider: &RankedProvider,
+    status: &Status,
+    injected: bool,
+) -> Action {
+    let provider_address = &provider.provider.address;
+    let error = classify(status);
+    match error {
+        ProviderError::EpochMismatch if injected => {}
+        ProviderError::EpochMismatch => {
+            context
+                .lock()
+                .await
+                .reset_session(spec_id, epoch, provider_address);
+            if pairing_state.lock().await.request_resync(Instant::now()) {
+                println!(
+                    "Epoch {} rejected by {}, resyncing pairing",
+                    epoch, provider_address
+                );
+            }
+        }
+        ProviderError::SessionOutOfSync => {
+            println!("Session with {} out of sync, recreating it", provider_address);
+            context
+                .lock()
+                .await
+                .reset_session(spec_id, epoch, provider_address);
+        }
+        ProviderError::Overloaded => {
+            let duration = context.lock().await.backoff.back_off(
+                provider_address,
+                retry_after(status),
+                Instant::now(),
+            );
+            println!("{} is overloaded, backing off for {:?}", provider_address, duration);
+        }
+        ProviderError::Unavailable | ProviderError::Other => {}
+    }
+    let mut context = context.lock().await;
+    context
+        .diagnostics
+        .record_error(spec_id, provider_address, status.message());
+    context
+        .error_policy
+        .decide(provider_address, error, injected, Instant::now())
+}
+
+//
+// Open a subscription on the provider, the first reply answers the subscribe request
+// itself and every following one is a notification
+pub async fn relay_subscribe(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    payload: Vec<u8>,
+) -> Result<Streaming<RelayReply>, StatusCode> {
+    let pairing_state = context.lock().await.pairing_state.clone();
+    let prepared = prepare_relay(
+        context,
+        &pairing_state,
+        SPEC_ID,
+        provider,
+        jsonrpc_relay_data(payload, vec![]),
+    )
+    .await?;
+    let connector = pairing_state.lock().await.connector.clone();
+    let mut client = provider.get_client(&connector).await.map_err(|e| {
+        println!("Failed to get client: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let stream = match client
+        .relay_subscribe(Request::new(prepared.request.clone()))
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            println!("Failed to subscribe: {:?}", status);
+            handle_provider_error(
+                context,
+                &pairing_state,
+                SPEC_ID,
+                prepared.epoch,
+                provider,
+                &status,
+                false,
+            )
+            .await;
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if let Some(relay_session) = &prepared.request.relay_session {
+        prepared.proofs.record(relay_session).await;
+    }
+    Ok(stream)
+}
+
+pub fn jsonrpc_relay_data(payload: Vec<u8>, extensions: Vec<String>) -> RelayPrivateData {
+    RelayPrivateData {
+        connection_type: "POST".to_string(),
+        api_url: "".to_string(),
+        data: payload,
+        request_block: -1,
+        api_interface: JSONRPC_INTERFACE.to_string(),
+        salt: vec![],
+        metadata: vec![],
+        addon: "".to_string(),
+        extensions,
+        seen_block: 0i64,
+    }
+}
+
+pub fn rest_relay_data(method: &str, api_url: String, body: Vec<u8>) -> RelayPrivateData {
+    RelayPrivateData {
+        connection_type: method.to_string(),
+        api_url,
+        data: body,
+        request_block: -1,
+        api_interface: REST_INTERFACE.to_string(),
+        salt: vec![],
+        metadata: vec![],
+        addon: "".to_string(),
+        extensions: vec![],
+        seen_block: 0i64,
+    }
+}
+
+async fn prepare_relay(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    pairing_state: &Arc<Mutex<SDKPairingState>>,
+    spec_id: &str,
+    provider: &RankedProvider,
+    relay_data: RelayPrivateData,
+) -> Result<PreparedRelay, StatusCode> {
+    let provider_address = provider.provider.address.clone();
+    let epoch = provider.epoch;
+    let (cu_limit, relay_cu, epoch_duration) = {
+        let state = pairing_state.lock().await;
+        (
+            state
+                .emergency
+                .cu_limit(state.params.max_cu, Instant::now()),
+            state.spec.relay_data_cu(&relay_data),
+            parse_duration(&state.params.epoch_duration).unwrap_or_default(),
+        )
+    };
+    let (private_key, unresponsive_providers, recorder, proofs) = {
+        let mut context = context.lock().await;
+        (
+            context.private_key.clone(),
+            context
+                .error_policy
+                .unresponsive_providers(&provider_address, Instant::now()),
+            context.recorder.clone(),
+            context.proofs.clone(),
+        )
+    };
+    println!("epoch: {:?}", epoch);
+
+    //
+    // Paced CU is reserved before the session is charged, the wait holds no lock
+    if let Some(limit) = cu_limit {
+        let reservation = context.lock().await.pacer.reserve(
+            (spec_id, epoch, &provider_address),
+            relay_cu,
+            limit,
+            epoch_duration,
+            Instant::now(),
+        );
+        match reservation {
+            Ok(wait) if !wait.is_zero() => tokio::time::sleep(wait).await,
+            Ok(_) => {}
+            Err(wait) => {
+                println!(
+                    "CU pacing: {} needs {:?} to refill, skipping it",
+                    provider_address, wait
+                );
+                return Err(StatusCode::TOO_MANY_REQUESTS);
+            }
+        }
+    }
+
+    //
+    let session = {
+        let mut context = context.lock().await;
+        let session = context
+            .get_or_create_session(spec_id, epoch, &provider_address)
+            .clone();
+        if let Some(limit) = cu_limit {
+            if session.cu_sum + relay_cu > limit {
+                println!("CU limit of {} reached for {}", limit, provider_address);
+                return Err(StatusCode::TOO_MANY_REQUESTS);
+            }
+        }
+        context.update_session(spec_id, epoch, &provider_address, relay_cu);
+        session
+    };
+
+    //
+    let content_hash = generate_content_hash(&relay_data);
+    let relay_session = RelaySession {
+        spec_id: spec_id.to_string(),
+        content_hash,
+        session_id: session.session_id,
+        cu_sum: session.cu_sum,
+        provider: provider_address,
+        relay_num: session.relay_num,
+        qos_report: None,
+        epoch,
+        unresponsive_providers,
+        lava_chain_id: LAVA_CHAIN_ID.to_string(),
+        sig: vec![],
+        badge: None,
+        qos_excellence_report: None,
+    };
+    let serialized_relay_session = serialize_relay_session(&relay_session);
+    let signature = sign_data(&serialized_relay_session, &private_key).map_err(|e| {
+        println!("Failed to sign data: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(PreparedRelay {
+        request: RelayRequest {
+            relay_session: Some(RelaySession {
+                sig: signature,
+                ..relay_session
+            }),
+            relay_data: Some(relay_data),
+        },
+        epoch,
+        recorder,
+        proofs,
+    })
+}
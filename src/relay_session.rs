@@ -7,20 +7,21 @@ pub fn serialize_relay_session(request: &RelaySession) -> Vec<u8> {
     let request_vec = request_to_vec(request);
 
     for (key, value) in request_vec {
-        serialized_request.push_str(&serialize_key_value(&key, &value));
+        serialized_request.push_str(&serialize_key_value(key, &value));
     }
 
     serialized_request.as_bytes().to_vec()
 }
 
 fn request_to_vec(request: &RelaySession) -> Vec<(&'static str, Value)> {
-    let mut vec = Vec::new();
-    vec.push(("spec_id", Value::String(request.spec_id.clone())));
-    vec.push(("content_hash", Value::Bytes(request.content_hash.clone())));
-    vec.push(("session_id", Value::Number(request.session_id as i64)));
-    vec.push(("cu_sum", Value::Number(request.cu_sum as i64)));
-    vec.push(("provider", Value::String(request.provider.clone())));
-    vec.push(("relay_num", Value::Number(request.relay_num as i64)));
+    let mut vec = vec![
+        ("spec_id", Value::String(request.spec_id.clone())),
+        ("content_hash", Value::Bytes(request.content_hash.clone())),
+        ("session_id", Value::Number(request.session_id as i64)),
+        ("cu_sum", Value::Number(request.cu_sum as i64)),
+        ("provider", Value::String(request.provider.clone())),
+        ("relay_num", Value::Number(request.relay_num as i64)),
+    ];
     if let Some(qos_report) = &request.qos_report {
         vec.push(("qos_report", Value::QoSReport(qos_report.clone())));
     }
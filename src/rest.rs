@@ -0,0 +1,134 @@
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::pairing::get_ranked_providers;
+use crate::relay::{relay_with_failover, rest_relay_data};
+use crate::session_context::ConsumerSessionContext;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestConfig {
+    // Cosmos REST specs to pair with and serve, each under /{spec}/..., e.g. ["LAV1", "COS5"]
+    pub chains: Vec<String>,
+}
+
+//
+// Incoming REST calls keep their method, path and query, only the /{spec} prefix is stripped
+pub async fn handle_rest(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    let (spec_id, api_url) = match split_path(&uri) {
+        Some(split) => split,
+        None => return rest_error(StatusCode::NOT_FOUND, 5, "Not found"),
+    };
+    relay_rest_call(&context, &spec_id, method, api_url, body).await
+}
+
+//
+// Relay one REST call of `spec_id`, whether it came in under /{spec} or on the chain's own port
+pub async fn relay_rest_call(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    spec_id: &str,
+    method: Method,
+    api_url: String,
+    body: Bytes,
+) -> Response {
+    if method != Method::GET && method != Method::POST {
+        return rest_error(StatusCode::METHOD_NOT_ALLOWED, 12, "Method not allowed");
+    }
+
+    let pairing_state = match context.lock().await.chain_pairing_state(spec_id) {
+        Some(pairing_state) => pairing_state,
+        None => return rest_error(StatusCode::NOT_FOUND, 5, "Unknown chain"),
+    };
+    let providers = get_ranked_providers(pairing_state.clone()).await;
+    let providers = context.lock().await.without_backed_off(providers);
+
+    //
+    // Same retries and failovers as interactive JSON-RPC requests, and the same fallback
+    let result = if providers.is_empty() {
+        println!("No providers paired for {}", spec_id);
+        Err(StatusCode::SERVICE_UNAVAILABLE)
+    } else {
+        let max_attempts = context.lock().await.error_policy.max_attempts();
+        relay_with_failover(
+            context,
+            &pairing_state,
+            spec_id,
+            &providers,
+            rest_relay_data(method.as_str(), api_url.clone(), body.to_vec()),
+            max_attempts,
+        )
+        .await
+        .map(|(data, _)| data)
+    };
+    let fallback = context.lock().await.fallback.clone();
+    match fallback.relay_rest(spec_id, result, &method, &api_url, &body).await {
+        Ok(served) => served.respond(rest_response),
+        Err(_) if providers.is_empty() => {
+            rest_error(StatusCode::SERVICE_UNAVAILABLE, 14, "No providers available")
+        }
+        Err(status) => {
+            println!("REST relay of {} failed: {}", api_url, status);
+            rest_error(StatusCode::BAD_GATEWAY, 14, "Relay to providers failed")
+        }
+    }
+}
+
+fn split_path(uri: &Uri) -> Option<(String, String)> {
+    let path = uri.path().strip_prefix('/')?;
+    let (spec_id, rest) = path.split_once('/')?;
+    let api_url = match uri.query() {
+        Some(query) => format!("/{}?{}", rest, query),
+        None => format!("/{}", rest),
+    };
+    Some((spec_id.to_string(), api_url))
+}
+
+//
+// Nodes answer failed queries with a gRPC-gateway body like {"code": 5, "message": ...},
+// which has to reach the client with a matching HTTP status rather than a 200
+fn rest_response(data: Vec<u8>) -> Response {
+    let status = serde_json::from_slice::<Value>(&data)
+        .ok()
+        .and_then(|body| body["code"].as_u64())
+        .filter(|code| *code != 0)
+        .map_or(StatusCode::OK, grpc_code_to_http);
+    (status, [(header::CONTENT_TYPE, "application/json")], data).into_response()
+}
+
+fn rest_error(status: StatusCode, code: u64, message: &str) -> Response {
+    let body = json!({
+        "code": code,
+        "message": message,
+        "details": [],
+    });
+    (status, axum::Json(body)).into_response()
+}
+
+// Same mapping grpc-gateway uses
+fn grpc_code_to_http(code: u64) -> StatusCode {
+    match code {
+        1 => StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST),
+        3 | 9 | 11 => StatusCode::BAD_REQUEST,
+        4 => StatusCode::GATEWAY_TIMEOUT,
+        5 => StatusCode::NOT_FOUND,
+        6 => StatusCode::CONFLICT,
+        7 => StatusCode::FORBIDDEN,
+        8 => StatusCode::TOO_MANY_REQUESTS,
+        10 => StatusCode::CONFLICT,
+        12 => StatusCode::NOT_IMPLEMENTED,
+        14 => StatusCode::SERVICE_UNAVAILABLE,
+        16 => StatusCode::UNAUTHORIZED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -1,119 +1,492 @@
 use axum::{
-    body::Bytes, extract::State, http::StatusCode, response::IntoResponse, routing::post, Router,
+    body::Bytes,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{any, get, post},
+    Json, Router,
 };
+use std::future::IntoFuture;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tonic::Request;
-use crate::utils::{LAVA_CHAIN_ID, SPEC_ID, JSONRPC_INTERFACE};
+use std::time::Duration;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinSet;
+use crate::archive::{archive_providers, ARCHIVE_EXTENSION};
+use crate::cache::{cacheable_result, cached_response, Lookup};
+use crate::chaos::ChaosConfig;
+use crate::diagnostics::{snapshot, Snapshot};
+use crate::estimate::{estimate, methods_from_payload, CuEstimate, EstimateRequest};
+use crate::fee_oracle::{fee_recommendation, FeeRecommendation};
+use crate::get_logs::{is_oversized, relay_get_logs};
+use crate::handover::{bind, shutdown_signal};
+use crate::listeners::chain_router;
+use crate::pacing::PacingMetrics;
+use crate::pinning::pin_key;
+use crate::priority::Priority;
+use crate::quorum::relay_quorum;
+use crate::relay::{jsonrpc_relay_data, relay_with_extensions, relay_with_failover};
+use crate::rest::handle_rest;
 use crate::session_context::ConsumerSessionContext;
-use crate::crypto::sign_data;
-use crate::proto::{RelayPrivateData, RelayRequest, RelaySession};
-use crate::relay_session::{generate_content_hash, serialize_relay_session};
+use crate::shadow::mirror;
+use crate::subscriptions::handle_ws;
+use crate::utils::{parse_duration, SPEC_ID};
 
 pub async fn start_server(
     context: Arc<Mutex<ConsumerSessionContext>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let app = Router::new()
-        .route("/", post(handle_query))
-        .with_state((context,));
+    let (listen, handover, drain) = {
+        let context = context.lock().await;
+        (
+            context.config.listen.clone(),
+            context.config.handover.clone(),
+            context.drain.clone(),
+        )
+    };
 
-    let addr = "127.0.0.1:3000";
-    println!("Listening on {}", addr);
+    //
+    // The main port, plus one per chain that has its own
+    let mut apps = vec![(listen.port, router(context.clone()).await)];
+    let mut chain_ports = listen.chains.into_iter().collect::<Vec<_>>();
+    chain_ports.sort_by_key(|(_, port)| *port);
+    for (spec_id, port) in chain_ports {
+        println!("Serving {} on port {}", spec_id, port);
+        let app = if spec_id == SPEC_ID {
+            router(context.clone()).await
+        } else {
+            chain_router(context.clone(), spec_id)
+        };
+        apps.push((port, app));
+    }
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let (draining_tx, draining_rx) = watch::channel(false);
+    let mut servers = JoinSet::new();
+    for (port, app) in apps {
+        let addr = SocketAddr::new(listen.host.parse()?, port);
+        let listener = bind(addr, &handover)?;
+        println!("Listening on {}", addr);
+        let mut draining = draining_rx.clone();
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        let serve = axum::serve(listener, app).with_graceful_shutdown(async move {
+            let _ = draining.changed().await;
+        });
+        servers.spawn(serve.into_future());
+    }
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        Some(result) = servers.join_next() => {
+            result??;
+            return Ok(());
+        }
+    }
 
+    //
+    // The listeners are closed, open requests and WS subscriptions get to finish here while
+    // new connections go to the process that took over the ports
+    println!("Draining open connections");
+    let _ = draining_tx.send(true);
+    let timeout = Duration::from_secs(handover.drain_timeout_secs);
+    let drained = async {
+        while let Some(result) = servers.join_next().await {
+            if let Ok(Err(e)) = result {
+                println!("Server error while draining: {}", e);
+            }
+        }
+        drain.wait_idle().await;
+    };
+    if tokio::time::timeout(timeout, drained).await.is_err() {
+        println!("Drain timeout after {:?}, dropping remaining connections", timeout);
+    }
     Ok(())
 }
 
+pub async fn router(context: Arc<Mutex<ConsumerSessionContext>>) -> Router {
+    let (chaos_admin, rest_chains) = {
+        let context = context.lock().await;
+        (
+            context.chaos.admin_enabled().await,
+            context.config.rest.chains.clone(),
+        )
+    };
+    let mut app = Router::new()
+        .route("/", post(handle_query))
+        .route("/ws", get(handle_ws))
+        .route("/estimate", post(handle_estimate))
+        .route("/fees", get(handle_fees))
+        .route(
+            "/diagnostics",
+            get(handle_diagnostics).route_layer(middleware::from_fn(local_only)),
+        )
+        .route("/pacing", get(handle_pacing));
+    if chaos_admin {
+        println!("Chaos admin endpoint enabled on /admin/chaos, for local clients only");
+        app = app.route(
+            "/admin/chaos",
+            get(get_chaos)
+                .post(set_chaos)
+                .route_layer(middleware::from_fn(local_only)),
+        );
+    }
+    for chain in rest_chains {
+        println!("Serving {} REST on /{}/", chain, chain);
+        app = app.route(&format!("/{}/*path", chain), any(handle_rest));
+    }
+    app.with_state((context,))
+}
+
 async fn handle_query(
     State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    headers: HeaderMap,
+    payload: Bytes,
+) -> Result<Response, StatusCode> {
+    serve_query(&context, SPEC_ID, &headers, payload).await
+}
+
+//
+// The JSON-RPC path of a spec, whether it's served on / of the main port or on a port of
+// its own
+pub async fn serve_query(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    spec_id: &str,
+    headers: &HeaderMap,
     payload: Bytes,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<Response, StatusCode> {
     //
-    let (top_provider, provider_address, private_key, epoch) = {
-        let mut context = context.lock().await;
+    // When most providers are failing, reject part of the traffic up front instead of
+    // letting every request wait for its timeout. Batch traffic goes before interactive
+    let (middleware, fallback) = {
+        let context = context.lock().await;
+        let summaries = context.stats.lock().await.summaries();
+        let priority = context.config.priority.resolve(headers);
+        if context.config.load_shedding.should_shed(&summaries, priority) {
+            return Ok(shed_response());
+        }
+        (context.middleware.clone(), context.fallback.clone())
+    };
 
-        let mut epoch: i64 = 0;
-        {
-            let state = context.pairing_state.lock().await;
-            epoch = state.params.current_epoch;
+    //
+    // Operator rules see the request before routing and the response before the client
+    let original = match serde_json::from_slice::<serde_json::Value>(&payload) {
+        Ok(request) if !middleware.is_empty() => request,
+        _ => {
+            let result = route_query(context, spec_id, headers, payload.clone()).await;
+            return fallback
+                .relay(spec_id, result, &payload)
+                .await
+                .map(IntoResponse::into_response);
         }
-        let top_provider = context.get_top_provider().await.ok_or_else(|| {
-            println!("No top provider found");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    };
+    let mut request = original.clone();
+    if let Err(reason) = middleware.apply_request(&mut request) {
+        println!("Request rejected by middleware: {}", reason);
+        return Ok(Json(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": original["id"],
+            "error": {"code": -32601, "message": reason},
+        }))
+        .into_response());
+    }
+    let payload = Bytes::from(request.to_string());
+    let result = route_query(context, spec_id, headers, payload.clone()).await;
+    let served = fallback.relay(spec_id, result, &payload).await?;
+    let served = served.map(|data| match serde_json::from_slice::<serde_json::Value>(&data) {
+        Ok(mut response) => {
+            middleware.apply_response(&original, &mut response);
+            response.to_string().into_bytes()
+        }
+        Err(_) => data,
+    });
+    Ok(served.into_response())
+}
 
-        (
-            top_provider.clone(),
-            top_provider.provider.address.clone(),
-            context.private_key.clone(),
-            epoch,
-        )      
+//
+// Queries pinned to a block hash are answered from the cache once any provider answered them
+async fn route_query(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    spec_id: &str,
+    headers: &HeaderMap,
+    payload: Bytes,
+) -> Result<Vec<u8>, StatusCode> {
+    let cache = context.lock().await.cache.clone();
+    let cached = serde_json::from_slice::<serde_json::Value>(&payload)
+        .ok()
+        .and_then(|request| Some((cache.key(spec_id, &request)?, request)));
+    let Some((key, request)) = cached else {
+        return relay_query(context, spec_id, headers, payload).await;
+    };
+    let expected = match cache.lookup(&key).await {
+        Lookup::Hit(result) => return Ok(cached_response(&request, &result)),
+        Lookup::Validate(result) => Some(result),
+        Lookup::Miss => None,
     };
-    println!("epoch: {:?}", epoch);
 
-    //
-    let session = {
+    let response = relay_query(context, spec_id, headers, payload).await?;
+    if let (Some(expected), Some(fresh)) = (expected, cacheable_result(&response)) {
+        if expected != fresh {
+            println!(
+                "Cache: {} entry didn't match a fresh relay, replacing it",
+                request["method"]
+            );
+        }
+    }
+    cache.store(key, &response);
+    Ok(response)
+}
+
+//
+// Pick the providers and the relay strategy for a JSON-RPC request or batch. getLogs
+// splitting, quorum reads, shadowing and the archive retry relay on the main spec only
+async fn relay_query(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    spec_id: &str,
+    headers: &HeaderMap,
+    payload: Bytes,
+) -> Result<Vec<u8>, StatusCode> {
+    let (
+        providers,
+        get_logs_config,
+        archive_config,
+        quorum_config,
+        shadow_config,
+        priority,
+        gate,
+    ) = {
         let mut context = context.lock().await;
-        let session = context.get_or_create_session(&provider_address).clone();
-        context.update_session(&provider_address);
-        session
+        let geolocation = context.config.geolocation.resolve(headers);
+        (
+            context.chain_ranked_providers(spec_id, geolocation).await,
+            context.config.get_logs.clone(),
+            context.config.archive.clone(),
+            context.config.quorum.clone(),
+            context.config.shadow.clone(),
+            context.config.priority.resolve(headers),
+            context.priority.clone(),
+        )
     };
-    
-    // Get the client from the top provider
-    let mut client = top_provider.get_client().await.map_err(|e| {
-        println!("Failed to get client: {:?}", e);
+    let mut top_provider = providers.first().cloned().ok_or_else(|| {
+        println!("No top provider found");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
     //
-    let relay_data = RelayPrivateData {
-        connection_type: "POST".to_string(),
-        api_url: "".to_string(),
-        data: payload.to_vec(),
-        request_block: -1,
-        api_interface: JSONRPC_INTERFACE.to_string(),
-        salt: vec![],
-        metadata: vec![],
-        addon: "".to_string(),
-        extensions: vec![],
-        seen_block: 0i64,
+    // Under pressure batch traffic waits for a slot or gets shed
+    let cu_usage = context
+        .lock()
+        .await
+        .cu_usage(spec_id, &top_provider)
+        .await;
+    let _permit = gate.admit(priority, cu_usage).await?;
+    let mut is_pinned = false;
+    let is_main_spec = spec_id == SPEC_ID;
+
+    if let Ok(request) = serde_json::from_slice::<serde_json::Value>(&payload) {
+        //
+        // Oversized eth_getLogs ranges get split across the ranked providers
+        if is_main_spec && is_oversized(&request, &get_logs_config) {
+            return relay_get_logs(context, &providers, &request, &get_logs_config).await;
+        }
+
+        //
+        // Critical reads can be configured to need agreement between several providers
+        if let Some(size) = quorum_config.quorum_size(&request).filter(|_| is_main_spec) {
+            return relay_quorum(context, &providers, &request, &payload, size).await;
+        }
+
+        //
+        // Nonce-sensitive calls of the same sender stick to one provider, failing over to
+        // another would break the nonce ordering the pin is there for
+        if let Some(key) = pin_key(&request) {
+            let key = format!("{}:{}", spec_id, key);
+            let mut context = context.lock().await;
+            let window = Duration::from_secs(context.config.pinning.window_secs);
+            if !window.is_zero() {
+                if let Some(pinned) = context.pins.select(&key, &providers, window) {
+                    top_provider = pinned;
+                    is_pinned = true;
+                }
+            }
+        }
+    }
+
+    //
+    // Interactive requests get retries and failovers as the error policy decides, batch
+    // requests a single attempt
+    let (pairing_state, max_attempts) = {
+        let context = context.lock().await;
+        let max_attempts = match priority {
+            Priority::Interactive => context.error_policy.max_attempts(),
+            Priority::Batch => 1,
+        };
+        let pairing_state = context
+            .chain_pairing_state(spec_id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        (pairing_state, max_attempts)
     };
-    let content_hash = generate_content_hash(&relay_data);
-    let relay_session = RelaySession {
-        spec_id: SPEC_ID.to_string(),
-        content_hash,
-        session_id: session.session_id,
-        cu_sum: session.cu_sum,
-        provider: provider_address, // This is already a String
-        relay_num: session.relay_num,
-        qos_report: None,
-        epoch,
-        unresponsive_providers: vec![],
-        lava_chain_id: LAVA_CHAIN_ID.to_string(),
-        sig: vec![],
-        badge: None,
-        qos_excellence_report: None,
+    let candidates = if is_pinned {
+        vec![top_provider.clone()]
+    } else {
+        std::iter::once(top_provider.clone())
+            .chain(
+                providers
+                    .iter()
+                    .filter(|p| p.provider.address != top_provider.provider.address)
+                    .cloned(),
+            )
+            .collect::<Vec<_>>()
     };
-    let serialized_relay_session = serialize_relay_session(&relay_session);
-    let signature = sign_data(&serialized_relay_session, &private_key).map_err(|e| {
-        println!("Failed to sign data: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let result = relay_with_failover(
+        context,
+        &pairing_state,
+        spec_id,
+        &candidates,
+        jsonrpc_relay_data(payload.to_vec(), vec![]),
+        max_attempts,
+    )
+    .await;
+    gate.record(result.is_ok()).await;
+    let (response, top_provider) = result?;
+
+    //
+    // A share of what succeeded is mirrored to the provider under evaluation
+    if is_main_spec && shadow_config.should_mirror() {
+        mirror(
+            context,
+            &shadow_config,
+            &providers,
+            &top_provider,
+            payload.to_vec(),
+            &response,
+        );
+    }
 
-    let relay_request = Request::new(RelayRequest {
-        relay_session: Some(RelaySession {
-            sig: signature,
-            ..relay_session
-        }),
-        relay_data: Some(relay_data),
+    //
+    // A pruned node can't answer historical queries, ask an archive node instead
+    if is_main_spec && archive_config.retry && archive_config.is_pruned_state_error(&response) {
+        match archive_providers(&providers).first() {
+            Some(archive_provider) => {
+                println!(
+                    "Pruned state error from {}, retrying on archive provider {}",
+                    top_provider.provider.address, archive_provider.provider.address
+                );
+                return relay_with_extensions(
+                    context,
+                    archive_provider,
+                    payload.to_vec(),
+                    vec![ARCHIVE_EXTENSION.to_string()],
+                )
+                .await;
+            }
+            None => println!("Pruned state error but no archive provider in the pairing"),
+        }
+    }
+
+    Ok(response)
+}
+
+fn shed_response() -> Response {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {
+            "code": -32005,
+            "message": "Request shed: providers are degraded, retry later",
+        },
     });
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [("retry-after", "5")],
+        Json(body),
+    )
+        .into_response()
+}
 
-    let response: tonic::Response<crate::proto::RelayReply> = client.relay(relay_request).await.map_err(|e| {
-        println!("Failed to relay request: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    let response_data = response.into_inner().data;
-    Ok(response_data)
-}
\ No newline at end of file
+//
+// CU cost of a request mix under the loaded spec, projected at the given rate
+async fn handle_estimate(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    Json(request): Json<EstimateRequest>,
+) -> Result<Json<CuEstimate>, StatusCode> {
+    let mut methods = request.methods;
+    if let Some(payload) = &request.payload {
+        methods.extend(methods_from_payload(payload));
+    }
+    if methods.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let pairing_state = context.lock().await.pairing_state.clone();
+    let state = pairing_state.lock().await;
+    Ok(Json(estimate(
+        &state.spec,
+        &methods,
+        request.requests_per_second.unwrap_or(1.0),
+        parse_duration(&state.params.epoch_duration),
+        Some(state.params.max_cu).filter(|max_cu| *max_cu > 0),
+    )))
+}
+
+async fn handle_fees(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    headers: HeaderMap,
+) -> Result<Json<FeeRecommendation>, StatusCode> {
+    let (providers, config) = {
+        let mut context = context.lock().await;
+        let geolocation = context.config.geolocation.resolve(&headers);
+        (
+            context.get_ranked_providers(geolocation).await,
+            context.config.fee_oracle.clone(),
+        )
+    };
+    fee_recommendation(&context, &providers, &config)
+        .await
+        .map(Json)
+}
+
+//
+// Sanitized support snapshot, `consumer diagnostics dump` saves it to a file
+async fn handle_diagnostics(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+) -> Json<Snapshot> {
+    Json(snapshot(&context).await)
+}
+
+async fn handle_pacing(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+) -> Json<PacingMetrics> {
+    Json(context.lock().await.pacer.metrics(std::time::Instant::now()))
+}
+
+//
+// Admin routes only answer connections from this machine, whatever host we listen on
+async fn local_only(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let local = match peer.ip() {
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(ip.is_loopback(), |ip| ip.is_loopback()),
+        ip => ip.is_loopback(),
+    };
+    if !local {
+        println!("Refusing admin request from {}", peer);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(request).await)
+}
+
+async fn get_chaos(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+) -> Json<ChaosConfig> {
+    let chaos = context.lock().await.chaos.clone();
+    Json(chaos.config().await)
+}
+
+async fn set_chaos(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    Json(config): Json<ChaosConfig>,
+) -> Json<ChaosConfig> {
+    let chaos = context.lock().await.chaos.clone();
+    chaos.update(config).await;
+    Json(chaos.config().await)
+}
@@ -1,61 +1,314 @@
-use crate::pairing::{get_ranked_providers, RankedProvider, SDKPairingState};
+use crate::cache::ResponseCache;
+use crate::chaos::FaultInjector;
+use crate::config::Config;
+use crate::crypto::public_key_to_address;
+use crate::diagnostics::Diagnostics;
+use crate::error_policy::ErrorPolicy;
+use crate::fallback::DirectFallback;
+use crate::geolocation::geolocation_name;
+use crate::handover::Drain;
+use crate::middleware::MiddlewareChain;
+use crate::pacing::CuPacer;
+use crate::pairing::{
+    get_geo_ranked_providers, get_ranked_providers, RankedProvider, SDKPairingState,
+};
+use crate::pinning::ProviderPins;
+use crate::priority::PriorityGate;
+use crate::proofs::ProofLog;
+use crate::provider_errors::ProviderBackoff;
+use crate::provider_stats::ProviderStats;
+use crate::recorder::Recorder;
+use crate::storage::{consumer_namespace, Storage};
+use crate::utils::{LAVA_CHAIN_PREFIX, SPEC_ID};
 use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+pub const RELAY_CU: u64 = 10;
+const SESSIONS: &str = "sessions";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    // How long sessions of a previous epoch stay around for relays that started under it
+    pub epoch_overlap_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            epoch_overlap_secs: 60,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ProviderSession {
     pub session_id: u64,
     pub cu_sum: u64,
     pub relay_num: u64,
+    pub last_used: Instant,
+}
+
+//
+// What a session keeps across restarts, CU signed for before one still counts against the
+// provider's limit afterwards
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    session_id: u64,
+    cu_sum: u64,
+    relay_num: u64,
+}
+
+fn session_key((spec_id, epoch, provider_address): &(String, i64, String)) -> String {
+    format!("{}/{}/{}", spec_id, epoch, provider_address)
+}
+
+fn parse_session_key(key: &str) -> Option<(String, i64, String)> {
+    let mut parts = key.splitn(3, '/');
+    Some((
+        parts.next()?.to_string(),
+        parts.next()?.parse().ok()?,
+        parts.next()?.to_string(),
+    ))
 }
 
 pub struct ConsumerSessionContext {
-    sessions: HashMap<String, ProviderSession>,
-    ranked_providers: Vec<RankedProvider>,
+    // Keyed by (spec, epoch, provider address), a provider gets a fresh session every epoch
+    // and a separate one for each chain it serves us
+    sessions: HashMap<(String, i64, String), ProviderSession>,
     pub private_key: SigningKey,
     pub pairing_state: Arc<Mutex<SDKPairingState>>,
+    // Pairings of the additional chains served next to SPEC_ID, keyed by spec
+    pub chains: HashMap<String, Arc<Mutex<SDKPairingState>>>,
+    pub recorder: Arc<Recorder>,
+    pub storage: Arc<dyn Storage>,
+    // Where this consumer's sessions live in storage, apart from other consumers'
+    sessions_namespace: String,
+    pub chaos: Arc<FaultInjector>,
+    pub proofs: Arc<ProofLog>,
+    pub config: Config,
+    pub pins: ProviderPins,
+    pub priority: Arc<PriorityGate>,
+    pub stats: Arc<Mutex<ProviderStats>>,
+    pub middleware: Arc<MiddlewareChain>,
+    // Providers that asked us to slow down, ranked last until their time is up
+    pub backoff: ProviderBackoff,
+    // Retry, failover, ban and report decisions after failed relays
+    pub error_policy: ErrorPolicy,
+    pub diagnostics: Diagnostics,
+    // Open WS connections, waited for when draining on shutdown
+    pub drain: Arc<Drain>,
+    pub pacer: CuPacer,
+    pub cache: Arc<ResponseCache>,
+    pub fallback: DirectFallback,
 }
 
 impl ConsumerSessionContext {
-    pub fn new(private_key: SigningKey, pairing_state: Arc<Mutex<SDKPairingState>>) -> Self {
-        ConsumerSessionContext {
-            sessions: HashMap::new(),
-            ranked_providers: Vec::new(),
+    pub fn new(
+        private_key: SigningKey,
+        pairing_state: Arc<Mutex<SDKPairingState>>,
+        recorder: Arc<Recorder>,
+        storage: Arc<dyn Storage>,
+        config: Config,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let consumer =
+            public_key_to_address(&private_key.verifying_key().to_sec1_bytes(), LAVA_CHAIN_PREFIX)?;
+        let sessions_namespace = consumer_namespace(SESSIONS, &consumer);
+        let now = Instant::now();
+        let sessions = storage
+            .load_all::<StoredSession>(&sessions_namespace)
+            .into_iter()
+            .filter_map(|(key, session)| {
+                let session = ProviderSession {
+                    session_id: session.session_id,
+                    cu_sum: session.cu_sum,
+                    relay_num: session.relay_num,
+                    last_used: now,
+                };
+                Some((parse_session_key(&key)?, session))
+            })
+            .collect::<HashMap<_, _>>();
+        if !sessions.is_empty() {
+            println!("Restored {} sessions from storage", sessions.len());
+        }
+
+        Ok(ConsumerSessionContext {
+            sessions,
             private_key,
             pairing_state,
+            chains: HashMap::new(),
+            recorder,
+            chaos: Arc::new(FaultInjector::new(config.chaos.clone())),
+            priority: Arc::new(PriorityGate::new(config.priority.clone())),
+            proofs: Arc::new(ProofLog::new(&config.proofs, Arc::clone(&storage), &consumer)?),
+            middleware: Arc::new(MiddlewareChain::new(&config.middleware)),
+            backoff: ProviderBackoff::new(config.backoff.clone()),
+            error_policy: ErrorPolicy::new(
+                config.error_policy.clone(),
+                Arc::clone(&storage),
+                &consumer,
+            ),
+            diagnostics: Diagnostics::new(Arc::clone(&storage), &consumer),
+            drain: Arc::new(Drain::default()),
+            pacer: CuPacer::new(config.pacing.clone()),
+            cache: Arc::new(ResponseCache::new(config.cache.clone(), Arc::clone(&storage))),
+            fallback: DirectFallback::new(config.fallback.clone()),
+            storage,
+            sessions_namespace,
+            config,
+            pins: ProviderPins::default(),
+            stats: Arc::new(Mutex::new(ProviderStats::default())),
+        })
+    }
+
+    pub fn chain_pairing_state(&self, spec_id: &str) -> Option<Arc<Mutex<SDKPairingState>>> {
+        if spec_id == SPEC_ID {
+            return Some(self.pairing_state.clone());
         }
+        self.chains.get(spec_id).cloned()
     }
 
-    pub fn get_or_create_session(&mut self, provider_address: &str) -> &mut ProviderSession {
+    pub fn get_or_create_session(
+        &mut self,
+        spec_id: &str,
+        epoch: i64,
+        provider_address: &str,
+    ) -> &mut ProviderSession {
         self.sessions
-            .entry(provider_address.to_string())
+            .entry((spec_id.to_string(), epoch, provider_address.to_string()))
             .or_insert_with(|| {
                 ProviderSession {
                 // FIXME: u64 sometimes encodes incorrectly with this implementation, truncate to u32 for now
                 session_id: (Uuid::new_v4().as_u128() as u32) as u64,
                 cu_sum: 0,
                 relay_num: 1,
+                last_used: Instant::now(),
             }})
     }
 
-    pub fn update_session(&mut self, provider_address: &str) {
-        if let Some(session) = self.sessions.get_mut(provider_address) {
-            session.cu_sum += 10;
+    //
+    // Share of the provider's CU limit already spent, None when the pairing has no limit
+    pub async fn cu_usage(&self, spec_id: &str, provider: &RankedProvider) -> Option<f64> {
+        let limit = {
+            let pairing_state = self.chain_pairing_state(spec_id)?;
+            let state = pairing_state.lock().await;
+            state
+                .emergency
+                .cu_limit(state.params.max_cu, std::time::Instant::now())?
+        };
+        let used = self
+            .sessions
+            .get(&(spec_id.to_string(), provider.epoch, provider.provider.address.clone()))
+            .map_or(0, |s| s.cu_sum);
+        Some(used as f64 / limit as f64)
+    }
+
+    //
+    // CU signed for across every provider of the chain in `epoch`
+    pub fn epoch_cu_used(&self, spec_id: &str, epoch: i64) -> u64 {
+        self.sessions
+            .iter()
+            .filter(|((spec, session_epoch, _), _)| spec == spec_id && *session_epoch == epoch)
+            .map(|(_, session)| session.cu_sum)
+            .sum()
+    }
+
+    pub fn update_session(&mut self, spec_id: &str, epoch: i64, provider_address: &str, cu: u64) {
+        let key = (spec_id.to_string(), epoch, provider_address.to_string());
+        if let Some(session) = self.sessions.get_mut(&key) {
+            session.cu_sum += cu;
             session.relay_num += 1;
+            session.last_used = Instant::now();
+            let stored = StoredSession {
+                session_id: session.session_id,
+                cu_sum: session.cu_sum,
+                relay_num: session.relay_num,
+            };
+            self.storage.save(&self.sessions_namespace, &session_key(&key), &stored);
         }
     }
 
-    pub async fn get_top_provider(&mut self) -> Option<&RankedProvider> {
-        if self.ranked_providers.is_empty() {
-            let ranked_providers = get_ranked_providers(self.pairing_state.clone()).await;
-            if !ranked_providers.is_empty() {
-                self.ranked_providers = ranked_providers;
-            } else {
-                return None;
-            }
+    //
+    // Dropped sessions are recreated with a fresh id on the next relay to the provider
+    pub fn reset_session(&mut self, spec_id: &str, epoch: i64, provider_address: &str) {
+        let key = (spec_id.to_string(), epoch, provider_address.to_string());
+        if self.sessions.remove(&key).is_some() {
+            self.storage.remove(&self.sessions_namespace, &session_key(&key));
         }
-        self.ranked_providers.first()
+    }
+
+    //
+    // Sessions of older epochs are only kept while relays may still be finishing on them
+    pub fn prune_sessions(&mut self, current_epoch: i64, now: Instant) {
+        let overlap = Duration::from_secs(self.config.sessions.epoch_overlap_secs);
+        let (storage, namespace) = (&self.storage, &self.sessions_namespace);
+        self.sessions.retain(|key, session| {
+            let keep =
+                key.1 >= current_epoch || now.saturating_duration_since(session.last_used) < overlap;
+            if !keep {
+                storage.remove(namespace, &session_key(key));
+            }
+            keep
+        });
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    //
+    // Always the latest pairing, so new requests move to the new epoch as soon as it lands
+    // while relays holding providers of the previous one keep signing for their own epoch.
+    // Prefer the pool of the client's region, fall back to the global ranking when it's empty
+    pub async fn get_ranked_providers(&mut self, geolocation: Option<u64>) -> Vec<RankedProvider> {
+        self.chain_ranked_providers(SPEC_ID, geolocation).await
+    }
+
+    pub async fn chain_ranked_providers(
+        &mut self,
+        spec_id: &str,
+        geolocation: Option<u64>,
+    ) -> Vec<RankedProvider> {
+        let Some(pairing_state) = self.chain_pairing_state(spec_id) else {
+            return Vec::new();
+        };
+        let current_epoch = pairing_state.lock().await.params.current_epoch;
+        self.prune_sessions(current_epoch, Instant::now());
+
+        let ranked = match geolocation {
+            Some(geolocation) => {
+                let pool = get_geo_ranked_providers(pairing_state.clone(), geolocation).await;
+                if pool.is_empty() {
+                    println!(
+                        "No providers in the {} pool, using global ranking",
+                        geolocation_name(geolocation)
+                    );
+                    get_ranked_providers(pairing_state).await
+                } else {
+                    pool
+                }
+            }
+            None => get_ranked_providers(pairing_state).await,
+        };
+        self.without_backed_off(ranked)
+    }
+
+    //
+    // Backed off providers go last rather than away, a request still has somewhere to go
+    // when every provider is backing off. Banned providers are dropped unless that leaves none
+    pub fn without_backed_off(&self, ranked: Vec<RankedProvider>) -> Vec<RankedProvider> {
+        let now = Instant::now();
+        let (banned, ranked): (Vec<_>, Vec<_>) = ranked
+            .into_iter()
+            .partition(|p| self.error_policy.is_banned(&p.provider.address, now));
+        let ranked = if ranked.is_empty() { banned } else { ranked };
+        let (backed_off, available): (Vec<_>, Vec<_>) = ranked
+            .into_iter()
+            .partition(|p| self.backoff.is_backed_off(&p.provider.address, now));
+        available.into_iter().chain(backed_off).collect()
     }
 }
@@ -0,0 +1,148 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+use crate::pairing::RankedProvider;
+use crate::relay::relay_untracked;
+use crate::session_context::ConsumerSessionContext;
+
+// Methods that can't change chain state, nothing else is ever sent twice
+const READ_ONLY_METHODS: &[&str] = &[
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getBlockReceipts",
+    "eth_getBlockTransactionCountByHash",
+    "eth_getBlockTransactionCountByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getProof",
+    "eth_getStorageAt",
+    "eth_getTransactionByBlockHashAndIndex",
+    "eth_getTransactionByBlockNumberAndIndex",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "eth_maxPriorityFeePerGas",
+    "eth_syncing",
+    "net_version",
+    "web3_clientVersion",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowConfig {
+    // Share of successful relays copied to the shadow provider, 0 turns mirroring off.
+    // Mirrored relays are real relays and use CU like any other
+    pub fraction: f64,
+    // Provider to evaluate, by default the best ranked one that didn't serve the request
+    pub provider: Option<String>,
+}
+
+impl ShadowConfig {
+    pub fn should_mirror(&self) -> bool {
+        self.fraction > 0.0 && rand::thread_rng().gen_bool(self.fraction.min(1.0))
+    }
+}
+
+//
+// Fire and forget: send the request to the shadow provider too and log whether it agrees
+// with the reply the client got. Nothing from the shadow ever reaches the client, and its
+// outcome doesn't count towards the provider's stats, bans or QoS
+pub fn mirror(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    config: &ShadowConfig,
+    providers: &[RankedProvider],
+    served_by: &RankedProvider,
+    payload: Vec<u8>,
+    reply: &[u8],
+) {
+    let shadow = match &config.provider {
+        Some(address) => providers.iter().find(|p| &p.provider.address == address),
+        None => providers
+            .iter()
+            .find(|p| p.provider.address != served_by.provider.address),
+    };
+    if !is_read_only(&payload) {
+        return;
+    }
+    let Some(shadow) = shadow.cloned() else {
+        println!("Shadow: no provider to mirror to");
+        return;
+    };
+    if shadow.provider.address == served_by.provider.address {
+        return;
+    }
+
+    let context = Arc::clone(context);
+    let expected = outcome(reply);
+    tokio::spawn(async move {
+        let method = serde_json::from_slice::<Value>(&payload)
+            .ok()
+            .and_then(|request| request["method"].as_str().map(str::to_string))
+            .unwrap_or_else(|| "batch".to_string());
+        let start = Instant::now();
+        let result = relay_untracked(&context, &shadow, payload).await;
+        let latency = start.elapsed();
+        match result {
+            Ok(data) if outcome(&data) == expected => println!(
+                "Shadow: {} matches on {} ({:?})",
+                shadow.provider.address, method, latency
+            ),
+            Ok(data) => println!(
+                "Shadow: {} differs on {} ({:?}): expected {}, got {}",
+                shadow.provider.address,
+                method,
+                latency,
+                expected.map_or("unparsable reply".to_string(), |v| v.to_string()),
+                String::from_utf8_lossy(&data)
+            ),
+            Err(status) => println!(
+                "Shadow: {} failed on {} ({:?}): {}",
+                shadow.provider.address, method, latency, status
+            ),
+        }
+    });
+}
+
+//
+// Batches are mirrored only when every call in them is read-only
+pub fn is_read_only(payload: &[u8]) -> bool {
+    let is_read_only_call =
+        |call: &Value| call["method"].as_str().is_some_and(|m| READ_ONLY_METHODS.contains(&m));
+    match serde_json::from_slice::<Value>(payload) {
+        Ok(Value::Array(calls)) => !calls.is_empty() && calls.iter().all(is_read_only_call),
+        Ok(call) => is_read_only_call(&call),
+        Err(_) => false,
+    }
+}
+
+//
+// What a reply says, without ids and formatting that differ between nodes anyway
+pub fn outcome(data: &[u8]) -> Option<Value> {
+    let reply = serde_json::from_slice::<Value>(data).ok()?;
+    let single = |reply: &Value| {
+        reply
+            .get("result")
+            .or_else(|| reply.get("error"))
+            .cloned()
+            .unwrap_or(Value::Null)
+    };
+    Some(match &reply {
+        Value::Array(replies) => {
+            let mut replies = replies.iter().collect::<Vec<_>>();
+            replies.sort_by_key(|reply| reply["id"].to_string());
+            Value::Array(replies.into_iter().map(single).collect())
+        }
+        reply => single(reply),
+    })
+}
@@ -0,0 +1,140 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::dogfood::LavaQuerier;
+use crate::proto::RelayPrivateData;
+use crate::session_context::RELAY_CU;
+use crate::utils::{JSONRPC_INTERFACE, REST_INTERFACE};
+
+const SPEC_PATH: &str = "/lavanet/lava/spec/spec";
+
+//
+// Compute units per api, taken from the expanded spec the sdk_pairing response carries
+#[derive(Debug, Clone, Default)]
+pub struct ChainSpec {
+    pub index: String,
+    // Enabled api interfaces in spec order, e.g. ["rest", "grpc", "tendermintrpc"]
+    pub interfaces: Vec<String>,
+    compute_units: HashMap<(String, String), u64>,
+}
+
+impl ChainSpec {
+    pub fn parse(spec: &Value) -> Self {
+        let mut compute_units = HashMap::new();
+        let mut interfaces: Vec<String> = Vec::new();
+        for collection in spec["api_collections"].as_array().into_iter().flatten() {
+            if collection["enabled"].as_bool() == Some(false) {
+                continue;
+            }
+            let interface = collection["collection_data"]["api_interface"]
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+            if !interfaces.contains(&interface) {
+                interfaces.push(interface.clone());
+            }
+            for api in collection["apis"].as_array().into_iter().flatten() {
+                let name = match api["name"].as_str() {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let cu = api["compute_units"]
+                    .as_str()
+                    .and_then(|cu| cu.parse().ok())
+                    .or_else(|| api["compute_units"].as_u64())
+                    .unwrap_or(RELAY_CU);
+                compute_units.insert((interface.clone(), name), cu);
+            }
+        }
+        Self {
+            index: spec["index"].as_str().unwrap_or("").to_string(),
+            interfaces,
+            compute_units,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compute_units.is_empty()
+    }
+
+    //
+    // The interface relays and probes default to, JSON-RPC when the spec has it
+    pub fn primary_interface(&self) -> &str {
+        if self.interfaces.is_empty() || self.interfaces.iter().any(|i| i == JSONRPC_INTERFACE) {
+            return JSONRPC_INTERFACE;
+        }
+        &self.interfaces[0]
+    }
+
+    pub fn compute_units(&self, interface: &str, api: &str) -> Option<u64> {
+        self.compute_units
+            .get(&(interface.to_string(), api.to_string()))
+            .copied()
+    }
+
+    //
+    // REST apis are path templates like /cosmos/bank/v1beta1/balances/{address}, the
+    // template with the fewest placeholders wins when several match
+    pub fn rest_compute_units(&self, api_url: &str) -> Option<u64> {
+        let path = api_url.split('?').next().unwrap_or("");
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        self.compute_units
+            .iter()
+            .filter(|((interface, _), _)| interface == REST_INTERFACE)
+            .filter_map(|((_, template), cu)| {
+                let template = template.trim_matches('/').split('/').collect::<Vec<_>>();
+                if template.len() != segments.len() {
+                    return None;
+                }
+                let mut placeholders = 0;
+                for (expected, segment) in template.iter().zip(&segments) {
+                    if expected.starts_with('{') && expected.ends_with('}') {
+                        placeholders += 1;
+                    } else if expected != segment {
+                        return None;
+                    }
+                }
+                Some((placeholders, *cu))
+            })
+            .min_by_key(|(placeholders, _)| *placeholders)
+            .map(|(_, cu)| cu)
+    }
+
+    pub fn relay_data_cu(&self, relay_data: &RelayPrivateData) -> u64 {
+        if relay_data.api_interface == REST_INTERFACE {
+            return self.rest_compute_units(&relay_data.api_url).unwrap_or(RELAY_CU);
+        }
+        self.relay_cu(&relay_data.api_interface, &relay_data.data)
+    }
+
+    //
+    // Batches cost the sum of their calls, anything we can't price falls back to RELAY_CU
+    pub fn relay_cu(&self, interface: &str, payload: &[u8]) -> u64 {
+        let request: Value = match serde_json::from_slice(payload) {
+            Ok(request) => request,
+            Err(_) => return RELAY_CU,
+        };
+        let calls = match request.as_array() {
+            Some(batch) => batch.iter().collect::<Vec<_>>(),
+            None => vec![&request],
+        };
+        calls
+            .iter()
+            .map(|call| {
+                call["method"]
+                    .as_str()
+                    .and_then(|method| self.compute_units(interface, method))
+                    .unwrap_or(RELAY_CU)
+            })
+            .sum::<u64>()
+            .max(1)
+    }
+}
+
+pub async fn fetch_spec(
+    querier: &LavaQuerier,
+    chain_id: &str,
+) -> Result<ChainSpec, Box<dyn std::error::Error>> {
+    let json = querier.get(&format!("{}/{}", SPEC_PATH, chain_id)).await?;
+    Ok(ChainSpec::parse(&json["spec"]))
+}
@@ -0,0 +1,372 @@
+use redis::Commands;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub type StorageError = Box<dyn std::error::Error>;
+
+// Bounds on every redis round trip, an unreachable server fails calls instead of hanging them
+const REDIS_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const REDIS_IO_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    // Nothing survives a restart
+    Memory,
+    // A local file, survives restarts of this consumer
+    Sqlite,
+    // Survives restarts and can be shared by several consumers
+    Redis,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: Backend,
+    pub sqlite_path: String,
+    pub redis_url: String,
+    // Prepended to every redis key, so several deployments can share a server
+    pub redis_prefix: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Memory,
+            sqlite_path: "lavap.db".to_string(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_prefix: "lavap".to_string(),
+        }
+    }
+}
+
+//
+// Key-value store behind everything the consumer persists: pairings, sessions, provider
+// bans, relay proofs and cached responses. Each of them keeps its entries in a namespace of
+// its own. Calls block, the sqlite and redis backends are opened behind WriteBehind so
+// writes made under the context lock never wait for I/O
+pub trait Storage: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError>;
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError>;
+
+    // Every entry of the namespace, in key order
+    fn scan(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError>;
+}
+
+pub fn open_storage(config: &StorageConfig) -> Result<Arc<dyn Storage>, StorageError> {
+    Ok(match config.backend {
+        Backend::Memory => Arc::new(MemoryStorage::default()),
+        Backend::Sqlite => {
+            println!("Persisting state to sqlite at {}", config.sqlite_path);
+            Arc::new(WriteBehind::new(SqliteStorage::open(&config.sqlite_path)?))
+        }
+        Backend::Redis => {
+            println!("Persisting state to redis under {}:*", config.redis_prefix);
+            let redis = RedisStorage::open(&config.redis_url, &config.redis_prefix)?;
+            Arc::new(WriteBehind::new(redis))
+        }
+    })
+}
+
+//
+// Namespace of state that belongs to one consumer key, so consumers sharing a backend never
+// read each other's sessions, bans, proofs or pairings. Only cached responses are shared
+pub fn consumer_namespace(namespace: &str, consumer: &str) -> String {
+    format!("{}/{}", namespace, consumer)
+}
+
+//
+// Entries are stored as JSON. Persistence is best effort, failures are logged and the
+// consumer carries on with what it has in memory
+impl dyn Storage + '_ {
+    pub fn load<T: DeserializeOwned>(&self, namespace: &str, key: &str) -> Option<T> {
+        match self.get(namespace, key) {
+            Ok(Some(value)) => decode(namespace, key, &value),
+            Ok(None) => None,
+            Err(e) => {
+                println!("Storage: failed to read {}/{}: {}", namespace, key, e);
+                None
+            }
+        }
+    }
+
+    pub fn load_all<T: DeserializeOwned>(&self, namespace: &str) -> Vec<(String, T)> {
+        match self.scan(namespace) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = decode(namespace, &key, &value)?;
+                    Some((key, value))
+                })
+                .collect(),
+            Err(e) => {
+                println!("Storage: failed to read {}: {}", namespace, e);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn save<T: Serialize>(&self, namespace: &str, key: &str, value: &T) {
+        let result = serde_json::to_vec(value)
+            .map_err(StorageError::from)
+            .and_then(|value| self.put(namespace, key, &value));
+        if let Err(e) = result {
+            println!("Storage: failed to write {}/{}: {}", namespace, key, e);
+        }
+    }
+
+    pub fn remove(&self, namespace: &str, key: &str) {
+        if let Err(e) = self.delete(namespace, key) {
+            println!("Storage: failed to delete {}/{}: {}", namespace, key, e);
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(namespace: &str, key: &str, value: &[u8]) -> Option<T> {
+    serde_json::from_slice(value)
+        .map_err(|e| println!("Storage: skipping unreadable {}/{}: {}", namespace, key, e))
+        .ok()
+}
+
+enum Write {
+    Put(String, String, Vec<u8>),
+    Delete(String, String),
+}
+
+//
+// Puts and deletes are queued and applied in order by a thread of their own, reads go
+// straight to the backend. A read may not see a write made just before it yet, which is
+// fine for state that's only read back at startup or as a cache
+pub struct WriteBehind {
+    inner: Arc<dyn Storage>,
+    writes: mpsc::Sender<Write>,
+}
+
+impl WriteBehind {
+    pub fn new(inner: impl Storage + 'static) -> Self {
+        let inner: Arc<dyn Storage> = Arc::new(inner);
+        let (tx, rx) = mpsc::channel();
+        let backend = Arc::clone(&inner);
+        std::thread::spawn(move || {
+            for write in rx {
+                let (namespace, key, result) = match write {
+                    Write::Put(namespace, key, value) => {
+                        let result = backend.put(&namespace, &key, &value);
+                        (namespace, key, result)
+                    }
+                    Write::Delete(namespace, key) => {
+                        let result = backend.delete(&namespace, &key);
+                        (namespace, key, result)
+                    }
+                };
+                if let Err(e) = result {
+                    println!("Storage: failed to write {}/{}: {}", namespace, key, e);
+                }
+            }
+        });
+        Self {
+            inner,
+            writes: tx,
+        }
+    }
+
+    fn queue(&self, write: Write) -> Result<(), StorageError> {
+        self.writes
+            .send(write)
+            .map_err(|_| "storage writer stopped".into())
+    }
+}
+
+impl Storage for WriteBehind {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.inner.get(namespace, key)
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.queue(Write::Put(namespace.to_string(), key.to_string(), value.to_vec()))
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        self.queue(Write::Delete(namespace.to_string(), key.to_string()))
+    }
+
+    fn scan(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        self.inner.scan(namespace)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    namespaces: Mutex<HashMap<String, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        Ok(namespaces.get(namespace).and_then(|entries| entries.get(key)).cloned())
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        let mut namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        let mut namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        if let Some(entries) = namespaces.get_mut(namespace) {
+            entries.remove(key);
+        }
+        Ok(())
+    }
+
+    fn scan(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        Ok(namespaces
+            .get(namespace)
+            .map(|entries| entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+}
+
+pub struct SqliteStorage {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, StorageError> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS entries (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (namespace, key)
+            )",
+            (),
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut statement = connection
+            .prepare_cached("SELECT value FROM entries WHERE namespace = ?1 AND key = ?2")?;
+        let mut rows = statement.query((namespace, key))?;
+        Ok(match rows.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection
+            .prepare_cached(
+                "INSERT INTO entries (namespace, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (namespace, key) DO UPDATE SET value = excluded.value",
+            )?
+            .execute((namespace, key, value))?;
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection
+            .prepare_cached("DELETE FROM entries WHERE namespace = ?1 AND key = ?2")?
+            .execute((namespace, key))?;
+        Ok(())
+    }
+
+    fn scan(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut statement = connection
+            .prepare_cached("SELECT key, value FROM entries WHERE namespace = ?1 ORDER BY key")?;
+        let entries = statement
+            .query_map((namespace,), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+}
+
+//
+// One hash per namespace, {prefix}:{namespace}. A connection that failed a call, e.g. on a
+// timeout, is dropped and the next call dials a new one: a late reply on the old one would
+// otherwise be read as the answer to the next command
+pub struct RedisStorage {
+    client: redis::Client,
+    connection: Mutex<Option<redis::Connection>>,
+    prefix: String,
+}
+
+impl RedisStorage {
+    pub fn open(url: &str, prefix: &str) -> Result<Self, StorageError> {
+        let client = redis::Client::open(url)?;
+        let connection = connect(&client)?;
+        Ok(Self {
+            client,
+            connection: Mutex::new(Some(connection)),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn hash(&self, namespace: &str) -> String {
+        format!("{}:{}", self.prefix, namespace)
+    }
+
+    fn call<T>(
+        &self,
+        f: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T, StorageError> {
+        let mut slot = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut connection = match slot.take() {
+            Some(connection) => connection,
+            None => connect(&self.client)?,
+        };
+        let value = f(&mut connection)?;
+        *slot = Some(connection);
+        Ok(value)
+    }
+}
+
+fn connect(client: &redis::Client) -> redis::RedisResult<redis::Connection> {
+    let connection = client.get_connection_with_timeout(REDIS_CONNECT_TIMEOUT)?;
+    connection.set_read_timeout(Some(REDIS_IO_TIMEOUT))?;
+    connection.set_write_timeout(Some(REDIS_IO_TIMEOUT))?;
+    Ok(connection)
+}
+
+impl Storage for RedisStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.call(|connection| connection.hget(self.hash(namespace), key))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.call(|connection| connection.hset::<_, _, _, ()>(self.hash(namespace), key, value))
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        self.call(|connection| connection.hdel::<_, _, ()>(self.hash(namespace), key))
+    }
+
+    fn scan(&self, namespace: &str) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let entries: BTreeMap<String, Vec<u8>> =
+            self.call(|connection| connection.hgetall(self.hash(namespace)))?;
+        Ok(entries.into_iter().collect())
+    }
+}
@@ -0,0 +1,367 @@
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tonic::Streaming;
+
+use crate::middleware::MiddlewareChain;
+use crate::pairing::RankedProvider;
+use crate::proto::RelayReply;
+use crate::relay::{relay, relay_subscribe};
+use crate::server::serve_query;
+use crate::session_context::ConsumerSessionContext;
+use crate::utils::SPEC_ID;
+
+const DEDUP_WINDOW: usize = 256;
+const RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(1);
+
+pub async fn handle_ws(
+    State((context,)): State<(Arc<Mutex<ConsumerSessionContext>>,)>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let (geolocation, drain, middleware) = {
+        let context = context.lock().await;
+        (
+            context.config.geolocation.resolve(&headers),
+            context.drain.clone(),
+            context.middleware.clone(),
+        )
+    };
+    ws.on_upgrade(move |socket| async move {
+        let _guard = drain.track();
+        serve_socket(context, geolocation, headers, middleware, socket).await
+    })
+}
+
+async fn serve_socket(
+    context: Arc<Mutex<ConsumerSessionContext>>,
+    geolocation: Option<u64>,
+    headers: HeaderMap,
+    middleware: Arc<MiddlewareChain>,
+    socket: WebSocket,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<String>(256);
+    let writer = tokio::spawn(async move {
+        while let Some(text) = rx.recv().await {
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    //
+    // Each subscription task holds the receiving end of its stop signal and reports its id
+    // on `ended_tx` when it's gone, so finished ones don't linger here
+    let mut subscriptions: HashMap<String, oneshot::Sender<()>> = HashMap::new();
+    let (ended_tx, mut ended_rx) = mpsc::unbounded_channel::<String>();
+    loop {
+        let message = tokio::select! {
+            biased;
+            Some(id) = ended_rx.recv() => {
+                subscriptions.remove(&id);
+                continue;
+            }
+            message = stream.next() => message,
+        };
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            Some(Ok(_)) => continue,
+        };
+        let original: Value = match serde_json::from_str(&text) {
+            Ok(request) => request,
+            Err(_) => {
+                let _ = tx.send(error_response(&Value::Null, -32700, "Parse error")).await;
+                continue;
+            }
+        };
+
+        //
+        // Plain calls take the HTTP path, failover, middleware and fallback included
+        let method = original["method"].as_str().unwrap_or_default();
+        if method != "eth_subscribe" && method != "eth_unsubscribe" {
+            spawn_call(&context, &headers, &tx, original, text);
+            continue;
+        }
+
+        //
+        // Same operator rules as over HTTP
+        let mut request = original.clone();
+        if let Err(reason) = middleware.apply_request(&mut request) {
+            println!("Request rejected by middleware: {}", reason);
+            let _ = tx.send(error_response(&original["id"], -32601, &reason)).await;
+            continue;
+        }
+
+        let method = request["method"].as_str().unwrap_or_default();
+        if method == "eth_subscribe" {
+            let id = new_subscription_id();
+            let (stop_tx, stop_rx) = oneshot::channel();
+            let client = Client {
+                context: context.clone(),
+                geolocation,
+                middleware: middleware.clone(),
+                tx: tx.clone(),
+            };
+            let subscription = run_subscription(client, id.clone(), request, original, stop_rx);
+            subscriptions.insert(id.clone(), stop_tx);
+            let ended_tx = ended_tx.clone();
+            tokio::spawn(async move {
+                subscription.await;
+                let _ = ended_tx.send(id);
+            });
+        } else if method == "eth_unsubscribe" {
+            // A subscription whose task already ended has dropped its stop receiver
+            let removed = request["params"][0]
+                .as_str()
+                .and_then(|id| subscriptions.remove(id))
+                .is_some_and(|stop| stop.send(()).is_ok());
+            let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": removed});
+            let _ = tx.send(response.to_string()).await;
+        } else {
+            // Rewritten into a plain call by a rule
+            spawn_call(&context, &headers, &tx, original, text);
+        }
+    }
+
+    //
+    // Dropping the stop senders ends every subscription, each unsubscribing upstream
+    drop(subscriptions);
+    writer.abort();
+}
+
+//
+// What a subscription needs of the socket it was opened on
+struct Client {
+    context: Arc<Mutex<ConsumerSessionContext>>,
+    geolocation: Option<u64>,
+    middleware: Arc<MiddlewareChain>,
+    tx: mpsc::Sender<String>,
+}
+
+fn spawn_call(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    headers: &HeaderMap,
+    tx: &mpsc::Sender<String>,
+    call: Value,
+    text: String,
+) {
+    let (context, headers, tx) = (context.clone(), headers.clone(), tx.clone());
+    tokio::spawn(async move {
+        let response = serve_call(&context, &headers, &call, text).await;
+        let _ = tx.send(response).await;
+    });
+}
+
+async fn serve_call(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    headers: &HeaderMap,
+    call: &Value,
+    text: String,
+) -> String {
+    let response = match serve_query(context, SPEC_ID, headers, Bytes::from(text)).await {
+        Ok(response) => response,
+        Err(status) => return error_response(&call["id"], -32603, &status.to_string()),
+    };
+    match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => String::from_utf8_lossy(&body).to_string(),
+        Err(e) => error_response(&call["id"], -32603, &e.to_string()),
+    }
+}
+
+//
+// Keeps one client subscription alive: when the provider stream dies the original
+// eth_subscribe is replayed on the next ranked provider, notifications keep the client's
+// subscription id and ones already delivered by the previous provider are dropped.
+// `request` is what the providers get, response rules see the `original` the client sent.
+// Runs until `stop` fires or its sender is dropped, then unsubscribes from the provider
+async fn run_subscription(
+    client: Client,
+    client_id: String,
+    request: Value,
+    original: Value,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let Client {
+        context,
+        geolocation,
+        middleware,
+        tx,
+    } = client;
+    let payload = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_subscribe",
+        "params": request["params"],
+    })
+    .to_string()
+    .into_bytes();
+
+    let mut seen = RecentHashes::default();
+    let mut confirmed = false;
+    let mut failures = 0;
+    let mut next_provider = 0;
+    loop {
+        let providers = context.lock().await.get_ranked_providers(geolocation).await;
+        if !confirmed && (providers.is_empty() || failures >= providers.len()) {
+            let _ = tx
+                .send(error_response(&request["id"], -32603, "No provider accepted the subscription"))
+                .await;
+            return;
+        }
+        if providers.is_empty() || (failures > 0 && failures % providers.len() == 0) {
+            tokio::select! {
+                _ = tokio::time::sleep(RESUBSCRIBE_BACKOFF) => {}
+                _ = &mut stop => return,
+            }
+            if providers.is_empty() {
+                continue;
+            }
+        }
+
+        let provider = &providers[next_provider % providers.len()];
+        next_provider += 1;
+        let opened = tokio::select! {
+            opened = open_subscription(&context, provider, &payload) => opened,
+            _ = &mut stop => return,
+        };
+        let Some((mut stream, upstream_id)) = opened else {
+            failures += 1;
+            continue;
+        };
+        failures = 0;
+        println!(
+            "Subscription {} served by {}",
+            client_id, provider.provider.address
+        );
+        if !confirmed {
+            let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": client_id});
+            if tx.send(response.to_string()).await.is_err() {
+                unsubscribe(&context, provider, &upstream_id).await;
+                return;
+            }
+            confirmed = true;
+        }
+
+        loop {
+            let message = tokio::select! {
+                message = stream.message() => message,
+                _ = &mut stop => {
+                    unsubscribe(&context, provider, &upstream_id).await;
+                    return;
+                }
+            };
+            match message {
+                Ok(Some(reply)) => {
+                    let mut notification: Value = match serde_json::from_slice(&reply.data) {
+                        Ok(notification) => notification,
+                        Err(_) => continue,
+                    };
+                    if !seen.insert(&notification["params"]["result"]) {
+                        continue;
+                    }
+                    notification["params"]["subscription"] = json!(client_id);
+                    middleware.apply_response(&original, &mut notification);
+                    if tx.send(notification.to_string()).await.is_err() {
+                        unsubscribe(&context, provider, &upstream_id).await;
+                        return;
+                    }
+                }
+                Ok(None) => {
+                    println!("Subscription {} stream closed by provider", client_id);
+                    break;
+                }
+                Err(e) => {
+                    println!("Subscription {} stream failed: {}", client_id, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+//
+// Tell the provider to stop sending, rather than only dropping the stream
+async fn unsubscribe(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    upstream_id: &Value,
+) {
+    let payload = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_unsubscribe",
+        "params": [upstream_id],
+    })
+    .to_string()
+    .into_bytes();
+    if let Err(status) = relay(context, provider, payload).await {
+        println!(
+            "Failed to unsubscribe {} from {}: {}",
+            upstream_id, provider.provider.address, status
+        );
+    }
+}
+
+async fn open_subscription(
+    context: &Arc<Mutex<ConsumerSessionContext>>,
+    provider: &RankedProvider,
+    payload: &[u8],
+) -> Option<(Streaming<RelayReply>, Value)> {
+    let mut stream = relay_subscribe(context, provider, payload.to_vec()).await.ok()?;
+    match stream.message().await {
+        Ok(Some(reply)) => {
+            let response: Value = serde_json::from_slice(&reply.data).ok()?;
+            if response.get("error").is_some() || response["result"].is_null() {
+                println!(
+                    "Provider {} rejected subscription: {}",
+                    provider.provider.address, response
+                );
+                return None;
+            }
+            Some((stream, response["result"].clone()))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct RecentHashes {
+    order: VecDeque<Vec<u8>>,
+    set: HashSet<Vec<u8>>,
+}
+
+impl RecentHashes {
+    fn insert(&mut self, value: &Value) -> bool {
+        let hash = Sha256::digest(value.to_string().as_bytes()).to_vec();
+        if !self.set.insert(hash.clone()) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > DEDUP_WINDOW {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+fn new_subscription_id() -> String {
+    format!("0x{}", hex::encode(rand::thread_rng().gen::<[u8; 16]>()))
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}).to_string()
+}
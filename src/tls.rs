@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+use crate::endpoint::{endpoint_url, BoxError};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    // First matching pin wins, providers without one use the system roots as before
+    pub pins: Vec<TlsPin>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsPin {
+    // Provider address (lava@...), endpoint iPPORT, or "*" for every provider
+    pub target: String,
+    // Hex SHA-256 of the DER leaf certificate. When set, a match is required and enough on
+    // its own, so self-signed provider certificates can be pinned too
+    pub fingerprints: Vec<String>,
+    // PEM bundle of the only CAs trusted for this target, instead of the system roots
+    pub ca_file: Option<String>,
+}
+
+impl TlsPin {
+    //
+    // Endpoints are compared normalized and without their scheme, so "host" pins
+    // "https://host:443" and an http:// form of a pinned endpoint still matches
+    fn matches(&self, provider: &str, address: &str) -> bool {
+        if self.target == "*" || self.target == provider {
+            return true;
+        }
+        match (authority(&self.target), authority(address)) {
+            (Some(target), Some(address)) => target == address,
+            _ => false,
+        }
+    }
+}
+
+// Full URLs keep whatever port they came with, a missing one is the scheme's default
+fn authority(address: &str) -> Option<String> {
+    let uri = endpoint_url(address).ok()?.parse::<Uri>().ok()?;
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("http") { 80 } else { 443 });
+    Some(format!("{}:{}", uri.host()?, port))
+}
+
+#[derive(Default)]
+pub struct PinnedTls {
+    pins: Vec<(TlsPin, TlsConnector)>,
+}
+
+impl PinnedTls {
+    pub fn new(config: &TlsConfig) -> Result<Self, Box<dyn Error>> {
+        let pins = config
+            .pins
+            .iter()
+            .map(|pin| Ok((pin.clone(), tls_connector(pin)?)))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        Ok(Self { pins })
+    }
+
+    pub fn connector_for(&self, provider: &str, address: &str) -> Option<TlsConnector> {
+        self.pins
+            .iter()
+            .find(|(pin, _)| pin.matches(provider, address))
+            .map(|(_, connector)| connector.clone())
+    }
+}
+
+fn tls_connector(pin: &TlsPin) -> Result<TlsConnector, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+    match &pin.ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path)?;
+            for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots.add(cert?)?;
+            }
+            if roots.is_empty() {
+                return Err(format!("No certificates in {}", path).into());
+            }
+        }
+        None => {
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs()?);
+        }
+    }
+
+    let fingerprints = pin
+        .fingerprints
+        .iter()
+        .map(|fingerprint| {
+            let fingerprint = fingerprint.replace(':', "").to_lowercase();
+            hex::decode(&fingerprint)
+                .ok()
+                .filter(|bytes| bytes.len() == 32)
+                .ok_or_else(|| format!("Invalid SHA-256 fingerprint {}", fingerprint))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let verifier = PinningVerifier {
+        webpki: WebPkiServerVerifier::builder(Arc::new(roots)).build()?,
+        fingerprints,
+    };
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+//
+// Dial `addr` ourselves and run TLS with the pinned verifier, tonic only sees a plain
+// stream so it doesn't add its own TLS on top. `origin` keeps the real https authority
+pub async fn connect_pinned(
+    tls: TlsConnector,
+    origin: Uri,
+    host: &str,
+    addr: SocketAddr,
+) -> Result<Channel, BoxError> {
+    let server_name = ServerName::try_from(host.to_string())?;
+    let endpoint = Endpoint::from_shared(format!("http://{}", addr))?.origin(origin);
+    let channel = endpoint
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            let tls = tls.clone();
+            let server_name = server_name.clone();
+            async move {
+                let tcp = TcpStream::connect(addr).await?;
+                tls.connect(server_name, tcp).await
+            }
+        }))
+        .await?;
+    Ok(channel)
+}
+
+#[derive(Debug)]
+struct PinningVerifier {
+    webpki: Arc<WebPkiServerVerifier>,
+    fingerprints: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.fingerprints.is_empty() {
+            return self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            );
+        }
+        let fingerprint = Sha256::digest(end_entity.as_ref());
+        if self.fingerprints.iter().any(|f| f[..] == fingerprint[..]) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            println!(
+                "Certificate of {:?} doesn't match any pinned fingerprint: {}",
+                server_name,
+                hex::encode(fingerprint)
+            );
+            Err(rustls::Error::General("certificate fingerprint mismatch".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.webpki.supported_verify_schemes()
+    }
+}
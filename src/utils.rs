@@ -1,9 +1,11 @@
 use byteorder::{ByteOrder, LittleEndian};
+use std::time::Duration;
 
 pub const LAVA_CHAIN_ID: &str = "lava-testnet-2";
 pub const SPEC_ID: &str = "ETH1";
 pub const LAVA_CHAIN_PREFIX: &str = "lava@";
 pub const JSONRPC_INTERFACE: &str = "jsonrpc";
+pub const REST_INTERFACE: &str = "rest";
 
 pub fn encode_uint64(value: u64) -> [u8; 8] {
     let mut buf = [0u8; 8];
@@ -24,4 +26,11 @@ pub fn byte_array_to_string(array: &[u8], replace_double_quotes: bool) -> String
             _ => format!("\\{:03o}", byte),
         })
         .collect()
-}
\ No newline at end of file
+}
+
+//
+// Durations in REST responses use the protobuf JSON form, e.g. "1200s" or "0.5s"
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let seconds = value.trim().strip_suffix('s')?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use lavap_rs::archive::{archive_providers, ArchiveConfig, ARCHIVE_EXTENSION};
+use lavap_rs::pairing::{Provider, ProviderEndpoint, RankedProvider};
+
+fn endpoint(host: &str, archive: bool) -> ProviderEndpoint {
+    ProviderEndpoint {
+        address: format!("{}:443", host),
+        geolocation: 1,
+        extensions: match archive {
+            true => vec![ARCHIVE_EXTENSION.to_string()],
+            false => vec![],
+        },
+    }
+}
+
+// Ranked on its first endpoint, as the probe would leave it
+fn ranked(address: &str, endpoints: Vec<ProviderEndpoint>) -> RankedProvider {
+    let provider = Provider {
+        address: address.to_string(),
+        stake: 1,
+        endpoints: endpoints.clone(),
+        latest_block: 0,
+    };
+    RankedProvider::new(
+        provider,
+        endpoints[0].clone(),
+        Duration::from_millis(10),
+        10,
+    )
+}
+
+#[test]
+fn pruned_state_errors_are_recognised_in_any_case() {
+    let config = ArchiveConfig::default();
+    let error = |message: &str| {
+        serde_json::to_vec(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {"code": -32000, "message": message},
+        }))
+        .unwrap()
+    };
+    assert!(config.is_pruned_state_error(&error("Missing trie node abc (path )")));
+    assert!(config.is_pruned_state_error(&error("block not available")));
+    assert!(!config.is_pruned_state_error(&error("execution reverted")));
+    assert!(!config.is_pruned_state_error(br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#));
+    assert!(!config.is_pruned_state_error(b"missing trie node"));
+}
+
+#[test]
+fn archive_providers_keep_their_rank_and_use_an_archive_endpoint() {
+    let providers = vec![
+        ranked("lava@a", vec![endpoint("a", false)]),
+        ranked(
+            "lava@b",
+            vec![endpoint("b", false), endpoint("b-archive", true)],
+        ),
+        ranked("lava@c", vec![endpoint("c-archive", true)]),
+    ];
+    let archive = archive_providers(&providers);
+
+    let picked: Vec<_> = archive
+        .iter()
+        .map(|p| (p.provider.address.as_str(), p.endpoint.address.as_str()))
+        .collect();
+    assert_eq!(
+        picked,
+        vec![("lava@b", "b-archive:443"), ("lava@c", "c-archive:443")]
+    );
+    assert!(archive_providers(&providers[..1]).is_empty());
+}
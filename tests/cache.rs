@@ -0,0 +1,149 @@
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use lavap_rs::cache::{CacheConfig, Lookup, ResponseCache};
+use lavap_rs::config::Config;
+use lavap_rs::storage::{MemoryStorage, Storage};
+
+mod common;
+use common::{pairing_state, replay, serve, set_pairing};
+
+const HASH: &str = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+const OTHER_HASH: &str = "0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9";
+
+fn cache(max_entries: usize, storage: &Arc<dyn Storage>) -> ResponseCache {
+    let config = CacheConfig {
+        enabled: true,
+        max_entries,
+        validate_fraction: 0.0,
+    };
+    ResponseCache::new(config, Arc::clone(storage))
+}
+
+fn call(id: u64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+fn result(result: Value) -> Vec<u8> {
+    json!({"jsonrpc": "2.0", "id": 1, "result": result})
+        .to_string()
+        .into_bytes()
+}
+
+#[test]
+fn only_queries_pinned_to_a_block_hash_get_a_key() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let cache = cache(10, &storage);
+    let key = |request: &Value| cache.key("ETH1", request);
+
+    assert!(key(&call(1, "eth_getBlockByHash", json!([HASH, false]))).is_some());
+    assert!(key(&call(1, "eth_getBlockByNumber", json!(["0x10", false]))).is_none());
+    assert!(key(&call(1, "eth_getBlockByHash", json!(["0x1234", false]))).is_none());
+    let tx = json!({"to": "0x00000000000000000000000000000000000000aa"});
+    assert!(key(&call(1, "eth_call", json!([tx, {"blockHash": HASH}]))).is_some());
+    assert!(key(&call(1, "eth_call", json!([tx, "latest"]))).is_none());
+    // Only the node can tell whether the block is still canonical
+    assert!(key(&call(
+        1,
+        "eth_call",
+        json!([tx, {"blockHash": HASH, "requireCanonical": true}])
+    ))
+    .is_none());
+    assert!(key(&call(
+        1,
+        "eth_call",
+        json!([tx, {"blockHash": HASH, "requireCanonical": false}])
+    ))
+    .is_some());
+
+    // The id doesn't matter, every param does
+    let block = |id, hash: &str, full| key(&call(id, "eth_getBlockByHash", json!([hash, full])));
+    assert_eq!(block(1, HASH, false), block(2, HASH, false));
+    assert_ne!(block(1, HASH, false), block(1, HASH, true));
+    assert_ne!(block(1, HASH, false), block(1, OTHER_HASH, false));
+    assert_ne!(
+        key(&call(1, "eth_getBlockByHash", json!([HASH, false]))),
+        cache.key("NEAR", &call(1, "eth_getBlockByHash", json!([HASH, false])))
+    );
+
+    let disabled = ResponseCache::new(CacheConfig::default(), storage);
+    assert!(disabled
+        .key("ETH1", &call(1, "eth_getBlockByHash", json!([HASH, false])))
+        .is_none());
+}
+
+#[tokio::test]
+async fn results_are_kept_up_to_the_limit_and_across_restarts() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let cache = cache(2, &storage);
+    let key = |hash: &str| {
+        cache
+            .key("ETH1", &call(1, "eth_getBlockByHash", json!([hash, false])))
+            .unwrap()
+    };
+    let a = key(HASH);
+    let b = key(OTHER_HASH);
+    let c = cache
+        .key("ETH1", &call(1, "eth_getBlockByHash", json!([HASH, true])))
+        .unwrap();
+
+    // Null results and errors aren't answers worth keeping
+    cache.store(a.clone(), &result(Value::Null));
+    cache.store(
+        a.clone(),
+        br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"x"}}"#,
+    );
+    assert!(matches!(cache.lookup(&a).await, Lookup::Miss));
+
+    cache.store(a.clone(), &result(json!({"number": "0x1"})));
+    cache.store(b.clone(), &result(json!({"number": "0x2"})));
+    match cache.lookup(&a).await {
+        Lookup::Hit(result) => assert_eq!(result, json!({"number": "0x1"})),
+        _ => panic!("expected a hit"),
+    }
+
+    // The oldest goes first
+    cache.store(c.clone(), &result(json!({"number": "0x1", "full": true})));
+    assert!(matches!(cache.lookup(&a).await, Lookup::Miss));
+    assert!(matches!(cache.lookup(&b).await, Lookup::Hit(_)));
+
+    let restarted = self::cache(2, &storage);
+    assert!(matches!(restarted.lookup(&c).await, Lookup::Hit(_)));
+}
+
+#[tokio::test]
+async fn a_cached_answer_is_served_with_the_new_request_id() {
+    let first = call(1, "eth_getBlockByHash", json!([HASH, false])).to_string();
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let config = Config {
+        cache: CacheConfig {
+            enabled: true,
+            validate_fraction: 0.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut context = common::context(&state, config);
+    // A single reply, the second request can only be answered from the cache
+    context.recorder = Arc::new(replay(&[(
+        first.as_bytes(),
+        Ok(br#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x1"}}"#),
+    )]));
+    let url = format!("http://{}/", serve(context).await);
+    let client = reqwest::Client::new();
+
+    let send = |body: String| {
+        let request = client.post(&url).body(body);
+        async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+    assert_eq!(
+        send(first.clone()).await["result"],
+        json!({"number": "0x1"})
+    );
+    let second = call(2, "eth_getBlockByHash", json!([HASH, false])).to_string();
+    assert_eq!(
+        send(second).await,
+        json!({"jsonrpc": "2.0", "id": 2, "result": {"number": "0x1"}})
+    );
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::relay::relay;
+use lavap_rs::storage::{MemoryStorage, Storage};
+
+mod common;
+use common::{pairing_state, ranked, set_pairing, KEY};
+
+#[tokio::test]
+async fn injected_failures_stay_local() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let mut config = Config::default();
+    config.chaos.connection_reset_rate = 1.0;
+    config.error_policy.ban_after_errors = 1;
+    let context = common::context_with(KEY, &state, &storage, config.clone());
+    let context = Arc::new(Mutex::new(context));
+
+    assert!(relay(&context, &ranked("lava@a", 10), b"{}".to_vec())
+        .await
+        .is_err());
+
+    let mut context = context.lock().await;
+    let now = Instant::now();
+    // Failed before the session was charged
+    assert_eq!(context.session_count(), 0);
+    // The ban applies here, but isn't reported in signed sessions or kept for a restart
+    assert!(context.error_policy.is_banned("lava@a", now));
+    assert!(context
+        .error_policy
+        .unresponsive_providers("lava@b", now)
+        .is_empty());
+    let restarted = common::context_with(KEY, &state, &storage, config);
+    assert!(!restarted.error_policy.is_banned("lava@a", now));
+}
+
+#[tokio::test]
+async fn an_injected_epoch_mismatch_leaves_the_pairing_alone() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut config = Config::default();
+    config.chaos.wrong_epoch_rate = 1.0;
+    let context = Arc::new(Mutex::new(common::context(&state, config)));
+
+    assert!(relay(&context, &ranked("lava@a", 10), b"{}".to_vec())
+        .await
+        .is_err());
+
+    // No resync was asked for, so one can be right now
+    assert!(state.lock().await.request_resync(Instant::now()));
+}
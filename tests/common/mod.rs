@@ -0,0 +1,150 @@
+// Fixtures shared by the integration tests, each test binary only uses some of them
+#![allow(dead_code)]
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use k256::ecdsa::SigningKey;
+use prost::Message;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::pairing::{Provider, ProviderEndpoint, RankedProvider, SDKPairingState};
+use lavap_rs::proto::{RelayPrivateData, RelayReply};
+use lavap_rs::recorder::{Record, Recorder};
+use lavap_rs::relay::jsonrpc_relay_data;
+use lavap_rs::relay_session::generate_content_hash;
+use lavap_rs::server::router;
+use lavap_rs::session_context::ConsumerSessionContext;
+use lavap_rs::storage::{MemoryStorage, Storage};
+
+// Consumer key used unless a test needs several consumers
+pub const KEY: u8 = 7;
+
+pub fn ranked(address: &str, epoch: i64) -> RankedProvider {
+    let endpoint = ProviderEndpoint {
+        address: format!("{}.example:443", address),
+        geolocation: 1,
+        extensions: vec![],
+    };
+    let provider = Provider {
+        address: address.to_string(),
+        stake: 1,
+        endpoints: vec![endpoint.clone()],
+        latest_block: 0,
+    };
+    RankedProvider::new(provider, endpoint, Duration::from_millis(10), epoch)
+}
+
+pub fn pairing_state() -> Arc<Mutex<SDKPairingState>> {
+    Arc::new(Mutex::new(SDKPairingState::new()))
+}
+
+pub async fn set_pairing(state: &Arc<Mutex<SDKPairingState>>, epoch: i64, providers: &[&str]) {
+    let mut state = state.lock().await;
+    state.params.current_epoch = epoch;
+    state.ranked_providers = providers.iter().map(|p| ranked(p, epoch)).collect();
+}
+
+pub fn context(state: &Arc<Mutex<SDKPairingState>>, config: Config) -> ConsumerSessionContext {
+    context_with(
+        KEY,
+        state,
+        &(Arc::new(MemoryStorage::default()) as Arc<dyn Storage>),
+        config,
+    )
+}
+
+pub fn context_with(
+    key: u8,
+    state: &Arc<Mutex<SDKPairingState>>,
+    storage: &Arc<dyn Storage>,
+    config: Config,
+) -> ConsumerSessionContext {
+    ConsumerSessionContext::new(
+        SigningKey::from_slice(&[key; 32]).unwrap(),
+        Arc::clone(state),
+        Arc::new(Recorder::live()),
+        Arc::clone(storage),
+        config,
+    )
+    .unwrap()
+}
+
+// A file name under the temp dir no other test uses
+pub fn temp_path(kind: &str) -> PathBuf {
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "lavap-{}-{}-{}",
+        kind,
+        std::process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+// A JSON-RPC payload and what its provider answered: the reply data or the gRPC error
+pub type Canned<'a> = (&'a [u8], Result<&'a [u8], tonic::Status>);
+
+//
+// A recorder serving canned replies, each payload gets its replies in the order given
+pub fn replay(relays: &[Canned]) -> Recorder {
+    let relays = relays
+        .iter()
+        .map(|(payload, result)| (jsonrpc_relay_data(payload.to_vec(), vec![]), result.clone()))
+        .collect::<Vec<_>>();
+    replay_relays(&relays)
+}
+
+//
+// Same for relays other than plain JSON-RPC ones
+pub fn replay_relays(relays: &[(RelayPrivateData, Result<&[u8], tonic::Status>)]) -> Recorder {
+    let path = temp_path("replay");
+    let lines = relays
+        .iter()
+        .map(|(relay_data, result)| {
+            let content_hash = generate_content_hash(relay_data);
+            let (reply, error_code, error_message) = match result {
+                Ok(data) => {
+                    let reply = RelayReply {
+                        data: data.to_vec(),
+                        ..Default::default()
+                    };
+                    (Some(STANDARD.encode(reply.encode_to_vec())), None, None)
+                }
+                Err(status) => (
+                    None,
+                    Some(status.code() as i32),
+                    Some(status.message().to_string()),
+                ),
+            };
+            let record = Record::Relay {
+                timestamp_ms: 0,
+                provider: String::new(),
+                content_hash: hex::encode(content_hash),
+                request: String::new(),
+                reply,
+                error_code,
+                error_message,
+            };
+            serde_json::to_string(&record).unwrap()
+        })
+        .collect::<Vec<_>>();
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    let recorder = Recorder::replay(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    recorder
+}
+
+//
+// Serves the consumer's router on a free loopback port, returns its address
+pub async fn serve(context: ConsumerSessionContext) -> SocketAddr {
+    let app = router(Arc::new(Mutex::new(context))).await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::serve(listener, app).into_future());
+    addr
+}
@@ -0,0 +1,31 @@
+use serde_json::json;
+
+use lavap_rs::diagnostics::imported_config;
+
+#[test]
+fn imported_bundles_stay_on_this_machine() {
+    let bundle = json!({
+        "fallback": {"urls": {"ETH1": "https://node.example"}},
+        "health_report": {"url": "https://sink.example"},
+        "shadow": {"fraction": 0.5},
+        "chaos": {"admin": true, "timeout_rate": 0.2},
+        "handover": {"reuse_port": true},
+        "listen": {"host": "0.0.0.0", "port": 8545, "chains": {"NEAR": 3001}},
+        "proofs": {"path": "/var/lib/lavap/proofs.jsonl"},
+        "quorum": {"methods": {"eth_getBalance": 3}},
+    });
+    let config = imported_config(&bundle).unwrap();
+
+    assert!(config.fallback.urls.is_empty());
+    assert_eq!(config.health_report.url, None);
+    assert_eq!(config.shadow.fraction, 0.0);
+    assert!(!config.chaos.admin);
+    assert_eq!(config.chaos.timeout_rate, 0.0);
+    assert!(!config.handover.reuse_port);
+    assert_eq!(config.listen.host, "127.0.0.1");
+    assert!(config.listen.chains.is_empty());
+    assert_eq!(config.proofs.path, None);
+
+    // What shapes routing is kept, that's what is being looked at
+    assert_eq!(config.quorum.methods["eth_getBalance"], 3);
+}
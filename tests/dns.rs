@@ -0,0 +1,57 @@
+use axum::Router;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+
+use lavap_rs::dns::{DnsCache, DnsConfig};
+use lavap_rs::endpoint::Connector;
+use lavap_rs::tls::TlsConfig;
+
+#[tokio::test]
+async fn resolutions_are_cached_with_the_working_address_first() {
+    let dns = DnsCache::new(DnsConfig::default());
+    let addrs = dns.resolve("localhost", 2221).await.unwrap();
+    assert!(!addrs.is_empty());
+    assert!(addrs.iter().all(|addr| addr.port() == 2221));
+
+    // Whatever connected last is tried first, until it fails
+    let last = *addrs.last().unwrap();
+    dns.mark_working("localhost", 2221, last).await;
+    assert_eq!(dns.resolve("localhost", 2221).await.unwrap()[0], last);
+    dns.mark_failed("localhost", 2221, last).await;
+    assert_eq!(dns.resolve("localhost", 2221).await.unwrap(), addrs);
+
+    // Ports are cached apart
+    dns.mark_working("localhost", 2221, last).await;
+    let other: Vec<SocketAddr> = dns.resolve("localhost", 2222).await.unwrap();
+    assert!(other.iter().all(|addr| addr.port() == 2222));
+}
+
+#[tokio::test]
+async fn an_unknown_host_fails_without_a_cached_entry() {
+    let dns = DnsCache::new(DnsConfig::default());
+    assert!(dns.resolve("provider.invalid", 443).await.is_err());
+}
+
+#[tokio::test]
+async fn hostnames_are_dialed_through_the_cache() {
+    // Any HTTP/2 server will do, the connector only dials
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(axum::serve(listener, Router::new()).into_future());
+    let connector = Connector::new(DnsConfig::default(), &TlsConfig::default()).unwrap();
+
+    let address = format!("http://localhost:{}", port);
+    connector.connect("lava@a", &address).await.unwrap();
+    let addrs = connector.dns.resolve("localhost", port).await.unwrap();
+    assert_eq!(addrs[0], SocketAddr::from(([127, 0, 0, 1], port)));
+
+    // Nothing listens there anymore
+    drop(connector);
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let connector = Connector::new(DnsConfig::default(), &TlsConfig::default()).unwrap();
+    let address = format!("http://localhost:{}", closed);
+    assert!(connector.connect("lava@a", &address).await.is_err());
+}
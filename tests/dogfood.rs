@@ -0,0 +1,37 @@
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::dogfood::{DogfoodConfig, LavaQuerier};
+use lavap_rs::relay::rest_relay_data;
+
+mod common;
+use common::{pairing_state, replay_relays, set_pairing};
+
+const PAIRING_PATH: &str = "/lavanet/lava/pairing/sdk_pairing?chainID=ETH1&client=lava@x";
+
+#[tokio::test]
+async fn chain_queries_go_through_lava_providers() {
+    let chain_state = pairing_state();
+    set_pairing(&chain_state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&pairing_state(), Config::default());
+    context.chains.insert("LAV1".to_string(), chain_state);
+    let relay_data = rest_relay_data("GET", PAIRING_PATH.to_string(), vec![]);
+    context.recorder = Arc::new(replay_relays(&[
+        // An error body from the first provider moves the query on to the next one
+        (
+            relay_data.clone(),
+            Ok(br#"{"code":14,"message":"unavailable"}"#),
+        ),
+        (relay_data, Ok(br#"{"pairing":{"providers":[]}}"#)),
+    ]));
+
+    let querier = LavaQuerier::new(DogfoodConfig {
+        enabled: true,
+        ..Default::default()
+    });
+    querier.attach(Arc::new(Mutex::new(context)));
+    let json = querier.get(PAIRING_PATH).await.unwrap();
+    assert_eq!(json, json!({"pairing": {"providers": []}}));
+}
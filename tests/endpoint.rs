@@ -0,0 +1,62 @@
+use lavap_rs::endpoint::endpoint_url;
+
+#[test]
+fn normalizes_provider_addresses() {
+    let cases = [
+        (
+            "provider.example.com:2221",
+            "https://provider.example.com:2221",
+        ),
+        ("10.0.0.1:2221", "https://10.0.0.1:2221"),
+        // A missing port falls back to 443
+        ("provider.example.com", "https://provider.example.com:443"),
+        ("10.0.0.1", "https://10.0.0.1:443"),
+        ("[2001:db8::1]:8443", "https://[2001:db8::1]:8443"),
+        ("[2001:db8::1]", "https://[2001:db8::1]:443"),
+        ("[::1]:2221", "https://[::1]:2221"),
+        // Bare IPv6 with the port as the last group
+        ("2001:db8::5:2221", "https://[2001:db8::5]:2221"),
+        // Ambiguous, it's also a valid address, but iPPORT always carries a port
+        ("2001:db8::1:8443", "https://[2001:db8::1]:8443"),
+        // Not an address once the last group is taken as the port, so all of it is the host
+        ("2001:db8:0:0:0:0:0:1", "https://[2001:db8::1]:443"),
+        (
+            "https://provider.example.com:2221",
+            "https://provider.example.com:2221",
+        ),
+        ("http://127.0.0.1:2221/", "http://127.0.0.1:2221"),
+        (
+            "  provider.example.com:2221  ",
+            "https://provider.example.com:2221",
+        ),
+    ];
+    for (address, expected) in cases {
+        assert_eq!(
+            endpoint_url(address).as_deref(),
+            Ok(expected),
+            "{}",
+            address
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_addresses() {
+    let cases = [
+        "",
+        "   ",
+        "ftp://provider.example.com:21",
+        "grpc://provider.example.com:2221",
+        "provider.example.com:port",
+        "provider.example.com:65536",
+        "[2001:db8::1",
+        "[2001:db8::zz]:443",
+        "[2001:db8::1]8443",
+        "2001:db8::zz:8443",
+        "user@provider.example.com:2221",
+        "provider.example.com:2221/path",
+    ];
+    for address in cases {
+        assert!(endpoint_url(address).is_err(), "{:?} was accepted", address);
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::pairing::SDKPairingState;
+use lavap_rs::session_context::{ConsumerSessionContext, RELAY_CU};
+
+mod common;
+use common::set_pairing;
+
+fn context(state: &Arc<Mutex<SDKPairingState>>, overlap_secs: u64) -> ConsumerSessionContext {
+    let mut config = Config::default();
+    config.sessions.epoch_overlap_secs = overlap_secs;
+    common::context(state, config)
+}
+
+#[tokio::test]
+async fn new_requests_see_the_new_epoch_immediately() {
+    let state = Arc::new(Mutex::new(SDKPairingState::new()));
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = context(&state, 60);
+
+    let before = context.get_ranked_providers(None).await;
+    assert_eq!(before[0].epoch, 10);
+
+    set_pairing(&state, 11, &["lava@c"]).await;
+    let after = context.get_ranked_providers(None).await;
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].provider.address, "lava@c");
+    assert_eq!(after[0].epoch, 11);
+
+    // Providers picked before the swap still carry the epoch they were ranked in
+    assert_eq!(before[0].epoch, 10);
+}
+
+#[tokio::test]
+async fn in_flight_relays_keep_their_old_epoch_session() {
+    let state = Arc::new(Mutex::new(SDKPairingState::new()));
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = context(&state, 60);
+
+    let old = context.get_or_create_session("ETH1", 10, "lava@a").clone();
+    context.update_session("ETH1", 10, "lava@a", RELAY_CU);
+
+    set_pairing(&state, 11, &["lava@a"]).await;
+    context.get_ranked_providers(None).await;
+
+    // Same provider in the new epoch starts from scratch
+    let new = context.get_or_create_session("ETH1", 11, "lava@a").clone();
+    assert_ne!(new.session_id, old.session_id);
+    assert_eq!(new.relay_num, 1);
+    assert_eq!(new.cu_sum, 0);
+
+    // A relay that started under epoch 10 continues its own session
+    let continued = context.get_or_create_session("ETH1", 10, "lava@a").clone();
+    assert_eq!(continued.session_id, old.session_id);
+    assert_eq!(continued.relay_num, old.relay_num + 1);
+    assert_eq!(context.session_count(), 2);
+}
+
+#[tokio::test]
+async fn old_epoch_sessions_expire_after_the_overlap_window() {
+    let state = Arc::new(Mutex::new(SDKPairingState::new()));
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = context(&state, 60);
+
+    context.get_or_create_session("ETH1", 10, "lava@a");
+    context.get_or_create_session("ETH1", 11, "lava@a");
+
+    context.prune_sessions(11, Instant::now() + Duration::from_secs(59));
+    assert_eq!(context.session_count(), 2);
+
+    context.prune_sessions(11, Instant::now() + Duration::from_secs(61));
+    assert_eq!(context.session_count(), 1);
+    assert_eq!(
+        context
+            .get_or_create_session("ETH1", 11, "lava@a")
+            .relay_num,
+        1
+    );
+}
+
+#[tokio::test]
+async fn current_epoch_sessions_are_never_pruned() {
+    let state = Arc::new(Mutex::new(SDKPairingState::new()));
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = context(&state, 0);
+
+    context.get_or_create_session("ETH1", 10, "lava@a");
+    context.prune_sessions(10, Instant::now() + Duration::from_secs(3600));
+    assert_eq!(context.session_count(), 1);
+
+    context.prune_sessions(11, Instant::now());
+    assert_eq!(context.session_count(), 0);
+}
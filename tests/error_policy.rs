@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tonic::Status;
+
+use lavap_rs::config::Config;
+use lavap_rs::error_policy::{Action, ErrorPolicy, ErrorPolicyConfig};
+use lavap_rs::pairing::get_ranked_providers;
+use lavap_rs::provider_errors::ProviderError;
+use lavap_rs::relay::{jsonrpc_relay_data, relay_with_failover};
+use lavap_rs::storage::{MemoryStorage, Storage};
+use lavap_rs::utils::SPEC_ID;
+
+mod common;
+use common::{pairing_state, replay, set_pairing};
+
+const REQUEST: &[u8] = br#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#;
+
+fn policy(config: ErrorPolicyConfig, storage: &Arc<dyn Storage>) -> ErrorPolicy {
+    ErrorPolicy::new(config, Arc::clone(storage), "lava@consumer")
+}
+
+#[test]
+fn each_error_gets_its_configured_action() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let mut policy = policy(ErrorPolicyConfig::default(), &storage);
+    let now = Instant::now();
+
+    let decide = |policy: &mut ErrorPolicy, error| policy.decide("lava@a", error, false, now);
+    assert_eq!(
+        decide(&mut policy, ProviderError::SessionOutOfSync),
+        Action::RetrySame
+    );
+    assert_eq!(
+        decide(&mut policy, ProviderError::EpochMismatch),
+        Action::Failover
+    );
+    assert_eq!(
+        decide(&mut policy, ProviderError::Overloaded),
+        Action::Failover
+    );
+    // Only unavailability is reported by default
+    assert!(policy.unresponsive_providers("lava@b", now).is_empty());
+    assert_eq!(
+        decide(&mut policy, ProviderError::Unavailable),
+        Action::Failover
+    );
+    let reported = policy.unresponsive_providers("lava@b", now);
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].address, "lava@a");
+    assert_eq!(reported[0].disconnections, 1);
+    // A provider is never reported to itself
+    assert!(policy.unresponsive_providers("lava@a", now).is_empty());
+    // Reports expire after report_secs
+    let later = now + Duration::from_secs(1200);
+    assert!(policy.unresponsive_providers("lava@b", later).is_empty());
+}
+
+#[test]
+fn errors_in_a_row_escalate_to_a_ban_that_survives_a_restart() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let config = ErrorPolicyConfig {
+        ban_after_errors: 3,
+        ban_secs: 600,
+        ..Default::default()
+    };
+    let mut policy = policy(config.clone(), &storage);
+    let now = Instant::now();
+
+    let other = ProviderError::Other;
+    assert_eq!(policy.decide("lava@a", other, false, now), Action::Failover);
+    assert_eq!(policy.decide("lava@a", other, false, now), Action::Failover);
+    // A success in between starts the count over
+    policy.record_success("lava@a");
+    assert_eq!(policy.decide("lava@a", other, false, now), Action::Failover);
+    assert_eq!(policy.decide("lava@a", other, false, now), Action::Failover);
+    assert_eq!(policy.decide("lava@a", other, false, now), Action::Ban);
+    assert!(policy.is_banned("lava@a", now));
+    assert!(!policy.is_banned("lava@a", now + Duration::from_secs(600)));
+    assert!(!policy.is_banned("lava@b", now));
+
+    let restarted = self::policy(config, &storage);
+    assert!(restarted.is_banned("lava@a", Instant::now()));
+}
+
+#[tokio::test]
+async fn failover_follows_the_decided_action() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        // Out of sync is retried on the same provider, a failure then moves on
+        (REQUEST, Err(Status::unknown("relay number mismatch"))),
+        (REQUEST, Err(Status::unavailable("down"))),
+        (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#)),
+    ]));
+    let context = Arc::new(Mutex::new(context));
+    let providers = get_ranked_providers(state.clone()).await;
+
+    let (data, provider) = relay_with_failover(
+        &context,
+        &state,
+        SPEC_ID,
+        &providers,
+        jsonrpc_relay_data(REQUEST.to_vec(), vec![]),
+        3,
+    )
+    .await
+    .unwrap();
+    assert_eq!(data, br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#);
+    assert_eq!(provider.provider.address, "lava@b");
+}
+
+#[tokio::test]
+async fn attempts_are_capped() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b", "lava@c"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        (REQUEST, Err(Status::unavailable("down"))),
+        (REQUEST, Err(Status::unavailable("down"))),
+        (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#)),
+    ]));
+    let context = Arc::new(Mutex::new(context));
+    let providers = get_ranked_providers(state.clone()).await;
+
+    let result = relay_with_failover(
+        &context,
+        &state,
+        SPEC_ID,
+        &providers,
+        jsonrpc_relay_data(REQUEST.to_vec(), vec![]),
+        2,
+    )
+    .await;
+    assert!(result.is_err());
+}
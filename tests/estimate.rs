@@ -0,0 +1,81 @@
+use serde_json::json;
+use std::time::Duration;
+
+use lavap_rs::estimate::{estimate, methods_from_payload};
+use lavap_rs::session_context::RELAY_CU;
+use lavap_rs::spec::ChainSpec;
+
+fn spec() -> ChainSpec {
+    ChainSpec::parse(&json!({
+        "index": "ETH1",
+        "api_collections": [{
+            "enabled": true,
+            "collection_data": {"api_interface": "jsonrpc"},
+            "apis": [
+                {"name": "eth_call", "compute_units": "20"},
+                {"name": "eth_getLogs", "compute_units": 75},
+            ],
+        }],
+    }))
+}
+
+#[test]
+fn methods_come_from_a_single_call_or_a_batch() {
+    assert_eq!(
+        methods_from_payload(&json!({"jsonrpc": "2.0", "id": 1, "method": "eth_call"})),
+        vec!["eth_call"]
+    );
+    assert_eq!(
+        methods_from_payload(&json!([
+            {"method": "eth_call"},
+            {"id": 2},
+            {"method": "eth_getLogs"},
+        ])),
+        vec!["eth_call", "eth_getLogs"]
+    );
+}
+
+#[test]
+fn the_mix_is_priced_per_spec_and_projected() {
+    let methods = vec![
+        "eth_call".to_string(),
+        "eth_getLogs".to_string(),
+        "eth_unknown".to_string(),
+    ];
+    let estimate = estimate(
+        &spec(),
+        &methods,
+        2.0,
+        Some(Duration::from_secs(600)),
+        Some(21_000),
+    );
+
+    assert_eq!(estimate.spec, "ETH1");
+    let costs: Vec<_> = estimate
+        .methods
+        .iter()
+        .map(|m| (m.method.as_str(), m.compute_units, m.in_spec))
+        .collect();
+    assert_eq!(
+        costs,
+        vec![
+            ("eth_call", 20, true),
+            ("eth_getLogs", 75, true),
+            ("eth_unknown", RELAY_CU, false),
+        ]
+    );
+    assert_eq!(estimate.cu_per_request_mix, 105);
+    assert_eq!(estimate.cu_per_second, 210.0);
+    assert_eq!(estimate.cu_per_hour, 756_000.0);
+    assert_eq!(estimate.cu_per_day, 18_144_000.0);
+    assert_eq!(estimate.cu_per_epoch, Some(126_000.0));
+    assert_eq!(estimate.epoch_allowance_lasts_secs, Some(100.0));
+}
+
+#[test]
+fn no_traffic_never_runs_out() {
+    let estimate = estimate(&spec(), &["eth_call".to_string()], 0.0, None, Some(1000));
+    assert_eq!(estimate.cu_per_second, 0.0);
+    assert_eq!(estimate.cu_per_epoch, None);
+    assert_eq!(estimate.epoch_allowance_lasts_secs, None);
+}
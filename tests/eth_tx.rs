@@ -0,0 +1,95 @@
+use lavap_rs::eth_tx::recover_sender;
+
+// Signed with the private key 0x4646...46 in the EIP-155 specification
+const EIP155_SPEC_TX: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+const EIP155_SPEC_SENDER: &str = "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+
+// Key 0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318
+const SENDER_A: &str = "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+// Key 0x0123456789abcdef repeated four times
+const SENDER_B: &str = "0xfcad0b19bb29d4674531d6f115237e16afce377c";
+
+const LEGACY_TX: &str = "f866808504a817c80082520894d46e8dd67c5d32be8058bb8eb970870f07244567823039801ba038272410e4f902adbfefcaf0f8ae7618752d2b39633e5cad8c1813216877a025a0269328168f9391b8c80a7c2b5b2a81c0da50b2652edb1d15ceedb67524714f08";
+// Contract creation with 80 bytes of data, so the data needs a long string header
+const LEGACY_CREATE_TX: &str = "f8a107843b9aca008307a1208080b850000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b4c4d4e4f1ca022285720a7141bca75ced4a4581cfe6eda5d7c4b34e96abfb8874fb942c8b306a0218c4ef7474d77a636ace2ec91c2318c47b17ebadd178586207367c575f5e0ad";
+// EIP-155 on chain 137, v = 309 takes two bytes
+const EIP155_CHAIN_137_TX: &str = "f866038506fc23ac0082520894d46e8dd67c5d32be8058bb8eb970870f072445670180820135a097af90129154726e23b9507740e0dd509544f5e0b15c4548b926b358c4ea66c4a05da8a50f8e289b97302e3e3eaa658469985dd3d082c2ddb4621f475b51ff757c";
+const EIP2930_TX: &str = "01f9011301058504a817c80082ea6094d46e8dd67c5d32be8058bb8eb970870f0724456780b850000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b4c4d4e4ff85bf85994d46e8dd67c5d32be8058bb8eb970870f07244567f842a00000000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000000000000000000000000000000000000000000101a0103cb8462bee8bda0ac35b37e14950bb01e520d56610e372c19f06246895dc11a0729cfeaddb3a6780e2b2ec88c8989db06164ccf28c837b179121e5c593e66660";
+const EIP1559_TX: &str = "02f8d4012a847735940085174876e80083015f9094d46e8dd67c5d32be8058bb8eb970870f0724456788016345785d8a000084a9059cbbf85bf85994d46e8dd67c5d32be8058bb8eb970870f07244567f842a00000000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000000000000000000000000000000000000000000101a02fd7c4c73e5c537bc37aca7e8c6367cca39c815487ac6ec272419cfd3d52343fa042408c1e7dad623fa472f37d6a8c8223ee7ee8a6ed9bb614b7a40af8256ade05";
+// Empty access list and y parity 0
+const EIP1559_EMPTY_ACCESS_LIST_TX: &str = "02f86b0180843b9aca008502540be40082520894d46e8dd67c5d32be8058bb8eb970870f072445670180c080a0e1a7e670ee20da65d00fcdfb54229f4166feee758da86a991fa809fa180a096fa0172388c20df87862876c934666f88f6bfc8457052e6065e9890be0d681e6904b";
+
+fn sender(raw_hex: &str) -> Option<String> {
+    recover_sender(&hex::decode(raw_hex).unwrap())
+}
+
+#[test]
+fn recovers_senders_of_every_transaction_type() {
+    let cases = [
+        (EIP155_SPEC_TX, EIP155_SPEC_SENDER),
+        (LEGACY_TX, SENDER_A),
+        (LEGACY_CREATE_TX, SENDER_B),
+        (EIP155_CHAIN_137_TX, SENDER_B),
+        (EIP2930_TX, SENDER_A),
+        (EIP1559_TX, SENDER_B),
+        (EIP1559_EMPTY_ACCESS_LIST_TX, SENDER_A),
+    ];
+    for (raw, expected) in cases {
+        assert_eq!(sender(raw).as_deref(), Some(expected), "{}", raw);
+    }
+}
+
+#[test]
+fn a_tampered_transaction_recovers_someone_else() {
+    // Nonce 9 -> 10 changes the signing payload, not the encoding
+    let tampered = EIP155_SPEC_TX.replacen("f86c09", "f86c0a", 1);
+    let recovered = sender(&tampered);
+    assert!(recovered.is_some());
+    assert_ne!(recovered.as_deref(), Some(EIP155_SPEC_SENDER));
+}
+
+#[test]
+fn rejects_malformed_input() {
+    let legacy = hex::decode(LEGACY_TX).unwrap();
+    let typed = hex::decode(EIP1559_TX).unwrap();
+
+    let mut trailing = legacy.clone();
+    trailing.push(0x00);
+    let mut long_length = legacy.clone();
+    long_length[1] = 0xff;
+    // v = 26 is neither 27/28 nor EIP-155
+    let bad_v = hex::decode(LEGACY_TX.replacen("801ba0", "801aa0", 1)).unwrap();
+    // A legacy list with 8 items: the value is gone, the header shrinks by its 3 bytes
+    let missing_field = hex::decode(
+        LEGACY_TX
+            .replacen("f866", "f863", 1)
+            .replacen("823039", "", 1),
+    )
+    .unwrap();
+    // r stretched to 33 bytes
+    let long_r = hex::decode(
+        LEGACY_TX
+            .replacen("f866", "f867", 1)
+            .replacen("1ba038", "1ba10038", 1),
+    )
+    .unwrap();
+
+    let cases: Vec<(&str, Vec<u8>)> = vec![
+        ("empty", vec![]),
+        ("single byte", vec![0x02]),
+        ("empty list", vec![0xc0]),
+        ("truncated legacy", legacy[..legacy.len() - 10].to_vec()),
+        ("truncated typed", typed[..typed.len() - 10].to_vec()),
+        ("trailing bytes", trailing),
+        ("length past the end", long_length),
+        ("bad v", bad_v),
+        ("missing field", missing_field),
+        ("r over 32 bytes", long_r),
+        ("typed without a list", vec![0x02, 0x80]),
+        ("typed with too few items", vec![0x02, 0xc2, 0x01, 0x02]),
+        ("oversized length of length", vec![0xff; 9]),
+    ];
+    for (name, raw) in cases {
+        assert_eq!(recover_sender(&raw), None, "{}", name);
+    }
+}
@@ -0,0 +1,104 @@
+use axum::routing::{get, post};
+use axum::Router;
+use std::future::IntoFuture;
+use std::sync::Arc;
+
+use lavap_rs::config::Config;
+use lavap_rs::fallback::FALLBACK_HEADER;
+use lavap_rs::utils::SPEC_ID;
+
+mod common;
+use common::{pairing_state, replay, serve, set_pairing};
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#;
+
+//
+// A plain node, answering JSON-RPC on / and REST under /cosmos
+async fn direct_node() -> String {
+    let app = Router::new()
+        .route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","id":1,"result":"0xd"}"# }),
+        )
+        .route(
+            "/cosmos/base/v1/height",
+            get(|| async { r#"{"height":"13"}"# }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(axum::serve(listener, app).into_future());
+    url
+}
+
+#[tokio::test]
+async fn serves_from_the_direct_node_until_providers_are_paired() {
+    let mut config = Config::default();
+    config
+        .fallback
+        .urls
+        .insert(SPEC_ID.to_string(), direct_node().await);
+    let state = pairing_state();
+    let mut context = common::context(&state, config);
+    context.recorder = Arc::new(replay(&[(
+        REQUEST.as_bytes(),
+        Ok(br#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#),
+    )]));
+    let url = format!("http://{}/", serve(context).await);
+    let client = reqwest::Client::new();
+
+    // Nothing paired yet, the direct node answers and says so
+    let response = client.post(&url).body(REQUEST).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[FALLBACK_HEADER], "direct-node");
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"jsonrpc":"2.0","id":1,"result":"0xd"}"#
+    );
+
+    // Once a provider is there traffic goes back to Lava
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let response = client.post(&url).body(REQUEST).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(FALLBACK_HEADER).is_none());
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#
+    );
+}
+
+#[tokio::test]
+async fn without_a_direct_node_the_error_stays() {
+    let state = pairing_state();
+    let context = common::context(&state, Config::default());
+    let url = format!("http://{}/", serve(context).await);
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .body(REQUEST)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_server_error());
+    assert!(response.headers().get(FALLBACK_HEADER).is_none());
+}
+
+#[tokio::test]
+async fn rest_calls_fall_back_too() {
+    let mut config = Config::default();
+    config.rest.chains = vec!["COS5".to_string()];
+    config
+        .fallback
+        .rest_urls
+        .insert("COS5".to_string(), direct_node().await);
+    let state = pairing_state();
+    let mut context = common::context(&state, config);
+    context.chains.insert("COS5".to_string(), pairing_state());
+    let addr = serve(context).await;
+
+    let response = reqwest::get(format!("http://{}/COS5/cosmos/base/v1/height", addr))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[FALLBACK_HEADER], "direct-node");
+    assert_eq!(response.text().await.unwrap(), r#"{"height":"13"}"#);
+}
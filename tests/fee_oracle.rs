@@ -0,0 +1,140 @@
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::fee_oracle::{fee_recommendation, FeeOracleConfig, FeeRecommendation, FeeSuggestion};
+use lavap_rs::pairing::get_ranked_providers;
+
+mod common;
+use common::{pairing_state, replay, set_pairing};
+
+// The exact requests the oracle sends with the default config
+fn gas_price_request() -> Vec<u8> {
+    json!({"jsonrpc": "2.0", "id": 1, "method": "eth_gasPrice", "params": []})
+        .to_string()
+        .into_bytes()
+}
+
+fn fee_history_request() -> Vec<u8> {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "eth_feeHistory",
+        "params": ["0x14", "latest", [25.0, 50.0, 75.0]],
+    })
+    .to_string()
+    .into_bytes()
+}
+
+fn result(result: Value) -> Vec<u8> {
+    json!({"jsonrpc": "2.0", "id": 1, "result": result})
+        .to_string()
+        .into_bytes()
+}
+
+fn history(base_fee: &str, rewards: Value) -> Vec<u8> {
+    result(json!({"baseFeePerGas": ["0x1", base_fee], "reward": rewards}))
+}
+
+async fn recommend(
+    providers: &[&str],
+    gas_prices: &[Result<Vec<u8>, tonic::Status>],
+    histories: &[Result<Vec<u8>, tonic::Status>],
+) -> FeeRecommendation {
+    let (gas_price_request, fee_history_request) = (gas_price_request(), fee_history_request());
+    let mut canned = Vec::new();
+    for reply in gas_prices {
+        canned.push((
+            &gas_price_request[..],
+            reply.as_deref().map_err(Clone::clone),
+        ));
+    }
+    for reply in histories {
+        canned.push((
+            &fee_history_request[..],
+            reply.as_deref().map_err(Clone::clone),
+        ));
+    }
+
+    let state = pairing_state();
+    set_pairing(&state, 10, providers).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&canned));
+    let context = Arc::new(Mutex::new(context));
+    let providers = get_ranked_providers(state).await;
+    fee_recommendation(&context, &providers, &FeeOracleConfig::default())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn one_skewed_node_does_not_move_the_medians() {
+    // Only the top three are asked
+    let recommendation = recommend(
+        &["lava@a", "lava@b", "lava@c", "lava@d"],
+        &[
+            Ok(result(json!("0x64"))),
+            Ok(result(json!("0xc8"))),
+            Ok(result(json!("0x2710"))),
+        ],
+        &[
+            // Rewards are averaged over the blocks first, this one means [2, 3, 4]
+            Ok(history(
+                "0xa",
+                json!([["0x1", "0x2", "0x3"], ["0x3", "0x4", "0x5"]]),
+            )),
+            Ok(history("0x14", json!([["0x2", "0x4", "0x6"]]))),
+            Ok(history("0x3e8", json!([["0x64", "0x64", "0x64"]]))),
+        ],
+    )
+    .await;
+
+    assert_eq!(recommendation.providers.len(), 3);
+    assert_eq!(recommendation.gas_price.as_deref(), Some("0xc8"));
+    assert_eq!(recommendation.base_fee_per_gas.as_deref(), Some("0x14"));
+    let suggestion = |s: &Option<FeeSuggestion>| {
+        let s = s.as_ref().unwrap();
+        (
+            s.max_priority_fee_per_gas.clone(),
+            s.max_fee_per_gas.clone(),
+        )
+    };
+    // Twice the base fee plus the priority fee
+    assert_eq!(
+        suggestion(&recommendation.slow),
+        ("0x2".into(), "0x2a".into())
+    );
+    assert_eq!(
+        suggestion(&recommendation.standard),
+        ("0x4".into(), "0x2c".into())
+    );
+    assert_eq!(
+        suggestion(&recommendation.fast),
+        ("0x6".into(), "0x2e".into())
+    );
+}
+
+#[tokio::test]
+async fn an_even_count_takes_the_midpoint_and_silent_providers_are_left_out() {
+    let recommendation = recommend(
+        &["lava@a", "lava@b", "lava@c"],
+        &[
+            Ok(result(json!("0x64"))),
+            Ok(result(json!("0xc9"))),
+            Err(tonic::Status::unavailable("down")),
+        ],
+        &[
+            Err(tonic::Status::unavailable("down")),
+            Err(tonic::Status::unavailable("down")),
+            Err(tonic::Status::unavailable("down")),
+        ],
+    )
+    .await;
+
+    assert_eq!(recommendation.providers.len(), 2);
+    assert_eq!(recommendation.gas_price.as_deref(), Some("0x96"));
+    // Without fee history there's nothing to build EIP-1559 fees from
+    assert_eq!(recommendation.base_fee_per_gas, None);
+    assert!(recommendation.standard.is_none());
+}
@@ -0,0 +1,99 @@
+use axum::http::{HeaderMap, HeaderValue};
+use std::time::Duration;
+
+use lavap_rs::config::Config;
+use lavap_rs::geolocation::{parse_geolocation, GeolocationConfig};
+use lavap_rs::pairing::{build_geo_pools, Provider, ProviderEndpoint, RankedProvider};
+use lavap_rs::utils::SPEC_ID;
+
+mod common;
+
+const USC: u64 = 1;
+const EU: u64 = 2;
+const AS: u64 = 32;
+
+fn endpoint(address: &str, host: &str, geolocation: u64, latency_ms: u64) -> RankedProvider {
+    let endpoint = ProviderEndpoint {
+        address: format!("{}:443", host),
+        geolocation,
+        extensions: vec![],
+    };
+    let provider = Provider {
+        address: address.to_string(),
+        stake: 1,
+        endpoints: vec![endpoint.clone()],
+        latest_block: 0,
+    };
+    RankedProvider::new(provider, endpoint, Duration::from_millis(latency_ms), 10)
+}
+
+fn addresses(providers: &[RankedProvider]) -> Vec<&str> {
+    providers
+        .iter()
+        .map(|p| p.provider.address.as_str())
+        .collect()
+}
+
+#[test]
+fn regions_parse_by_code_or_bit() {
+    assert_eq!(parse_geolocation("EU"), Some(EU));
+    assert_eq!(parse_geolocation(" as "), Some(AS));
+    assert_eq!(parse_geolocation("2"), Some(EU));
+    // Only single known bits are regions
+    assert_eq!(parse_geolocation("3"), None);
+    assert_eq!(parse_geolocation("MARS"), None);
+}
+
+#[test]
+fn the_header_wins_over_the_default_region() {
+    let config = GeolocationConfig {
+        default: Some("USC".to_string()),
+        ..Default::default()
+    };
+    let mut headers = HeaderMap::new();
+    assert_eq!(config.resolve(&headers), Some(USC));
+    headers.insert("x-lava-geolocation", HeaderValue::from_static("eu"));
+    assert_eq!(config.resolve(&headers), Some(EU));
+    // An unreadable header falls back to the default rather than to no region
+    headers.insert("x-lava-geolocation", HeaderValue::from_static("nowhere"));
+    assert_eq!(config.resolve(&headers), Some(USC));
+
+    assert_eq!(
+        GeolocationConfig::default().resolve(&HeaderMap::new()),
+        None
+    );
+}
+
+#[test]
+fn pools_keep_the_ranking_and_one_endpoint_per_provider() {
+    // Already sorted by latency, as the probe leaves them
+    let ranked = vec![
+        endpoint("lava@a", "a-us", USC, 5),
+        endpoint("lava@b", "b-global", USC | EU, 10),
+        endpoint("lava@a", "a-eu", EU, 20),
+        endpoint("lava@c", "c-eu", EU, 30),
+    ];
+    let pools = build_geo_pools(&ranked);
+
+    assert_eq!(pools.len(), 2);
+    assert_eq!(addresses(&pools[&USC]), vec!["lava@a", "lava@b"]);
+    assert_eq!(addresses(&pools[&EU]), vec!["lava@b", "lava@a", "lava@c"]);
+    assert_eq!(pools[&EU][1].endpoint.address, "a-eu:443");
+    assert!(!pools.contains_key(&AS));
+}
+
+#[tokio::test]
+async fn requests_use_their_region_pool_or_the_global_ranking() {
+    let state = common::pairing_state();
+    common::set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    state.lock().await.geo_pools = build_geo_pools(&[endpoint("lava@b", "b-eu", EU, 10)]);
+    let mut context = common::context(&state, Config::default());
+
+    let eu = context.chain_ranked_providers(SPEC_ID, Some(EU)).await;
+    assert_eq!(addresses(&eu), vec!["lava@b"]);
+    // Nothing serves Asia, better a far provider than none
+    let asia = context.chain_ranked_providers(SPEC_ID, Some(AS)).await;
+    assert_eq!(addresses(&asia), vec!["lava@a", "lava@b"]);
+    let global = context.chain_ranked_providers(SPEC_ID, None).await;
+    assert_eq!(addresses(&global), vec!["lava@a", "lava@b"]);
+}
@@ -0,0 +1,137 @@
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::get_logs::{is_oversized, relay_get_logs, GetLogsConfig};
+
+mod common;
+use common::{pairing_state, ranked, replay, set_pairing};
+
+fn get_logs(from: &str, to: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 7,
+        "method": "eth_getLogs",
+        "params": [{"fromBlock": from, "toBlock": to, "address": "0xabc"}],
+    })
+}
+
+// The chunk relayed for from..=to, as the `index`th of the split
+fn chunk(request: &Value, index: usize, from: &str, to: &str) -> Vec<u8> {
+    let mut chunk = request.clone();
+    chunk["id"] = json!(index);
+    chunk["params"][0]["fromBlock"] = json!(from);
+    chunk["params"][0]["toBlock"] = json!(to);
+    serde_json::to_vec(&chunk).unwrap()
+}
+
+fn logs(id: usize, logs: &[&str]) -> Vec<u8> {
+    serde_json::to_vec(&json!({"jsonrpc": "2.0", "id": id, "result": logs})).unwrap()
+}
+
+fn config(max_block_range: u64, max_chunks: u64) -> GetLogsConfig {
+    GetLogsConfig {
+        max_block_range,
+        max_chunks,
+    }
+}
+
+#[test]
+fn only_numeric_ranges_over_the_limit_are_split() {
+    let limit = config(10, 20);
+    assert!(!is_oversized(&get_logs("0x0", "0x9"), &limit));
+    assert!(is_oversized(&get_logs("0x0", "0xa"), &limit));
+    // Reversed ranges, tags and block hashes go through as they are
+    assert!(!is_oversized(&get_logs("0xa", "0x0"), &limit));
+    assert!(!is_oversized(&get_logs("0x0", "latest"), &limit));
+    let mut by_hash = get_logs("0x0", "0xff");
+    by_hash["params"][0]["blockHash"] = json!("0x1234");
+    assert!(!is_oversized(&by_hash, &limit));
+    // 0 turns splitting off
+    assert!(!is_oversized(&get_logs("0x0", "0xffff"), &config(0, 20)));
+    // The whole u64 range doesn't wrap around to an empty one
+    assert!(is_oversized(&get_logs("0x0", "0xffffffffffffffff"), &limit));
+}
+
+#[tokio::test]
+async fn chunks_are_merged_in_block_order() {
+    let request = get_logs("0x0", "0x18");
+    let first = chunk(&request, 0, "0x0", "0x9");
+    let second = chunk(&request, 1, "0xa", "0x13");
+    let third = chunk(&request, 2, "0x14", "0x18");
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        (&first, Ok(&logs(0, &["a"]))),
+        (&second, Ok(&logs(1, &["b"]))),
+        (&third, Ok(&logs(2, &["c"]))),
+    ]));
+    let context = Arc::new(Mutex::new(context));
+
+    let providers = [ranked("lava@a", 10), ranked("lava@b", 10)];
+    let reply = relay_get_logs(&context, &providers, &request, &config(10, 20))
+        .await
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(
+        reply,
+        json!({"jsonrpc": "2.0", "id": 7, "result": ["a", "b", "c"]})
+    );
+}
+
+#[tokio::test]
+async fn a_failed_chunk_moves_on_to_the_next_provider() {
+    let request = get_logs("0x0", "0x13");
+    let first = chunk(&request, 0, "0x0", "0x9");
+    let second = chunk(&request, 1, "0xa", "0x13");
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        (&first, Err(tonic::Status::unavailable("connection reset"))),
+        (&first, Ok(&logs(0, &["a"]))),
+        (&second, Ok(&logs(1, &["b"]))),
+    ]));
+    let context = Arc::new(Mutex::new(context));
+
+    let providers = [ranked("lava@a", 10), ranked("lava@b", 10)];
+    let reply = relay_get_logs(&context, &providers, &request, &config(10, 20))
+        .await
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(reply["result"], json!(["a", "b"]));
+}
+
+#[tokio::test]
+async fn a_chunk_no_provider_serves_fails_the_request() {
+    let request = get_logs("0x0", "0x13");
+    let second = chunk(&request, 1, "0xa", "0x13");
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    // Nothing recorded for the first chunk, every provider fails it
+    context.recorder = Arc::new(replay(&[(&second, Ok(&logs(1, &["b"])))]));
+    let context = Arc::new(Mutex::new(context));
+
+    let providers = [ranked("lava@a", 10), ranked("lava@b", 10)];
+    let result = relay_get_logs(&context, &providers, &request, &config(10, 20)).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn ranges_over_max_chunks_are_refused() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let context = Arc::new(Mutex::new(common::context(&state, Config::default())));
+
+    let request = get_logs("0x0", "0x1e");
+    let reply = relay_get_logs(&context, &[ranked("lava@a", 10)], &request, &config(10, 3))
+        .await
+        .unwrap();
+    let reply: Value = serde_json::from_slice(&reply).unwrap();
+    assert_eq!(reply["id"], 7);
+    assert_eq!(reply["error"]["code"], -32005);
+    assert_eq!(context.lock().await.session_count(), 0);
+}
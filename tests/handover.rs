@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use lavap_rs::handover::{bind, Drain, HandoverConfig};
+
+#[cfg(unix)]
+#[tokio::test]
+async fn an_upgraded_process_can_bind_the_same_port_only_when_allowed() {
+    let config = HandoverConfig {
+        reuse_port: true,
+        ..Default::default()
+    };
+    let old = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+    let addr = old.local_addr().unwrap();
+    let new = bind(addr, &config).unwrap();
+    assert_eq!(new.local_addr().unwrap(), addr);
+
+    let old = bind("127.0.0.1:0".parse().unwrap(), &HandoverConfig::default()).unwrap();
+    let addr = old.local_addr().unwrap();
+    assert!(bind(addr, &HandoverConfig::default()).is_err());
+}
+
+#[tokio::test]
+async fn the_drain_waits_for_every_tracked_connection() {
+    let drain = Arc::new(Drain::default());
+    // Nothing open, nothing to wait for
+    drain.wait_idle().await;
+
+    let first = drain.track();
+    let second = drain.track();
+    assert_eq!(drain.active(), 2);
+    let waiting = tokio::spawn({
+        let drain = Arc::clone(&drain);
+        async move { drain.wait_idle().await }
+    });
+
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiting.is_finished());
+    drop(second);
+    tokio::time::timeout(Duration::from_secs(1), waiting)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(drain.active(), 0);
+}
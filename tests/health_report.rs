@@ -0,0 +1,99 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::Value;
+use std::future::IntoFuture;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+use lavap_rs::config::Config;
+use lavap_rs::health_report::{health_report, health_report_task};
+use lavap_rs::session_context::RELAY_CU;
+use lavap_rs::utils::SPEC_ID;
+
+mod common;
+use common::{pairing_state, set_pairing};
+
+#[tokio::test]
+async fn the_report_sums_up_chains_and_relays() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    state.lock().await.params.max_cu = 1000;
+    let near = pairing_state();
+    set_pairing(&near, 4, &[]).await;
+    let mut context = common::context(&state, Config::default());
+    context.chains.insert("NEAR".to_string(), near);
+    context.get_or_create_session(SPEC_ID, 10, "lava@a");
+    context.update_session(SPEC_ID, 10, "lava@a", RELAY_CU);
+    {
+        let mut stats = context.stats.lock().await;
+        for success in [true, true, true, false] {
+            stats.record("lava@a", success, Duration::from_millis(10));
+        }
+    }
+    let context = Arc::new(Mutex::new(context));
+
+    let report = health_report(&context).await;
+    assert!(report.consumer.starts_with("lava@"));
+    let chains: Vec<_> = report
+        .chains
+        .iter()
+        .map(|c| {
+            (
+                c.spec_id.as_str(),
+                c.epoch,
+                c.providers_reachable,
+                c.cu_used,
+                c.max_cu,
+            )
+        })
+        .collect();
+    assert_eq!(
+        chains,
+        vec![(SPEC_ID, 10, 2, RELAY_CU, 1000), ("NEAR", 4, 0, 0, 0)]
+    );
+    assert_eq!(report.relays, 4);
+    assert_eq!(report.relay_errors, 1);
+    assert_eq!(report.relay_success_rate, 0.75);
+    assert_eq!(report.providers.len(), 1);
+}
+
+#[tokio::test]
+async fn reports_are_posted_with_the_configured_headers() {
+    let (tx, mut rx) = mpsc::channel(1);
+    let sink = Router::new()
+        .route(
+            "/health",
+            post(
+                |State(tx): State<mpsc::Sender<(HeaderMap, Value)>>,
+                 headers: HeaderMap,
+                 Json(report): Json<Value>| async move {
+                    let _ = tx.send((headers, report)).await;
+                },
+            ),
+        )
+        .with_state(tx);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::serve(listener, sink).into_future());
+
+    let mut config = Config::default();
+    config.health_report.url = Some(format!("http://{}/health", addr));
+    config
+        .health_report
+        .headers
+        .insert("Authorization".to_string(), "Bearer secret".to_string());
+    let context = common::context(&pairing_state(), config);
+    let task = tokio::spawn(health_report_task(Arc::new(Mutex::new(context))));
+
+    let (headers, report) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    task.abort();
+    assert_eq!(headers["authorization"], "Bearer secret");
+    assert_eq!(report["relay_success_rate"], 1.0);
+    assert_eq!(report["chains"][0]["spec_id"], SPEC_ID);
+}
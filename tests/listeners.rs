@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::future::IntoFuture;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::listeners::{chain_router, ListenConfig};
+use lavap_rs::relay::{jsonrpc_relay_data, rest_relay_data};
+
+mod common;
+use common::{pairing_state, replay_relays, set_pairing};
+
+const REQUEST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"block","params":{"finality":"final"}}"#;
+
+#[test]
+fn every_port_is_bound_once() {
+    let config = |chains: &[(&str, u16)]| ListenConfig {
+        chains: chains
+            .iter()
+            .map(|(spec_id, port)| (spec_id.to_string(), *port))
+            .collect::<HashMap<_, _>>(),
+        ..Default::default()
+    };
+    assert!(config(&[]).validate().is_ok());
+    assert!(config(&[("NEAR", 3001), ("LAV1", 3002)]).validate().is_ok());
+    assert_eq!(
+        config(&[("NEAR", 3000)]).validate().unwrap_err(),
+        "Port 3000 is set for both the main router and NEAR"
+    );
+    assert_eq!(
+        config(&[("NEAR", 3001), ("LAV1", 3001)])
+            .validate()
+            .unwrap_err(),
+        "Port 3001 is set for both LAV1 and NEAR"
+    );
+}
+
+#[tokio::test]
+async fn a_chain_port_serves_its_spec_at_the_root() {
+    let chain_state = pairing_state();
+    set_pairing(&chain_state, 10, &["lava@a"]).await;
+    let mut context = common::context(&pairing_state(), Config::default());
+    context.chains.insert("NEAR".to_string(), chain_state);
+    context.recorder = Arc::new(replay_relays(&[
+        (
+            jsonrpc_relay_data(REQUEST.as_bytes().to_vec(), vec![]),
+            Ok(br#"{"jsonrpc":"2.0","id":1,"result":{"header":{}}}"#),
+        ),
+        (
+            rest_relay_data("GET", "/status?verbose=1".to_string(), vec![]),
+            Ok(br#"{"version":"1"}"#),
+        ),
+    ]));
+    let app = chain_router(Arc::new(Mutex::new(context)), "NEAR".to_string());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::serve(listener, app).into_future());
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("http://{}/", addr))
+        .body(REQUEST)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"jsonrpc":"2.0","id":1,"result":{"header":{}}}"#
+    );
+
+    let response = client
+        .get(format!("http://{}/status?verbose=1", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), r#"{"version":"1"}"#);
+}
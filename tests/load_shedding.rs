@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use lavap_rs::config::Config;
+use lavap_rs::load_shedding::LoadSheddingConfig;
+use lavap_rs::priority::Priority;
+use lavap_rs::provider_stats::{ProviderStats, ProviderSummary};
+
+mod common;
+
+fn summary(
+    address: &str,
+    requests: usize,
+    error_rate: f64,
+    p95_latency_ms: u64,
+) -> ProviderSummary {
+    ProviderSummary {
+        address: address.to_string(),
+        requests,
+        errors: (requests as f64 * error_rate) as usize,
+        error_rate,
+        p50_latency_ms: p95_latency_ms / 2,
+        p95_latency_ms,
+        dissents: 0,
+    }
+}
+
+fn config(shed_fraction: f64) -> LoadSheddingConfig {
+    LoadSheddingConfig {
+        enabled: true,
+        shed_fraction,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn most_sampled_providers_have_to_be_degraded() {
+    let config = config(1.0);
+    let healthy = summary("lava@a", 20, 0.0, 100);
+    let failing = summary("lava@b", 20, 0.6, 100);
+    let slow = summary("lava@c", 20, 0.0, 6000);
+    // Too few samples to judge, left out of the count
+    let fresh = summary("lava@d", 2, 0.0, 100);
+
+    assert!(config.is_degraded(&[failing.clone(), slow.clone(), fresh.clone()]));
+    assert!(!config.is_degraded(&[failing.clone(), slow.clone(), healthy.clone()]));
+    assert!(config.is_degraded(&[
+        failing.clone(),
+        slow.clone(),
+        summary("lava@e", 20, 0.5, 100),
+        healthy
+    ]));
+    assert!(!config.is_degraded(&[fresh]));
+    assert!(!config.is_degraded(&[]));
+}
+
+#[test]
+fn batch_goes_first_and_interactive_by_fraction() {
+    let degraded = [summary("lava@a", 20, 1.0, 100)];
+
+    assert!(config(0.0001).should_shed(&degraded, Priority::Batch));
+    assert!(config(1.0).should_shed(&degraded, Priority::Interactive));
+    assert!(!config(0.0).should_shed(&degraded, Priority::Batch));
+    let shed = (0..1000)
+        .filter(|_| config(0.5).should_shed(&degraded, Priority::Interactive))
+        .count();
+    assert!((350..650).contains(&shed), "shed {} of 1000", shed);
+
+    let disabled = LoadSheddingConfig {
+        enabled: false,
+        ..config(1.0)
+    };
+    assert!(!disabled.should_shed(&degraded, Priority::Batch));
+    assert!(!config(1.0).should_shed(&[summary("lava@a", 20, 0.0, 100)], Priority::Batch));
+}
+
+#[tokio::test]
+async fn shed_requests_get_a_fast_explicit_error() {
+    let mut config = Config::default();
+    config.load_shedding = self::config(1.0);
+    let state = common::pairing_state();
+    common::set_pairing(&state, 10, &["lava@a"]).await;
+    let context = common::context(&state, config);
+    {
+        let mut stats = context.stats.lock().await;
+        for _ in 0..20 {
+            stats.record("lava@a", false, Duration::from_millis(10));
+        }
+    }
+    let addr = common::serve(context).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/", addr))
+        .body(r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["retry-after"], "5");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32005);
+}
+
+#[test]
+fn summaries_come_from_recorded_outcomes() {
+    let mut stats = ProviderStats::default();
+    for i in 0..10 {
+        stats.record("lava@a", i % 5 != 0, Duration::from_millis(10 * (i + 1)));
+    }
+    let summaries = stats.summaries();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].requests, 10);
+    assert_eq!(summaries[0].errors, 2);
+    assert_eq!(summaries[0].error_rate, 0.2);
+}
@@ -0,0 +1,94 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::loadtest::{mix_from_methods, mix_from_payload, run, validate_rps, Target, MAX_RPS};
+use lavap_rs::server::router;
+use lavap_rs::spec::ChainSpec;
+
+mod common;
+use common::{pairing_state, replay, set_pairing, Canned};
+
+#[test]
+fn mixes_come_from_methods_or_payloads() {
+    let mix = mix_from_methods(&["eth_blockNumber".to_string(), "eth_chainId".to_string()]);
+    assert_eq!(
+        mix,
+        vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": []}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": []}),
+        ]
+    );
+
+    let single = json!({"jsonrpc": "2.0", "id": 1, "method": "eth_call", "params": []});
+    assert_eq!(mix_from_payload(&single), vec![single.clone()]);
+    // A list is sent in turn, a list inside it is one batch
+    let payload = json!([single, [single, single]]);
+    let mix = mix_from_payload(&payload);
+    assert_eq!(mix.len(), 2);
+    assert!(mix[1].is_array());
+}
+
+#[test]
+fn rates_stay_within_what_one_process_can_send() {
+    assert!(validate_rps(0.5).is_ok());
+    assert!(validate_rps(MAX_RPS).is_ok());
+    assert!(validate_rps(0.0).is_err());
+    assert!(validate_rps(-1.0).is_err());
+    assert!(validate_rps(MAX_RPS + 1.0).is_err());
+    assert!(validate_rps(f64::NAN).is_err());
+}
+
+#[tokio::test]
+async fn load_goes_through_the_consumer_and_is_reported_per_method() {
+    let mix = mix_from_methods(&["eth_blockNumber".to_string(), "eth_call".to_string()]);
+    let (block_number, call) = (mix[0].to_string(), mix[1].to_string());
+    let mut canned: Vec<Canned> = Vec::new();
+    for _ in 0..50 {
+        canned.push((
+            block_number.as_bytes(),
+            Ok(br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#),
+        ));
+        // Answered, but with an error: counted as failed and not charged
+        canned.push((
+            call.as_bytes(),
+            Ok(br#"{"jsonrpc":"2.0","id":1,"error":{"code":3,"message":"reverted"}}"#),
+        ));
+    }
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&canned));
+    let target = Arc::new(Target::Local(router(Arc::new(Mutex::new(context))).await));
+    let spec = ChainSpec::parse(&json!({
+        "index": "ETH1",
+        "api_collections": [{
+            "collection_data": {"api_interface": "jsonrpc"},
+            "apis": [
+                {"name": "eth_blockNumber", "compute_units": "5"},
+                {"name": "eth_call", "compute_units": "20"},
+            ],
+        }],
+    }));
+
+    let report = run(target, &mix, &spec, 40.0, Duration::from_millis(500)).await;
+
+    let block_number = &report.methods["eth_blockNumber"];
+    let call = &report.methods["eth_call"];
+    assert!(block_number.latency.requests >= 5, "{:?}", report);
+    assert_eq!(block_number.latency.errors, 0);
+    assert_eq!(call.latency.errors, call.latency.requests);
+    assert_eq!(
+        report.latency.requests,
+        block_number.latency.requests + call.latency.requests
+    );
+    assert_eq!(
+        block_number.compute_units,
+        5 * block_number.latency.requests as u64
+    );
+    assert_eq!(call.compute_units, 0);
+    assert_eq!(report.cu_consumed, block_number.compute_units);
+    assert!(report.throughput_rps > 0.0);
+}
@@ -0,0 +1,146 @@
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use lavap_rs::middleware::{Middleware, MiddlewareChain, MiddlewareConfig};
+
+fn chain(rules: Value) -> MiddlewareChain {
+    let config: MiddlewareConfig = serde_json::from_value(json!({ "rules": rules })).unwrap();
+    MiddlewareChain::new(&config)
+}
+
+fn call(id: u64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+#[test]
+fn request_rules_apply_in_order_to_every_call_of_a_batch() {
+    let chain = chain(json!([
+        {"type": "rewrite_method", "from": "eth_old", "to": "eth_new"},
+        {"type": "sanitize_params", "method": "eth_new", "max_params": 1, "drop_fields": ["secret"]},
+    ]));
+    let mut batch = json!([
+        call(
+            1,
+            "eth_old",
+            json!([{"to": "0x1", "secret": "x"}, "latest"])
+        ),
+        call(2, "eth_other", json!([{"secret": "y"}, "latest"])),
+    ]);
+    chain.apply_request(&mut batch).unwrap();
+    assert_eq!(batch[0], call(1, "eth_new", json!([{"to": "0x1"}])));
+    // Scoped to eth_new, the other call is untouched
+    assert_eq!(
+        batch[1],
+        call(2, "eth_other", json!([{"secret": "y"}, "latest"]))
+    );
+}
+
+#[test]
+fn a_denied_method_rejects_the_whole_request() {
+    let chain = chain(json!([{"type": "deny", "methods": ["debug_traceTransaction"]}]));
+    let mut single = call(1, "debug_traceTransaction", json!([]));
+    assert_eq!(
+        chain.apply_request(&mut single),
+        Err("Method debug_traceTransaction is not allowed".to_string())
+    );
+    let mut batch = json!([
+        call(1, "eth_blockNumber", json!([])),
+        call(2, "debug_traceTransaction", json!([])),
+    ]);
+    assert!(chain.apply_request(&mut batch).is_err());
+    assert!(chain
+        .apply_request(&mut call(1, "eth_blockNumber", json!([])))
+        .is_ok());
+}
+
+#[test]
+fn response_rules_redact_and_inject() {
+    let chain = chain(json!([
+        {"type": "redact_response", "method": "eth_getTransactionByHash",
+         "pointers": ["/result/from", "/result/logs/1"]},
+        {"type": "inject_field", "pointer": "/result/served_by", "value": "lava"},
+    ]));
+    let request = call(1, "eth_getTransactionByHash", json!(["0xabc"]));
+    let mut response = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "result": {"from": "0xdead", "to": "0xbeef", "logs": ["a", "b", "c"]},
+    });
+    chain.apply_response(&request, &mut response);
+    assert_eq!(
+        response["result"],
+        json!({"to": "0xbeef", "logs": ["a", null, "c"], "served_by": "lava"})
+    );
+}
+
+#[test]
+fn batch_replies_are_matched_to_their_calls_by_id() {
+    let chain = chain(json!([
+        {"type": "redact_response", "method": "eth_getBalance", "pointers": ["/result"]},
+        {"type": "inject_field", "pointer": "/served_by", "value": "lava"},
+    ]));
+    let batch = json!([
+        call(1, "eth_getBalance", json!(["0x1"])),
+        call(2, "eth_blockNumber", json!([])),
+    ]);
+    // Reordered by the node, plus a reply no call claims
+    let mut response = json!([
+        {"jsonrpc": "2.0", "id": 2, "result": "0x10"},
+        {"jsonrpc": "2.0", "id": 1, "result": "0x64"},
+        {"jsonrpc": "2.0", "id": 9, "result": "0x1"},
+    ]);
+    chain.apply_response(&batch, &mut response);
+    assert_eq!(response[0]["result"], "0x10");
+    assert_eq!(response[1].get("result"), None);
+    // Unmatched, so only the unscoped rule applies
+    assert_eq!(response[2]["result"], "0x1");
+    for reply in response.as_array().unwrap() {
+        assert_eq!(reply["served_by"], "lava");
+    }
+}
+
+#[test]
+fn response_rules_see_the_method_the_client_called() {
+    let chain = chain(json!([
+        {"type": "rewrite_method", "from": "eth_old", "to": "eth_new"},
+        {"type": "redact_response", "method": "eth_old", "pointers": ["/result"]},
+    ]));
+    let original = call(1, "eth_old", json!([]));
+    let mut request = original.clone();
+    chain.apply_request(&mut request).unwrap();
+    assert_eq!(request["method"], "eth_new");
+
+    let mut response = json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"});
+    chain.apply_response(&original, &mut response);
+    assert_eq!(response.get("result"), None);
+}
+
+struct Tag(&'static str);
+
+impl Middleware for Tag {
+    fn on_request(&self, call: &mut Value) -> Result<(), String> {
+        let tags = call["tags"].as_str().unwrap_or_default().to_string();
+        call["tags"] = json!(tags + self.0);
+        Ok(())
+    }
+
+    fn on_response(&self, _call: &Value, response: &mut Value) {
+        let tags = response["tags"].as_str().unwrap_or_default().to_string();
+        response["tags"] = json!(tags + self.0);
+    }
+}
+
+#[test]
+fn layers_wrap_the_relay_in_reverse_order_on_the_way_back() {
+    let chain = MiddlewareChain::default()
+        .with(Arc::new(Tag("a")))
+        .with(Arc::new(Tag("b")));
+    assert!(!chain.is_empty());
+    let mut request = call(1, "eth_blockNumber", json!([]));
+    chain.apply_request(&mut request).unwrap();
+    assert_eq!(request["tags"], "ab");
+
+    let mut response = json!({"jsonrpc": "2.0", "id": 1, "result": "0x1"});
+    chain.apply_response(&request, &mut response);
+    assert_eq!(response["tags"], "ba");
+}
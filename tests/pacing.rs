@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+use lavap_rs::pacing::{CuPacer, PacingConfig};
+
+// 1000 CU over a 100s epoch is 10 CU/s, with a burst of 100 CU
+const LIMIT: u64 = 1000;
+const EPOCH: Duration = Duration::from_secs(100);
+
+fn pacer() -> CuPacer {
+    CuPacer::new(PacingConfig {
+        enabled: true,
+        burst_fraction: 0.1,
+        max_wait_ms: 1000,
+    })
+}
+
+#[test]
+fn spend_beyond_the_burst_waits_for_the_refill() {
+    let mut pacer = pacer();
+    let now = Instant::now();
+    let a = ("ETH1", 10, "lava@a");
+
+    for _ in 0..10 {
+        assert_eq!(pacer.reserve(a, 10, LIMIT, EPOCH, now), Ok(Duration::ZERO));
+    }
+    assert_eq!(
+        pacer.reserve(a, 10, LIMIT, EPOCH, now),
+        Ok(Duration::from_secs(1))
+    );
+    // Waiting longer than max_wait is refused, and reserves nothing
+    assert_eq!(
+        pacer.reserve(a, 10, LIMIT, EPOCH, now),
+        Err(Duration::from_secs(2))
+    );
+    // Other providers have buckets of their own
+    assert_eq!(
+        pacer.reserve(("ETH1", 10, "lava@b"), 10, LIMIT, EPOCH, now),
+        Ok(Duration::ZERO)
+    );
+    let later = now + Duration::from_secs(3);
+    assert_eq!(
+        pacer.reserve(a, 10, LIMIT, EPOCH, later),
+        Ok(Duration::ZERO)
+    );
+
+    let metrics = pacer.metrics(later);
+    assert_eq!(
+        (metrics.admitted, metrics.delayed, metrics.rejected),
+        (12, 1, 1)
+    );
+    assert_eq!(metrics.total_delay_ms, 1000);
+    assert_eq!(metrics.buckets.len(), 2);
+    assert_eq!(metrics.buckets[0].rate_cu_per_sec, 10.0);
+    assert_eq!(metrics.buckets[0].capacity, 100.0);
+}
+
+#[test]
+fn a_relay_larger_than_the_burst_still_fits() {
+    let mut pacer = pacer();
+    let now = Instant::now();
+    assert_eq!(
+        pacer.reserve(("ETH1", 10, "lava@a"), 500, LIMIT, EPOCH, now),
+        Ok(Duration::ZERO)
+    );
+}
+
+#[test]
+fn buckets_of_past_epochs_are_dropped() {
+    let mut pacer = pacer();
+    let now = Instant::now();
+    for epoch in [8, 9, 10] {
+        pacer
+            .reserve(("ETH1", epoch, "lava@a"), 10, LIMIT, EPOCH, now)
+            .unwrap();
+    }
+    pacer
+        .reserve(("NEAR", 1, "lava@a"), 10, LIMIT, EPOCH, now)
+        .unwrap();
+    let epochs: Vec<_> = pacer
+        .metrics(now)
+        .buckets
+        .iter()
+        .map(|b| (b.spec_id.clone(), b.epoch))
+        .collect();
+    assert_eq!(
+        epochs,
+        vec![
+            ("ETH1".to_string(), 9),
+            ("ETH1".to_string(), 10),
+            ("NEAR".to_string(), 1)
+        ]
+    );
+}
+
+#[test]
+fn pacing_stays_out_of_the_way_when_off_or_unknown() {
+    let now = Instant::now();
+    let mut off = CuPacer::new(PacingConfig::default());
+    for _ in 0..100 {
+        assert_eq!(
+            off.reserve(("ETH1", 10, "lava@a"), 100, LIMIT, EPOCH, now),
+            Ok(Duration::ZERO)
+        );
+    }
+    // No limit or epoch length known yet
+    let mut pacer = pacer();
+    assert_eq!(
+        pacer.reserve(("ETH1", 10, "lava@a"), 5000, 0, EPOCH, now),
+        Ok(Duration::ZERO)
+    );
+    assert_eq!(
+        pacer.reserve(("ETH1", 10, "lava@a"), 5000, LIMIT, Duration::ZERO, now),
+        Ok(Duration::ZERO)
+    );
+}
@@ -0,0 +1,89 @@
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lavap_rs::priority::{Priority, PriorityConfig, PriorityGate};
+
+fn config() -> PriorityConfig {
+    let mut config = PriorityConfig {
+        batch_concurrency: 1,
+        batch_queue_limit: 1,
+        batch_queue_timeout_ms: 50,
+        ..Default::default()
+    };
+    config.keys.insert("indexer".to_string(), Priority::Batch);
+    config
+}
+
+#[test]
+fn api_keys_pick_the_class() {
+    let config = config();
+    let mut headers = HeaderMap::new();
+    assert_eq!(config.resolve(&headers), Priority::Interactive);
+    headers.insert("x-api-key", HeaderValue::from_static("indexer"));
+    assert_eq!(config.resolve(&headers), Priority::Batch);
+    headers.insert("x-api-key", HeaderValue::from_static("unknown"));
+    assert_eq!(config.resolve(&headers), Priority::Interactive);
+}
+
+#[tokio::test]
+async fn batch_traffic_is_only_throttled_under_pressure() {
+    let gate = PriorityGate::new(config());
+    assert!(gate
+        .admit(Priority::Batch, Some(0.5))
+        .await
+        .unwrap()
+        .is_none());
+
+    // CU pressure alone is enough
+    let slot = gate.admit(Priority::Batch, Some(0.9)).await.unwrap();
+    assert!(slot.is_some());
+    // Interactive traffic never waits
+    assert!(gate
+        .admit(Priority::Interactive, Some(0.9))
+        .await
+        .unwrap()
+        .is_none());
+    // The one slot is taken, the queued request gives up after its timeout
+    assert_eq!(
+        gate.admit(Priority::Batch, Some(0.9)).await.unwrap_err(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[tokio::test]
+async fn failing_relays_put_the_gate_under_pressure() {
+    let gate = PriorityGate::new(config());
+    for success in [true, true, true, false] {
+        gate.record(success).await;
+    }
+    assert_eq!(gate.error_rate().await, 0.25);
+    assert!(gate.under_pressure(None).await);
+    gate.record(true).await;
+    assert!(!gate.under_pressure(None).await);
+}
+
+#[tokio::test]
+async fn a_full_queue_sheds_right_away() {
+    let gate = Arc::new(PriorityGate::new(PriorityConfig {
+        batch_queue_timeout_ms: 10_000,
+        ..config()
+    }));
+    let slot = gate.admit(Priority::Batch, Some(1.0)).await.unwrap();
+    let waiting = tokio::spawn({
+        let gate = gate.clone();
+        async move { gate.admit(Priority::Batch, Some(1.0)).await.is_ok() }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let start = Instant::now();
+    assert_eq!(
+        gate.admit(Priority::Batch, Some(1.0)).await.unwrap_err(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The waiting request gets the slot once it's released
+    drop(slot);
+    assert!(waiting.await.unwrap());
+}
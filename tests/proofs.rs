@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use lavap_rs::proofs::{export_proofs, export_stored_proofs, ProofLog, ProofsConfig, RelayProof};
+use lavap_rs::proto::RelaySession;
+use lavap_rs::storage::{MemoryStorage, Storage};
+
+mod common;
+
+fn session(provider: &str, epoch: i64, session_id: u64, relay_num: u64) -> RelaySession {
+    RelaySession {
+        provider: provider.to_string(),
+        spec_id: "ETH1".to_string(),
+        epoch,
+        session_id,
+        relay_num,
+        cu_sum: relay_num * 10,
+        sig: vec![relay_num as u8],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn only_the_last_relay_of_each_session_is_claimed() {
+    let path = common::temp_path("proofs");
+    let config = ProofsConfig {
+        path: Some(path.to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let log = ProofLog::new(&config, Arc::clone(&storage), "lava@consumer").unwrap();
+    for relay_num in 1..=3 {
+        log.record(&session("lava@a", 10, 1, relay_num)).await;
+    }
+    log.record(&session("lava@a", 10, 2, 1)).await;
+    log.record(&session("lava@b", 10, 3, 2)).await;
+
+    let export = export_proofs(path.to_str().unwrap()).unwrap();
+    assert_eq!(export.proofs.len(), 3);
+    let claimed: Vec<_> = export
+        .proofs
+        .iter()
+        .map(|p| (p.provider.as_str(), p.session_id, p.relay_num, p.cu_sum))
+        .collect();
+    assert_eq!(
+        claimed,
+        vec![
+            ("lava@a", 1, 3, 30),
+            ("lava@a", 2, 1, 10),
+            ("lava@b", 3, 2, 20)
+        ]
+    );
+    let payouts: Vec<_> = export
+        .payouts
+        .iter()
+        .map(|p| (p.provider.as_str(), p.epoch, p.sessions, p.relays, p.cu))
+        .collect();
+    assert_eq!(
+        payouts,
+        vec![("lava@a", 10, 2, 4, 40), ("lava@b", 10, 1, 2, 20)]
+    );
+
+    // Storage keeps the same latest proofs, for consumers without a proof file
+    let stored = export_stored_proofs(&*storage, "lava@consumer");
+    assert_eq!(stored.proofs.len(), 3);
+    assert_eq!(stored.payouts.len(), 2);
+    assert!(export_stored_proofs(&*storage, "lava@other")
+        .proofs
+        .is_empty());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn an_older_relay_read_later_does_not_replace_a_newer_one() {
+    let path = common::temp_path("proofs");
+    let lines: Vec<String> = [3, 1, 2]
+        .iter()
+        .map(|relay_num| {
+            let proof = RelayProof::from_session(&session("lava@a", 10, 1, *relay_num));
+            serde_json::to_string(&proof).unwrap()
+        })
+        .collect();
+    std::fs::write(&path, lines.join("\n\n")).unwrap();
+
+    let export = export_proofs(path.to_str().unwrap()).unwrap();
+    assert_eq!(export.proofs.len(), 1);
+    assert_eq!(export.proofs[0].relay_num, 3);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn stored_proofs_past_the_payment_window_are_dropped() {
+    let config = ProofsConfig {
+        keep_epochs: 5,
+        ..Default::default()
+    };
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let log = ProofLog::new(&config, Arc::clone(&storage), "lava@consumer").unwrap();
+    log.record(&session("lava@a", 10, 1, 1)).await;
+    log.record(&session("lava@a", 15, 2, 1)).await;
+    log.record(&session("lava@a", 16, 3, 1)).await;
+
+    // Pruning runs off the runtime's workers
+    for _ in 0..100 {
+        if export_stored_proofs(&*storage, "lava@consumer")
+            .proofs
+            .len()
+            == 2
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let epochs: Vec<_> = export_stored_proofs(&*storage, "lava@consumer")
+        .proofs
+        .iter()
+        .map(|p| p.epoch)
+        .collect();
+    assert_eq!(epochs, vec![15, 16]);
+}
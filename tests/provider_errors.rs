@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Status};
+
+use lavap_rs::config::Config;
+use lavap_rs::provider_errors::{
+    classify, retry_after, BackoffConfig, ProviderBackoff, ProviderError,
+};
+use lavap_rs::relay::relay;
+
+mod common;
+use common::{pairing_state, ranked, replay, set_pairing};
+
+const REQUEST: &[u8] = br#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#;
+
+#[test]
+fn statuses_are_classified_by_name_message_and_code() {
+    let cases = [
+        (
+            Code::Unknown,
+            "InvalidEpoch: requested epoch 9",
+            ProviderError::EpochMismatch,
+        ),
+        (
+            Code::Unknown,
+            "EpochIsNotRegistered",
+            ProviderError::EpochMismatch,
+        ),
+        (
+            Code::Unknown,
+            "relay number mismatch",
+            ProviderError::SessionOutOfSync,
+        ),
+        (
+            Code::Unknown,
+            "SessionOutOfSync",
+            ProviderError::SessionOutOfSync,
+        ),
+        (
+            Code::ResourceExhausted,
+            "slow down",
+            ProviderError::Overloaded,
+        ),
+        (
+            Code::Unknown,
+            "Too Many Requests",
+            ProviderError::Overloaded,
+        ),
+        (
+            Code::Unavailable,
+            "connection refused",
+            ProviderError::Unavailable,
+        ),
+        (
+            Code::DeadlineExceeded,
+            "timeout",
+            ProviderError::Unavailable,
+        ),
+        (Code::Internal, "execution failed", ProviderError::Other),
+        // The message says more than the code
+        (
+            Code::Unavailable,
+            "epoch mismatch",
+            ProviderError::EpochMismatch,
+        ),
+    ];
+    for (code, message, expected) in cases {
+        assert_eq!(
+            classify(&Status::new(code, message)),
+            expected,
+            "{}",
+            message
+        );
+    }
+}
+
+#[test]
+fn retry_after_is_read_in_seconds() {
+    let mut status = Status::resource_exhausted("slow down");
+    assert_eq!(retry_after(&status), None);
+    status
+        .metadata_mut()
+        .insert("retry-after", MetadataValue::from_static(" 30 "));
+    assert_eq!(retry_after(&status), Some(Duration::from_secs(30)));
+    status
+        .metadata_mut()
+        .insert("retry-after", MetadataValue::from_static("soon"));
+    assert_eq!(retry_after(&status), None);
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap_and_honours_retry_after() {
+    let mut backoff = ProviderBackoff::new(BackoffConfig {
+        base_secs: 5,
+        max_secs: 30,
+    });
+    let now = Instant::now();
+    let secs = |d: Duration| d.as_secs();
+
+    assert_eq!(secs(backoff.back_off("lava@a", None, now)), 5);
+    assert_eq!(secs(backoff.back_off("lava@a", None, now)), 10);
+    assert_eq!(secs(backoff.back_off("lava@a", None, now)), 20);
+    assert_eq!(secs(backoff.back_off("lava@a", None, now)), 30);
+    assert!(backoff.is_backed_off("lava@a", now + Duration::from_secs(29)));
+    assert!(!backoff.is_backed_off("lava@a", now + Duration::from_secs(30)));
+
+    // A longer retry-after wins, but only up to the cap
+    let requested = Some(Duration::from_secs(25));
+    assert_eq!(secs(backoff.back_off("lava@b", requested, now)), 25);
+    let requested = Some(Duration::from_secs(3600));
+    assert_eq!(secs(backoff.back_off("lava@c", requested, now)), 30);
+
+    // A success starts the next backoff from scratch
+    backoff.record_success("lava@a");
+    assert!(!backoff.is_backed_off("lava@a", now));
+    assert_eq!(secs(backoff.back_off("lava@a", None, now)), 5);
+}
+
+#[tokio::test]
+async fn overloaded_providers_go_last() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[(
+        REQUEST,
+        Err(Status::resource_exhausted("rate limit")),
+    )]));
+    let context = Arc::new(Mutex::new(context));
+
+    assert!(relay(&context, &ranked("lava@a", 10), REQUEST.to_vec())
+        .await
+        .is_err());
+
+    let context = context.lock().await;
+    assert!(context.backoff.is_backed_off("lava@a", Instant::now()));
+    let ranked = state.lock().await.ranked_providers.clone();
+    let order: Vec<_> = context
+        .without_backed_off(ranked)
+        .into_iter()
+        .map(|p| p.provider.address)
+        .collect();
+    assert_eq!(order, vec!["lava@b", "lava@a"]);
+}
+
+#[tokio::test]
+async fn a_rejected_epoch_resets_the_session_and_resyncs_once() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        (REQUEST, Err(Status::unknown("InvalidEpoch"))),
+        (REQUEST, Err(Status::unknown("InvalidEpoch"))),
+    ]));
+    let context = Arc::new(Mutex::new(context));
+
+    for _ in 0..2 {
+        assert!(relay(&context, &ranked("lava@a", 10), REQUEST.to_vec())
+            .await
+            .is_err());
+    }
+
+    assert_eq!(context.lock().await.session_count(), 0);
+    // Both rejections were covered by a single resync, the next one is debounced
+    assert!(!state.lock().await.request_resync(Instant::now()));
+}
+
+#[tokio::test]
+async fn other_failures_keep_the_session() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[(REQUEST, Err(Status::unavailable("down")))]));
+    let context = Arc::new(Mutex::new(context));
+
+    assert!(relay(&context, &ranked("lava@a", 10), REQUEST.to_vec())
+        .await
+        .is_err());
+
+    assert_eq!(context.lock().await.session_count(), 1);
+    assert!(state.lock().await.request_resync(Instant::now()));
+}
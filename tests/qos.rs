@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::provider_stats::ProviderSummary;
+use lavap_rs::qos::{demote_on_breach, QosConfig};
+
+mod common;
+use common::{pairing_state, set_pairing};
+
+fn summary(requests: usize, error_rate: f64, p95_latency_ms: u64) -> ProviderSummary {
+    ProviderSummary {
+        address: "lava@a".to_string(),
+        requests,
+        errors: (requests as f64 * error_rate) as usize,
+        error_rate,
+        p50_latency_ms: 0,
+        p95_latency_ms,
+        dissents: 0,
+    }
+}
+
+#[test]
+fn each_threshold_is_a_breach_of_its_own() {
+    let config = QosConfig::default();
+    assert_eq!(config.breach(Some(&summary(20, 0.1, 100)), 0), None);
+    assert_eq!(config.breach(None, 20), None);
+    assert_eq!(config.breach(None, 21).as_deref(), Some("21 blocks behind"));
+    assert_eq!(
+        config.breach(Some(&summary(20, 0.5, 100)), 0).as_deref(),
+        Some("error rate 0.50")
+    );
+    assert_eq!(
+        config.breach(Some(&summary(20, 0.0, 3001)), 0).as_deref(),
+        Some("p95 latency 3001ms")
+    );
+    // Too few relays to judge error rate and latency, sync lag still counts
+    assert_eq!(config.breach(Some(&summary(5, 1.0, 10_000)), 0), None);
+    assert!(config.breach(Some(&summary(5, 1.0, 10_000)), 50).is_some());
+}
+
+#[tokio::test]
+async fn a_breaching_top_provider_is_moved_last() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b", "lava@c"]).await;
+    let mut config = Config::default();
+    config.qos.enabled = true;
+    let context = common::context(&state, config);
+    {
+        let mut stats = context.stats.lock().await;
+        for _ in 0..10 {
+            stats.record("lava@a", false, Duration::from_millis(10));
+            stats.record("lava@b", false, Duration::from_millis(10));
+        }
+    }
+    let context = Arc::new(Mutex::new(context));
+    let order = || async {
+        state
+            .lock()
+            .await
+            .ranked_providers
+            .iter()
+            .map(|p| p.provider.address.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // Only the top provider is judged
+    demote_on_breach(&context, &state, "lava@b").await;
+    assert_eq!(order().await, vec!["lava@a", "lava@b", "lava@c"]);
+    demote_on_breach(&context, &state, "lava@a").await;
+    assert_eq!(order().await, vec!["lava@b", "lava@c", "lava@a"]);
+}
+
+#[tokio::test]
+async fn nothing_moves_unless_enabled() {
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let context = common::context(&state, Config::default());
+    {
+        let mut stats = context.stats.lock().await;
+        for _ in 0..10 {
+            stats.record("lava@a", false, Duration::from_millis(10));
+        }
+    }
+    let context = Arc::new(Mutex::new(context));
+
+    demote_on_breach(&context, &state, "lava@a").await;
+    assert_eq!(
+        state.lock().await.ranked_providers[0].provider.address,
+        "lava@a"
+    );
+}
@@ -0,0 +1,100 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use lavap_rs::config::Config;
+use lavap_rs::pairing::get_ranked_providers;
+use lavap_rs::quorum::{relay_quorum, QuorumConfig};
+
+mod common;
+use common::{pairing_state, replay, set_pairing, Canned};
+
+const REQUEST: &[u8] =
+    br#"{"jsonrpc":"2.0","id":7,"method":"eth_getBalance","params":["0xabc","latest"]}"#;
+
+async fn quorum(providers: &[&str], replies: &[Canned<'_>], size: usize) -> (Value, u64) {
+    let state = pairing_state();
+    set_pairing(&state, 10, providers).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(replies));
+    let stats = context.stats.clone();
+    let context = Arc::new(Mutex::new(context));
+
+    let providers = get_ranked_providers(state).await;
+    let request: Value = serde_json::from_slice(REQUEST).unwrap();
+    let data = relay_quorum(&context, &providers, &request, REQUEST, size)
+        .await
+        .unwrap();
+    let dissents = stats
+        .lock()
+        .await
+        .summaries()
+        .iter()
+        .map(|s| s.dissents)
+        .sum();
+    (serde_json::from_slice(&data).unwrap(), dissents)
+}
+
+#[test]
+fn only_listed_methods_with_more_than_one_provider_need_a_quorum() {
+    let config = QuorumConfig {
+        methods: HashMap::from([
+            ("eth_getBalance".to_string(), 3),
+            ("eth_call".to_string(), 1),
+        ]),
+    };
+    assert_eq!(
+        config.quorum_size(&json!({"method": "eth_getBalance"})),
+        Some(3)
+    );
+    assert_eq!(config.quorum_size(&json!({"method": "eth_call"})), None);
+    assert_eq!(config.quorum_size(&json!({"method": "eth_chainId"})), None);
+}
+
+#[tokio::test]
+async fn the_majority_answer_wins_and_the_dissenter_is_counted() {
+    let (response, dissents) = quorum(
+        &["lava@a", "lava@b", "lava@c"],
+        &[
+            (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":7,"result":"0x1"}"#)),
+            (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":7,"result":"0x2"}"#)),
+            // Same answer in another shape still agrees
+            (REQUEST, Ok(br#"{"id":7, "jsonrpc":"2.0", "result":"0x1"}"#)),
+        ],
+        3,
+    )
+    .await;
+    assert_eq!(response["result"], "0x1");
+    assert_eq!(dissents, 1);
+}
+
+#[tokio::test]
+async fn without_a_strict_majority_the_client_gets_an_error() {
+    let (response, dissents) = quorum(
+        &["lava@a", "lava@b", "lava@c", "lava@d"],
+        &[
+            (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":7,"result":"0x1"}"#)),
+            (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":7,"result":"0x1"}"#)),
+            (REQUEST, Ok(br#"{"jsonrpc":"2.0","id":7,"result":"0x2"}"#)),
+            (REQUEST, Err(tonic::Status::unavailable("down"))),
+        ],
+        4,
+    )
+    .await;
+    assert_eq!(response["id"], 7);
+    assert_eq!(
+        response["error"]["message"],
+        "Providers did not reach a quorum"
+    );
+    assert_eq!(dissents, 0);
+}
+
+#[tokio::test]
+async fn too_few_providers_refuse_rather_than_lower_the_bar() {
+    let (response, _) = quorum(&["lava@a", "lava@b"], &[], 3).await;
+    assert_eq!(
+        response["error"]["message"],
+        "Not enough providers for a quorum"
+    );
+}
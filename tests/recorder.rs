@@ -0,0 +1,93 @@
+use serde_json::json;
+use std::time::Duration;
+use tonic::{Code, Status};
+
+use lavap_rs::proto::{RelayReply, RelayRequest, RelaySession};
+use lavap_rs::recorder::Recorder;
+use lavap_rs::relay::jsonrpc_relay_data;
+use lavap_rs::relay_session::generate_content_hash;
+
+mod common;
+use common::{replay, temp_path};
+
+fn relay_request(payload: &[u8], session_id: u64) -> RelayRequest {
+    let relay_data = jsonrpc_relay_data(payload.to_vec(), vec![]);
+    RelayRequest {
+        relay_session: Some(RelaySession {
+            content_hash: generate_content_hash(&relay_data),
+            session_id,
+            ..Default::default()
+        }),
+        relay_data: Some(relay_data),
+    }
+}
+
+#[tokio::test]
+async fn a_recording_replays_what_was_recorded() {
+    let path = temp_path("recording");
+    let path = path.to_str().unwrap();
+    let pairing = json!({"pairing": {"epoch": 10}});
+    let request = relay_request(b"{\"method\":\"eth_blockNumber\"}", 1);
+
+    let recorder = Recorder::record(path).unwrap();
+    recorder.record_pairing(&pairing).await;
+    recorder
+        .record_probe("lava@a", "a.example:443", Duration::from_millis(25), true)
+        .await;
+    let reply = RelayReply {
+        data: b"0x10".to_vec(),
+        ..Default::default()
+    };
+    recorder.record_relay("lava@a", &request, &Ok(reply)).await;
+    let failure = Err(Status::unavailable("connection reset"));
+    recorder.record_relay("lava@a", &request, &failure).await;
+    drop(recorder);
+
+    let replay = Recorder::replay(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert!(replay.is_replaying());
+    assert_eq!(replay.next_pairing().await.unwrap(), pairing);
+    assert!(replay.next_pairing().await.is_err());
+    assert_eq!(
+        replay.next_probe("a.example:443").await,
+        (Duration::from_millis(25), true)
+    );
+    // Endpoints never probed, or probed fewer times than asked, count as down
+    assert_eq!(
+        replay.next_probe("a.example:443").await,
+        (Duration::ZERO, false)
+    );
+
+    // Sessions differ between runs, replies are matched on the content of the relay
+    let rerun = relay_request(b"{\"method\":\"eth_blockNumber\"}", 2);
+    assert_eq!(replay.next_relay(&rerun).await.unwrap().data, b"0x10");
+    let status = replay.next_relay(&rerun).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(status.message(), "connection reset");
+    let status = replay.next_relay(&rerun).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn live_and_offline_recorders_replay_nothing() {
+    let request = relay_request(b"{}", 1);
+    let live = Recorder::live();
+    assert!(!live.is_replaying());
+    assert!(live.next_pairing().await.is_err());
+
+    let offline = Recorder::offline();
+    assert!(offline.is_replaying());
+    assert_eq!(
+        offline.next_relay(&request).await.unwrap_err().code(),
+        Code::NotFound
+    );
+}
+
+#[tokio::test]
+async fn relays_of_other_payloads_get_no_reply() {
+    let recorder = replay(&[(b"{\"id\":1}", Ok(b"one"))]);
+    let other = relay_request(b"{\"id\":2}", 1);
+    assert!(recorder.next_relay(&other).await.is_err());
+    let same = relay_request(b"{\"id\":1}", 1);
+    assert_eq!(recorder.next_relay(&same).await.unwrap().data, b"one");
+}
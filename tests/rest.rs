@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use lavap_rs::config::Config;
+use lavap_rs::relay::rest_relay_data;
+
+mod common;
+use common::{pairing_state, replay_relays, serve, set_pairing};
+
+const BALANCES: &str = "/cosmos/bank/v1beta1/balances/lava@x?pagination.limit=1";
+
+async fn consumer(providers: &[&str], replies: &[(&str, &str, &[u8])]) -> SocketAddr {
+    let mut config = Config::default();
+    config.rest.chains = vec!["COS5".to_string()];
+    let chain_state = pairing_state();
+    set_pairing(&chain_state, 10, providers).await;
+    let mut context = common::context(&pairing_state(), config);
+    context.chains.insert("COS5".to_string(), chain_state);
+    let relays = replies
+        .iter()
+        .map(|(method, api_url, reply)| {
+            let body = match *method {
+                "POST" => br#"{"tx_bytes":"AA=="}"#.to_vec(),
+                _ => vec![],
+            };
+            (
+                rest_relay_data(method, api_url.to_string(), body),
+                Ok(*reply),
+            )
+        })
+        .collect::<Vec<_>>();
+    context.recorder = Arc::new(replay_relays(&relays));
+    serve(context).await
+}
+
+#[tokio::test]
+async fn paths_and_queries_reach_the_provider_as_they_came() {
+    let addr = consumer(
+        &["lava@a"],
+        &[
+            ("GET", BALANCES, br#"{"balances":[]}"#),
+            ("POST", "/cosmos/tx/v1beta1/txs", br#"{"tx_response":{}}"#),
+        ],
+    )
+    .await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("http://{}/COS5{}", addr, BALANCES))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.text().await.unwrap(), r#"{"balances":[]}"#);
+
+    let response = client
+        .post(format!("http://{}/COS5/cosmos/tx/v1beta1/txs", addr))
+        .body(r#"{"tx_bytes":"AA=="}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), r#"{"tx_response":{}}"#);
+}
+
+#[tokio::test]
+async fn grpc_gateway_errors_keep_their_status() {
+    let not_found = br#"{"code":5,"message":"account not found","details":[]}"#;
+    let addr = consumer(&["lava@a"], &[("GET", BALANCES, not_found)]).await;
+
+    let response = reqwest::get(format!("http://{}/COS5{}", addr, BALANCES))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.bytes().await.unwrap(), &not_found[..]);
+}
+
+#[tokio::test]
+async fn unsupported_methods_and_missing_providers_get_rest_errors() {
+    let addr = consumer(&[], &[]).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(format!("http://{}/COS5{}", addr, BALANCES))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], 12);
+
+    let response = client
+        .get(format!("http://{}/COS5{}", addr, BALANCES))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "No providers available");
+}
@@ -0,0 +1,56 @@
+use serde_json::json;
+
+use lavap_rs::shadow::{is_read_only, outcome, ShadowConfig};
+
+#[test]
+fn only_reads_are_mirrored() {
+    assert!(is_read_only(
+        br#"{"jsonrpc":"2.0","id":1,"method":"eth_call","params":[]}"#
+    ));
+    assert!(!is_read_only(
+        br#"{"jsonrpc":"2.0","id":1,"method":"eth_sendRawTransaction","params":["0x00"]}"#
+    ));
+    // One write makes the whole batch a write
+    assert!(is_read_only(
+        br#"[{"id":1,"method":"eth_chainId"},{"id":2,"method":"eth_blockNumber"}]"#
+    ));
+    assert!(!is_read_only(
+        br#"[{"id":1,"method":"eth_chainId"},{"id":2,"method":"eth_sendRawTransaction"}]"#
+    ));
+    assert!(!is_read_only(b"[]"));
+    assert!(!is_read_only(br#"{"id":1}"#));
+    assert!(!is_read_only(b"not json"));
+}
+
+#[test]
+fn outcomes_ignore_ids_order_and_formatting() {
+    assert_eq!(
+        outcome(br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#),
+        outcome(br#"{ "id": 9, "result": "0x1", "jsonrpc": "2.0" }"#)
+    );
+    assert_eq!(
+        outcome(br#"{"id":1,"error":{"code":3,"message":"reverted"}}"#),
+        Some(json!({"code": 3, "message": "reverted"}))
+    );
+    assert_ne!(
+        outcome(br#"{"id":1,"result":"0x1"}"#),
+        outcome(br#"{"id":1,"result":"0x2"}"#)
+    );
+    // Batch replies may come back in any order, they're compared by id
+    assert_eq!(
+        outcome(br#"[{"id":1,"result":"0xa"},{"id":2,"result":"0xb"}]"#),
+        outcome(br#"[{"id":2,"result":"0xb"},{"id":1,"result":"0xa"}]"#)
+    );
+    assert_eq!(outcome(br#"{"id":1}"#), Some(json!(null)));
+    assert_eq!(outcome(b"<html>"), None);
+}
+
+#[test]
+fn nothing_is_mirrored_by_default() {
+    assert!(!ShadowConfig::default().should_mirror());
+    let always = ShadowConfig {
+        fraction: 1.0,
+        provider: None,
+    };
+    assert!(always.should_mirror());
+}
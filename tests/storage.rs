@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use lavap_rs::config::Config;
+use lavap_rs::session_context::{ConsumerSessionContext, RELAY_CU};
+use lavap_rs::storage::{MemoryStorage, SqliteStorage, Storage};
+
+mod common;
+
+fn round_trip(storage: &dyn Storage) {
+    assert_eq!(storage.get("sessions", "a").unwrap(), None);
+
+    storage.put("sessions", "b", b"2").unwrap();
+    storage.put("sessions", "a", b"1").unwrap();
+    storage.put("sessions", "c", b"3").unwrap();
+    storage.put("reputation", "a", b"other").unwrap();
+    assert_eq!(storage.get("sessions", "a").unwrap(), Some(b"1".to_vec()));
+
+    // Writing a key again replaces its value
+    storage.put("sessions", "a", b"4").unwrap();
+    assert_eq!(storage.get("sessions", "a").unwrap(), Some(b"4".to_vec()));
+
+    storage.delete("sessions", "b").unwrap();
+    assert_eq!(storage.get("sessions", "b").unwrap(), None);
+    // Deleting what isn't there is fine
+    storage.delete("sessions", "b").unwrap();
+    storage.delete("missing", "b").unwrap();
+
+    // Scans stay within their namespace and come back in key order
+    assert_eq!(
+        storage.scan("sessions").unwrap(),
+        vec![
+            ("a".to_string(), b"4".to_vec()),
+            ("c".to_string(), b"3".to_vec())
+        ]
+    );
+    assert_eq!(
+        storage.scan("reputation").unwrap(),
+        vec![("a".to_string(), b"other".to_vec())]
+    );
+    assert!(storage.scan("missing").unwrap().is_empty());
+}
+
+#[test]
+fn memory_storage_round_trips_entries() {
+    round_trip(&MemoryStorage::default());
+}
+
+#[test]
+fn sqlite_storage_round_trips_entries() {
+    round_trip(&SqliteStorage::open(":memory:").unwrap());
+}
+
+fn context(key: u8, storage: &Arc<dyn Storage>) -> ConsumerSessionContext {
+    common::context_with(key, &common::pairing_state(), storage, Config::default())
+}
+
+#[test]
+fn sessions_survive_a_restart() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+
+    let mut before = context(7, &storage);
+    let session_id = before
+        .get_or_create_session("ETH1", 10, "lava@a")
+        .session_id;
+    before.update_session("ETH1", 10, "lava@a", RELAY_CU);
+    before.update_session("ETH1", 10, "lava@a", RELAY_CU);
+    drop(before);
+
+    let mut after = context(7, &storage);
+    assert_eq!(after.session_count(), 1);
+    assert_eq!(after.epoch_cu_used("ETH1", 10), 2 * RELAY_CU);
+    let restored = after.get_or_create_session("ETH1", 10, "lava@a");
+    assert_eq!(restored.session_id, session_id);
+    assert_eq!(restored.cu_sum, 2 * RELAY_CU);
+    assert_eq!(restored.relay_num, 3);
+}
+
+#[test]
+fn consumers_sharing_storage_keep_their_own_sessions() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+
+    let mut first = context(7, &storage);
+    first.get_or_create_session("ETH1", 10, "lava@a");
+    first.update_session("ETH1", 10, "lava@a", RELAY_CU);
+
+    let other = context(8, &storage);
+    assert_eq!(other.session_count(), 0);
+    assert_eq!(context(7, &storage).session_count(), 1);
+}
+
+#[test]
+fn the_error_log_survives_a_restart_and_stays_bounded() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+
+    let mut before = context(7, &storage);
+    for i in 0..205 {
+        before
+            .diagnostics
+            .record_error("ETH1", "lava@a", &i.to_string());
+    }
+    drop(before);
+
+    let mut after = context(7, &storage);
+    let errors = after.diagnostics.errors();
+    assert_eq!(errors.len(), 200);
+    assert_eq!(errors[0].message, "5");
+    assert_eq!(errors[199].message, "204");
+
+    // New errors go after the restored ones, and push the oldest out of storage too
+    after.diagnostics.record_error("ETH1", "lava@a", "205");
+    drop(after);
+    let errors = context(7, &storage).diagnostics.errors();
+    assert_eq!(errors.len(), 200);
+    assert_eq!(errors[0].message, "6");
+    assert_eq!(errors[199].message, "205");
+}
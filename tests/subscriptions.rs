@@ -0,0 +1,72 @@
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use lavap_rs::config::Config;
+
+mod common;
+use common::{pairing_state, replay, serve, set_pairing};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(addr: SocketAddr) -> Socket {
+    connect_async(format!("ws://{}/ws", addr)).await.unwrap().0
+}
+
+async fn call(socket: &mut Socket, request: &str) -> Value {
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn plain_calls_fail_over_like_http() {
+    let request = r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#;
+    let reply = br#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#;
+    let state = pairing_state();
+    set_pairing(&state, 10, &["lava@a", "lava@b"]).await;
+    let mut context = common::context(&state, Config::default());
+    context.recorder = Arc::new(replay(&[
+        (request.as_bytes(), Err(tonic::Status::unavailable("down"))),
+        (request.as_bytes(), Ok(reply)),
+    ]));
+    let mut socket = connect(serve(context).await).await;
+
+    let response = call(&mut socket, request).await;
+    assert_eq!(
+        response,
+        json!({"jsonrpc": "2.0", "id": 1, "result": "0x10"})
+    );
+}
+
+#[tokio::test]
+async fn unsubscribing_what_never_started_returns_false() {
+    let state = pairing_state();
+    let context = common::context(&state, Config::default());
+    let mut socket = connect(serve(context).await).await;
+
+    let subscribe = r#"{"jsonrpc":"2.0","id":1,"method":"eth_subscribe","params":["newHeads"]}"#;
+    let response = call(&mut socket, subscribe).await;
+    assert_eq!(
+        response["error"]["message"],
+        "No provider accepted the subscription"
+    );
+
+    let unsubscribe = r#"{"jsonrpc":"2.0","id":2,"method":"eth_unsubscribe","params":["0x01"]}"#;
+    let response = call(&mut socket, unsubscribe).await;
+    assert_eq!(
+        response,
+        json!({"jsonrpc": "2.0", "id": 2, "result": false})
+    );
+}
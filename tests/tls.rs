@@ -0,0 +1,92 @@
+use lavap_rs::dns::DnsConfig;
+use lavap_rs::endpoint::Connector;
+use lavap_rs::tls::{PinnedTls, TlsConfig, TlsPin};
+
+mod common;
+
+const FINGERPRINT: &str = "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:\
+                           AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+
+fn pin(target: &str) -> TlsPin {
+    TlsPin {
+        target: target.to_string(),
+        fingerprints: vec![FINGERPRINT.to_string()],
+        ca_file: None,
+    }
+}
+
+fn pinned(pins: Vec<TlsPin>) -> PinnedTls {
+    PinnedTls::new(&TlsConfig { pins }).unwrap()
+}
+
+#[test]
+fn pins_match_providers_and_normalized_endpoints() {
+    let tls = pinned(vec![pin("lava@a"), pin("provider.example")]);
+
+    assert!(tls.connector_for("lava@a", "other.example:2221").is_some());
+    // A bare host pins its default https endpoint, whatever form the provider publishes
+    assert!(tls
+        .connector_for("lava@b", "provider.example:443")
+        .is_some());
+    assert!(tls
+        .connector_for("lava@b", "https://provider.example")
+        .is_some());
+    assert!(tls
+        .connector_for("lava@b", "http://provider.example:443")
+        .is_some());
+    assert!(tls
+        .connector_for("lava@b", "provider.example:2221")
+        .is_none());
+    assert!(tls.connector_for("lava@b", "other.example:443").is_none());
+
+    let everyone = pinned(vec![pin("*")]);
+    assert!(everyone.connector_for("lava@c", "any.example:1").is_some());
+    assert!(pinned(vec![])
+        .connector_for("lava@a", "any.example:1")
+        .is_none());
+}
+
+#[test]
+fn bad_pins_fail_at_startup() {
+    let config = |pin: TlsPin| TlsConfig { pins: vec![pin] };
+
+    let short = TlsPin {
+        fingerprints: vec!["abcd".to_string()],
+        ..pin("*")
+    };
+    assert!(PinnedTls::new(&config(short)).is_err());
+
+    let missing = TlsPin {
+        ca_file: Some("/nonexistent/ca.pem".to_string()),
+        ..pin("*")
+    };
+    assert!(PinnedTls::new(&config(missing)).is_err());
+
+    let path = common::temp_path("ca");
+    std::fs::write(&path, "not a certificate").unwrap();
+    let empty = TlsPin {
+        ca_file: Some(path.to_str().unwrap().to_string()),
+        ..pin("*")
+    };
+    let result = PinnedTls::new(&config(empty));
+    std::fs::remove_file(path).unwrap();
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn pinned_providers_are_never_dialed_in_plaintext() {
+    let config = TlsConfig {
+        pins: vec![pin("lava@a")],
+    };
+    let connector = Connector::new(DnsConfig::default(), &config).unwrap();
+
+    let error = connector
+        .connect("lava@a", "http://127.0.0.1:2221")
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("refusing plaintext"),
+        "{}",
+        error
+    );
+}